@@ -1,3 +1,21 @@
+use std::path::Path;
+
 fn main() {
     lalrpop::process_root().unwrap();
+    export_token_table();
+}
+
+/// Copies the table of regular expressions of the generated lexer to `tokens.rs`, so tests can
+/// check that `cst::tokenize` splits tokens like the grammar does.
+fn export_token_table() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let parser = std::fs::read_to_string(Path::new(&out_dir).join("rscad.rs")).unwrap();
+    let declaration = "let __strs: &[(&str, bool)] = ";
+    let start = parser
+        .find(declaration)
+        .expect("token table not found in the generated parser")
+        + declaration.len();
+    let table = &parser[start..];
+    let table = &table[..table.find("];").unwrap() + 1];
+    std::fs::write(Path::new(&out_dir).join("tokens.rs"), table).unwrap();
 }
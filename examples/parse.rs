@@ -6,6 +6,11 @@ fn main() {
 
     // println!("{}", &input[66..]);
 
-    let res = rscad::parse(&input).unwrap();
-    println!("{:#?}", res);
+    match rscad::parse(&input) {
+        Ok(res) => println!("{:#?}", res),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
 }
@@ -1,12 +1,86 @@
 //! Raw AST for the OpenSCAD syntax.
 //!
 //! This does not know about any standard functions (like `sphere`, `import`, or even `if`  and `for`).
+//!
+//! Every [`Statement`] and [`Expr`] carries the [`Span`] of source it was parsed from.
+//! Equality between nodes only compares their structure, not their location.
+//!
+//! Nodes borrow names and strings from the source when possible. Use `into_owned` to get a
+//! `'static` copy of a tree that no longer depends on the source, for example to cache it or
+//! send it to another thread.
+//!
+//! # Serialization
+//!
+//! With the `serde` feature, every node implements `Serialize` and `Deserialize`. The JSON
+//! shape is stable:
+//!
+//! * Structs are objects with their field names: `{"kind": ..., "span": {"start": 0, "end": 7}}`.
+//! * Enum variants are named in `snake_case`. Variants without data are plain strings
+//!   (`"no_op"`, `"add"`), others are objects with a single key: `{"variable": "a"}`,
+//!   `{"variable_declaration": ["a", <expr>]}`, `{"range": {"start": ..., "end": ..., "increment": null}}`.
+//! * Missing optional values (like an unnamed parameter) are `null`.
+//!
+//! For example, `a = -1;` becomes:
+//!
+//! ```json
+//! {
+//!   "kind": {
+//!     "variable_declaration": [
+//!       "a",
+//!       {
+//!         "kind": {"negative": {"kind": {"number": 1.0}, "span": {"start": 5, "end": 6}}},
+//!         "span": {"start": 4, "end": 6}
+//!       }
+//!     ]
+//!   },
+//!   "span": {"start": 0, "end": 7}
+//! }
+//! ```
+
+use std::borrow::Cow;
 
-/// An item in a SCAD scene.
+pub use crate::span::Span;
+
+/// An item in a SCAD scene, with its location in the source.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Statement<'input> {
+    /// What this statement does.
+    pub kind: StatementKind<'input>,
+    /// Where this statement comes from.
+    pub span: Span,
+}
+
+impl<'input> Statement<'input> {
+    pub(crate) fn new(kind: StatementKind<'input>, start: usize, end: usize) -> Self {
+        let span = Span::new(start, end);
+        Statement { kind, span }
+    }
+}
+
+impl<'input> PartialEq for Statement<'input> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl<'input> From<StatementKind<'input>> for Statement<'input> {
+    fn from(kind: StatementKind<'input>) -> Self {
+        let span = Span::default();
+        Statement { kind, span }
+    }
+}
+
+/// The different kinds of statement.
 #[derive(Clone, Debug, PartialEq)]
-pub enum Statement<'input> {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum StatementKind<'input> {
     /// Variable declaration
-    VariableDeclaration(&'input str, Expr<'input>),
+    VariableDeclaration(Cow<'input, str>, Expr<'input>),
 
     /// A statement with a special modifier.
     Modifier(Modifier, Box<Statement<'input>>),
@@ -17,9 +91,12 @@ pub enum Statement<'input> {
     /// Nothing.
     NoOp,
 
+    /// Placeholder for a statement that could not be parsed.
+    Error,
+
     /// Module definition
     ModuleDefinition {
-        name: &'input str,
+        name: Cow<'input, str>,
         args: Vec<ParameterDefinition<'input>>,
         body: Box<Statement<'input>>,
     },
@@ -27,13 +104,17 @@ pub enum Statement<'input> {
     Let(Vec<Let<'input>>, Box<Statement<'input>>),
 
     /// Function definition
-    FunctionDefinition(&'input str, Vec<ParameterDefinition<'input>>, Expr<'input>),
+    FunctionDefinition(
+        Cow<'input, str>,
+        Vec<ParameterDefinition<'input>>,
+        Expr<'input>,
+    ),
 
     /// Includes another file
-    Include(&'input str),
+    Include(Cow<'input, str>),
 
     /// Use another file
-    Use(&'input str),
+    Use(Cow<'input, str>),
 
     /// Function call (or module call)
     ModuleCall(ModuleCall<'input>),
@@ -46,8 +127,10 @@ pub enum Statement<'input> {
         body: Box<Statement<'input>>,
     },
 
-    /// A comment (can be ignored)
-    Comment(&'input str),
+    /// A comment, including its `//` or `/* */` markers (can be ignored)
+    ///
+    /// Only produced by `cst::parse_lossless`.
+    Comment(Cow<'input, str>),
 
     /// If-block
     If {
@@ -60,11 +143,11 @@ pub enum Statement<'input> {
     },
 }
 
-impl<'input> Statement<'input> {
+impl<'input> StatementKind<'input> {
     pub(crate) fn make_if(condition: Expr<'input>, if_true: Statement<'input>) -> Self {
         let if_true = Box::new(if_true);
-        let if_false = Box::new(Statement::NoOp);
-        Statement::If {
+        let if_false = Box::new(StatementKind::NoOp.into());
+        StatementKind::If {
             condition,
             if_true,
             if_false,
@@ -78,7 +161,7 @@ impl<'input> Statement<'input> {
     ) -> Self {
         let if_true = Box::new(if_true);
         let if_false = Box::new(if_false);
-        Statement::If {
+        StatementKind::If {
             condition,
             if_true,
             if_false,
@@ -88,9 +171,10 @@ impl<'input> Statement<'input> {
 
 /// Describes a function call: ex `sphere(1, center=true)`
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModuleCall<'input> {
     /// Name of the function being called
-    pub function: &'input str,
+    pub function: Cow<'input, str>,
     /// List of parameters given
     pub params: Vec<ParameterValue<'input>>,
     /// Children of the call, if any (used for `union`/`difference`/...)
@@ -98,6 +182,11 @@ pub struct ModuleCall<'input> {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Modifier {
     /// Do not render this element (*)
     Disable,
@@ -111,70 +200,114 @@ pub enum Modifier {
 
 /// A parameter given to a function, possibly named.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterValue<'input> {
     /// Optional name for this parameter
-    pub name: Option<&'input str>,
+    pub name: Option<Cow<'input, str>>,
     /// Value given to this parameter
     pub value: Expr<'input>,
 }
 
 /// An argument in a function declaration, possibly with default value.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterDefinition<'input> {
     /// Name of the parameter
-    pub name: &'input str,
+    pub name: Cow<'input, str>,
     /// Optional default value for this parameter
     pub default_value: Option<Expr<'input>>,
 }
 
+/// Describes a function call: ex `max(a, 3)`, `f(2)(3)` or `(function(x) x)(4)`.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionCall<'input> {
-    /// Name of the function being called
-    pub name: &'input str,
+    /// Function being called: usually just a name, but can be any expression
+    pub function: Box<Expr<'input>>,
     /// Parameters given to the function
     pub parameters: Vec<ParameterValue<'input>>,
 }
 
 /// A local variable definition: `let (a=42)`
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Let<'input> {
     pub vars: Vec<ParameterValue<'input>>,
 }
 
-/// An expression in the AST. Directly what lalrpop produces.
+/// An expression in the AST, with its location in the source.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Expr<'input> {
+    /// What this expression computes.
+    pub kind: ExprKind<'input>,
+    /// Where this expression comes from.
+    pub span: Span,
+}
+
+impl<'input> Expr<'input> {
+    pub(crate) fn new(kind: ExprKind<'input>, start: usize, end: usize) -> Self {
+        let span = Span::new(start, end);
+        Expr { kind, span }
+    }
+}
+
+impl<'input> PartialEq for Expr<'input> {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl<'input> From<ExprKind<'input>> for Expr<'input> {
+    fn from(kind: ExprKind<'input>) -> Self {
+        let span = Span::default();
+        Expr { kind, span }
+    }
+}
+
+/// The different kinds of expression. Directly what lalrpop produces.
 #[derive(Clone, Debug, PartialEq)]
-pub enum Expr<'input> {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ExprKind<'input> {
     /// Undefined expression.
     Undef,
     /// A boolean literal
     Boolean(bool),
     /// A number literal
-    Number(f32),
-    /// A text literal
-    Text(&'input str),
+    Number(f64),
+    /// A text literal, with escape sequences already replaced
+    Text(Cow<'input, str>),
     /// Negative another expression
     Negative(Box<Expr<'input>>),
     /// Boolean NOT (!)
     Not(Box<Expr<'input>>),
+    /// Bitwise NOT (~)
+    BitNot(Box<Expr<'input>>),
     /// A variable
-    Variable(&'input str),
+    Variable(Cow<'input, str>),
     /// A function call
     Function(FunctionCall<'input>),
+    /// An anonymous function: `function(x) x * x`
+    Lambda {
+        /// Arguments of the function
+        args: Vec<ParameterDefinition<'input>>,
+        /// Body of the function
+        body: Box<Expr<'input>>,
+    },
     /// Print something, the resolve the expression.
     Echo(Vec<ParameterValue<'input>>, Box<Expr<'input>>),
     /// Print something, the resolve the expression.
     Assert(Vec<ParameterValue<'input>>, Box<Expr<'input>>),
     /// Defines some local variables, then resolve the expression.
     Let(Vec<Let<'input>>, Box<Expr<'input>>),
-    /// A list comprehension: [let(n=5) for(i = [1:n]) i*i]
-    ListComprehension {
-        /// Variable definitions to run before the loop.
-        lets: Vec<Let<'input>>,
-        /// Variables to loop over
-        variables: Vec<ParameterValue<'input>>,
-        /// Body of the loop
-        body: Box<Expr<'input>>,
-    },
+    /// A list comprehension: `[let(n=5) for(i = [1:n]) i*i]`
+    ///
+    /// Any vector with at least one generator (`for`, `if`, `each`, ...) is a list comprehension.
+    ListComprehension(Vec<ListElement<'input>>),
     /// A vector: `[1, 2, 3*a]`
     Vector(Vec<Expr<'input>>),
     /// An operation: `a + 3`, `f(n) == 0`, ...
@@ -186,7 +319,7 @@ pub enum Expr<'input> {
     /// Access a field from an object: `foobar.x`
     FieldAccess {
         parent: Box<Expr<'input>>,
-        field: &'input str,
+        field: Cow<'input, str>,
     },
     /// Access an array value: `a[2 + 3]`
     ArrayAccess {
@@ -215,23 +348,43 @@ pub enum Expr<'input> {
     },
 }
 
-impl<'input> Expr<'input> {
+impl<'input> ExprKind<'input> {
+    pub(crate) fn vector(elements: Vec<ListElement<'input>>) -> Self {
+        if elements.iter().all(|e| matches!(e, ListElement::Expr(_))) {
+            ExprKind::Vector(
+                elements
+                    .into_iter()
+                    .map(|e| match e {
+                        ListElement::Expr(e) => e,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            )
+        } else {
+            ExprKind::ListComprehension(elements)
+        }
+    }
+
     pub(crate) fn array_access(array: Expr<'input>, index: Expr<'input>) -> Self {
         let array = Box::new(array);
         let index = Box::new(index);
-        Expr::ArrayAccess { array, index }
+        ExprKind::ArrayAccess { array, index }
     }
 
-    pub(crate) fn field_access(parent: Expr<'input>, field: &'input str) -> Self {
+    pub(crate) fn field_access(parent: Expr<'input>, field: Cow<'input, str>) -> Self {
         let parent = Box::new(parent);
-        Expr::FieldAccess { parent, field }
+        ExprKind::FieldAccess { parent, field }
     }
 
-    pub(crate) fn range(start: Self, increment: Option<Self>, end: Self) -> Self {
+    pub(crate) fn range(
+        start: Expr<'input>,
+        increment: Option<Expr<'input>>,
+        end: Expr<'input>,
+    ) -> Self {
         let start = Box::new(start);
         let increment = increment.map(Box::new);
         let end = Box::new(end);
-        Expr::Range {
+        ExprKind::Range {
             start,
             increment,
             end,
@@ -239,8 +392,82 @@ impl<'input> Expr<'input> {
     }
 }
 
+/// An element of a list comprehension.
+///
+/// Each element generates zero, one or more values in the final vector.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ListElement<'input> {
+    /// A single value
+    Expr(Expr<'input>),
+    /// Generates all values from a vector: `each v`
+    Each(Box<ListElement<'input>>),
+    /// Generates values for each iteration: `for (i = [0:3]) i * i`
+    For {
+        /// Variables looped over (nested loops if more than one)
+        variables: Vec<ParameterValue<'input>>,
+        /// Body of the loop
+        body: Box<ListElement<'input>>,
+    },
+    /// C-style loop: `for (i = 0; i < 10; i = i + 1) i`
+    ForC {
+        /// Variables initialized before the loop
+        init: Vec<ParameterValue<'input>>,
+        /// Loop goes on while this is true
+        condition: Expr<'input>,
+        /// Variables re-assigned after each iteration
+        update: Vec<ParameterValue<'input>>,
+        /// Body of the loop
+        body: Box<ListElement<'input>>,
+    },
+    /// Filters values: `if (i % 2) i else -i`
+    If {
+        /// Condition for this element
+        condition: Expr<'input>,
+        /// Element if the condition is true
+        if_true: Box<ListElement<'input>>,
+        /// Optional element if the condition is false
+        if_false: Option<Box<ListElement<'input>>>,
+    },
+    /// Defines local variables for the rest of the element: `let (n = 5) for (i = [1:n]) i`
+    Let {
+        /// Variables defined
+        vars: Vec<ParameterValue<'input>>,
+        /// Body where the variables are visible
+        body: Box<ListElement<'input>>,
+    },
+}
+
+impl<'input> ListElement<'input> {
+    pub(crate) fn lets(lets: Vec<Let<'input>>, body: Self) -> Self {
+        lets.into_iter()
+            .rev()
+            .fold(body, |body, l| ListElement::Let {
+                vars: l.vars,
+                body: Box::new(body),
+            })
+    }
+
+    pub(crate) fn make_if(condition: Expr<'input>, if_true: Self, if_false: Option<Self>) -> Self {
+        ListElement::If {
+            condition,
+            if_true: Box::new(if_true),
+            if_false: if_false.map(Box::new),
+        }
+    }
+}
+
 /// An operation between expressions
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Opcode {
     /// Multiplication
     Mul,
@@ -264,4 +491,278 @@ pub enum Opcode {
     Lt,
     /// Less than, or equal
     Lte,
+    /// Exponentiation (`^`)
+    Pow,
+    /// Bitwise left shift (`<<`)
+    ShiftLeft,
+    /// Bitwise right shift (`>>`)
+    ShiftRight,
+    /// Bitwise AND (`&`)
+    BitAnd,
+    /// Bitwise OR (`|`)
+    BitOr,
+}
+
+impl Opcode {
+    /// Returns the operator as written in the source: `+`, `<=`, ...
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Opcode::Mul => "*",
+            Opcode::Div => "/",
+            Opcode::Rem => "%",
+            Opcode::Add => "+",
+            Opcode::Sub => "-",
+            Opcode::Equal => "==",
+            Opcode::NotEqual => "!=",
+            Opcode::Gt => ">",
+            Opcode::Gte => ">=",
+            Opcode::Lt => "<",
+            Opcode::Lte => "<=",
+            Opcode::Pow => "^",
+            Opcode::ShiftLeft => "<<",
+            Opcode::ShiftRight => ">>",
+            Opcode::BitAnd => "&",
+            Opcode::BitOr => "|",
+        }
+    }
+}
+
+fn owned(text: Cow<str>) -> Cow<'static, str> {
+    Cow::Owned(text.into_owned())
+}
+
+fn owned_all<T, U>(items: Vec<T>, f: impl Fn(T) -> U) -> Vec<U> {
+    items.into_iter().map(f).collect()
+}
+
+impl<'input> Statement<'input> {
+    /// Returns a copy of this statement that does not borrow from the source.
+    pub fn into_owned(self) -> Statement<'static> {
+        Statement {
+            kind: self.kind.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+impl<'input> StatementKind<'input> {
+    /// Returns a copy of this statement that does not borrow from the source.
+    pub fn into_owned(self) -> StatementKind<'static> {
+        match self {
+            StatementKind::VariableDeclaration(name, value) => {
+                StatementKind::VariableDeclaration(owned(name), value.into_owned())
+            }
+            StatementKind::Modifier(modifier, child) => {
+                StatementKind::Modifier(modifier, Box::new(child.into_owned()))
+            }
+            StatementKind::StatementList(statements) => {
+                StatementKind::StatementList(owned_all(statements, Statement::into_owned))
+            }
+            StatementKind::NoOp => StatementKind::NoOp,
+            StatementKind::Error => StatementKind::Error,
+            StatementKind::ModuleDefinition { name, args, body } => {
+                StatementKind::ModuleDefinition {
+                    name: owned(name),
+                    args: owned_all(args, ParameterDefinition::into_owned),
+                    body: Box::new(body.into_owned()),
+                }
+            }
+            StatementKind::Let(lets, body) => StatementKind::Let(
+                owned_all(lets, Let::into_owned),
+                Box::new(body.into_owned()),
+            ),
+            StatementKind::FunctionDefinition(name, args, body) => {
+                StatementKind::FunctionDefinition(
+                    owned(name),
+                    owned_all(args, ParameterDefinition::into_owned),
+                    body.into_owned(),
+                )
+            }
+            StatementKind::Include(path) => StatementKind::Include(owned(path)),
+            StatementKind::Use(path) => StatementKind::Use(owned(path)),
+            StatementKind::ModuleCall(call) => StatementKind::ModuleCall(call.into_owned()),
+            StatementKind::For { variables, body } => StatementKind::For {
+                variables: owned_all(variables, ParameterValue::into_owned),
+                body: Box::new(body.into_owned()),
+            },
+            StatementKind::Comment(text) => StatementKind::Comment(owned(text)),
+            StatementKind::If {
+                condition,
+                if_true,
+                if_false,
+            } => StatementKind::If {
+                condition: condition.into_owned(),
+                if_true: Box::new(if_true.into_owned()),
+                if_false: Box::new(if_false.into_owned()),
+            },
+        }
+    }
+}
+
+impl<'input> ModuleCall<'input> {
+    /// Returns a copy of this call that does not borrow from the source.
+    pub fn into_owned(self) -> ModuleCall<'static> {
+        ModuleCall {
+            function: owned(self.function),
+            params: owned_all(self.params, ParameterValue::into_owned),
+            child: Box::new(self.child.into_owned()),
+        }
+    }
+}
+
+impl<'input> ParameterValue<'input> {
+    /// Returns a copy of this parameter that does not borrow from the source.
+    pub fn into_owned(self) -> ParameterValue<'static> {
+        ParameterValue {
+            name: self.name.map(owned),
+            value: self.value.into_owned(),
+        }
+    }
+}
+
+impl<'input> ParameterDefinition<'input> {
+    /// Returns a copy of this parameter that does not borrow from the source.
+    pub fn into_owned(self) -> ParameterDefinition<'static> {
+        ParameterDefinition {
+            name: owned(self.name),
+            default_value: self.default_value.map(Expr::into_owned),
+        }
+    }
+}
+
+impl<'input> FunctionCall<'input> {
+    /// Returns a copy of this call that does not borrow from the source.
+    pub fn into_owned(self) -> FunctionCall<'static> {
+        FunctionCall {
+            function: Box::new(self.function.into_owned()),
+            parameters: owned_all(self.parameters, ParameterValue::into_owned),
+        }
+    }
+}
+
+impl<'input> Let<'input> {
+    /// Returns a copy of these variables that does not borrow from the source.
+    pub fn into_owned(self) -> Let<'static> {
+        Let {
+            vars: owned_all(self.vars, ParameterValue::into_owned),
+        }
+    }
+}
+
+impl<'input> Expr<'input> {
+    /// Returns a copy of this expression that does not borrow from the source.
+    pub fn into_owned(self) -> Expr<'static> {
+        Expr {
+            kind: self.kind.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+fn owned_box(expr: Expr) -> Box<Expr<'static>> {
+    Box::new(expr.into_owned())
+}
+
+impl<'input> ExprKind<'input> {
+    /// Returns a copy of this expression that does not borrow from the source.
+    pub fn into_owned(self) -> ExprKind<'static> {
+        match self {
+            ExprKind::Undef => ExprKind::Undef,
+            ExprKind::Boolean(b) => ExprKind::Boolean(b),
+            ExprKind::Number(n) => ExprKind::Number(n),
+            ExprKind::Text(text) => ExprKind::Text(owned(text)),
+            ExprKind::Negative(e) => ExprKind::Negative(owned_box(*e)),
+            ExprKind::Not(e) => ExprKind::Not(owned_box(*e)),
+            ExprKind::BitNot(e) => ExprKind::BitNot(owned_box(*e)),
+            ExprKind::Variable(name) => ExprKind::Variable(owned(name)),
+            ExprKind::Function(call) => ExprKind::Function(call.into_owned()),
+            ExprKind::Lambda { args, body } => ExprKind::Lambda {
+                args: owned_all(args, ParameterDefinition::into_owned),
+                body: owned_box(*body),
+            },
+            ExprKind::Echo(params, body) => ExprKind::Echo(
+                owned_all(params, ParameterValue::into_owned),
+                owned_box(*body),
+            ),
+            ExprKind::Assert(params, body) => ExprKind::Assert(
+                owned_all(params, ParameterValue::into_owned),
+                owned_box(*body),
+            ),
+            ExprKind::Let(lets, body) => {
+                ExprKind::Let(owned_all(lets, Let::into_owned), owned_box(*body))
+            }
+            ExprKind::ListComprehension(elements) => {
+                ExprKind::ListComprehension(owned_all(elements, ListElement::into_owned))
+            }
+            ExprKind::Vector(values) => ExprKind::Vector(owned_all(values, Expr::into_owned)),
+            ExprKind::Op(a, op, b) => ExprKind::Op(owned_box(*a), op, owned_box(*b)),
+            ExprKind::Or(a, b) => ExprKind::Or(owned_box(*a), owned_box(*b)),
+            ExprKind::And(a, b) => ExprKind::And(owned_box(*a), owned_box(*b)),
+            ExprKind::FieldAccess { parent, field } => ExprKind::FieldAccess {
+                parent: owned_box(*parent),
+                field: owned(field),
+            },
+            ExprKind::ArrayAccess { array, index } => ExprKind::ArrayAccess {
+                array: owned_box(*array),
+                index: owned_box(*index),
+            },
+            ExprKind::Ternary {
+                condition,
+                if_true,
+                if_false,
+            } => ExprKind::Ternary {
+                condition: owned_box(*condition),
+                if_true: owned_box(*if_true),
+                if_false: owned_box(*if_false),
+            },
+            ExprKind::Range {
+                start,
+                end,
+                increment,
+            } => ExprKind::Range {
+                start: owned_box(*start),
+                end: owned_box(*end),
+                increment: increment.map(|increment| owned_box(*increment)),
+            },
+        }
+    }
+}
+
+impl<'input> ListElement<'input> {
+    /// Returns a copy of this element that does not borrow from the source.
+    pub fn into_owned(self) -> ListElement<'static> {
+        let owned_element = |element: Box<ListElement>| Box::new(element.into_owned());
+        match self {
+            ListElement::Expr(expr) => ListElement::Expr(expr.into_owned()),
+            ListElement::Each(body) => ListElement::Each(owned_element(body)),
+            ListElement::For { variables, body } => ListElement::For {
+                variables: owned_all(variables, ParameterValue::into_owned),
+                body: owned_element(body),
+            },
+            ListElement::ForC {
+                init,
+                condition,
+                update,
+                body,
+            } => ListElement::ForC {
+                init: owned_all(init, ParameterValue::into_owned),
+                condition: condition.into_owned(),
+                update: owned_all(update, ParameterValue::into_owned),
+                body: owned_element(body),
+            },
+            ListElement::If {
+                condition,
+                if_true,
+                if_false,
+            } => ListElement::If {
+                condition: condition.into_owned(),
+                if_true: owned_element(if_true),
+                if_false: if_false.map(owned_element),
+            },
+            ListElement::Let { vars, body } => ListElement::Let {
+                vars: owned_all(vars, ParameterValue::into_owned),
+                body: owned_element(body),
+            },
+        }
+    }
 }
@@ -0,0 +1,180 @@
+//! CSG tree, the result of interpreting a document.
+//!
+//! Variables, loops and user modules are gone: only primitives, transformations and boolean
+//! operations are left, with every parameter computed.
+//!
+//! [`to_csg`] writes a tree in the `.csg` format of OpenSCAD, to compare with its output.
+
+mod dump;
+
+pub use self::dump::to_csg;
+
+/// A 4x4 transformation matrix, in rows, applied to column vectors.
+pub type Matrix = [[f64; 4]; 4];
+
+/// The identity matrix.
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Values of `$fn`, `$fa` and `$fs` where a curved shape is instantiated, which decide how many
+/// segments approximate it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fragments {
+    /// `$fn`: number of segments of a full circle, or 0 to use `angle` and `size`.
+    pub number: f64,
+    /// `$fa`: maximum angle of a segment, in degrees.
+    pub angle: f64,
+    /// `$fs`: minimum length of a segment.
+    pub size: f64,
+}
+
+impl Default for Fragments {
+    /// The default values in OpenSCAD: `$fn = 0`, `$fa = 12` and `$fs = 2`.
+    fn default() -> Self {
+        Fragments {
+            number: 0.0,
+            angle: 12.0,
+            size: 2.0,
+        }
+    }
+}
+
+impl Fragments {
+    /// Number of segments of a circle of radius `r`, computed like OpenSCAD.
+    ///
+    /// ```
+    /// use rscad::csg::Fragments;
+    ///
+    /// assert_eq!(Fragments::default().count(10.0), 30);
+    /// assert_eq!(Fragments::default().count(1.0), 5);
+    /// let fragments = Fragments { number: 8.0, ..Fragments::default() };
+    /// assert_eq!(fragments.count(10.0), 8);
+    /// ```
+    pub fn count(&self, r: f64) -> u32 {
+        // Smaller circles are a single point.
+        const GRID_FINE: f64 = 0.000_000_953_674_316_406_25;
+        if r < GRID_FINE || r.is_nan() {
+            3
+        } else if self.number > 0.0 {
+            self.number.max(3.0) as u32
+        } else {
+            let by_angle = 360.0 / self.angle;
+            let by_size = r * 2.0 * std::f64::consts::PI / self.size;
+            by_angle.min(by_size).max(5.0).ceil() as u32
+        }
+    }
+}
+
+/// A node of the CSG tree.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum CsgNode {
+    /// Children kept together, like the result of a user module or a loop.
+    Group(Vec<CsgNode>),
+    Union(Vec<CsgNode>),
+    /// The first child, minus all the others.
+    Difference(Vec<CsgNode>),
+    Intersection(Vec<CsgNode>),
+    /// Convex hull of the children.
+    Hull(Vec<CsgNode>),
+    /// Minkowski sum of the children.
+    Minkowski {
+        convexity: u32,
+        children: Vec<CsgNode>,
+    },
+    /// Transforms the children: `translate`, `rotate`, `scale`, `mirror` and `multmatrix`.
+    Multmatrix {
+        matrix: Matrix,
+        children: Vec<CsgNode>,
+    },
+    /// Colors the children, with red, green, blue and alpha between 0 and 1.
+    Color {
+        rgba: [f64; 4],
+        children: Vec<CsgNode>,
+    },
+    /// Extrudes the 2D children along z.
+    LinearExtrude {
+        height: f64,
+        center: bool,
+        convexity: u32,
+        /// Rotation of the top relative to the bottom, in degrees, if given.
+        twist: Option<f64>,
+        /// Number of intermediate layers, if given.
+        slices: Option<u32>,
+        /// Scale of the top relative to the bottom, in x and y.
+        scale: [f64; 2],
+        fragments: Fragments,
+        children: Vec<CsgNode>,
+    },
+    /// Rotates the 2D children around z, seen as the y axis.
+    RotateExtrude {
+        /// Angle swept, in degrees.
+        angle: f64,
+        convexity: u32,
+        fragments: Fragments,
+        children: Vec<CsgNode>,
+    },
+    /// Shape read from a file: STL, OFF, DXF, SVG...
+    Import {
+        /// Path of the file, as given.
+        file: String,
+        /// Layer to read, for DXF files.
+        layer: String,
+        /// Origin of the shape, for DXF files.
+        origin: [f64; 2],
+        scale: f64,
+        convexity: u32,
+        fragments: Fragments,
+    },
+    Cube {
+        size: [f64; 3],
+        center: bool,
+    },
+    Sphere {
+        r: f64,
+        fragments: Fragments,
+    },
+    Cylinder {
+        h: f64,
+        /// Radius at the bottom.
+        r1: f64,
+        /// Radius at the top.
+        r2: f64,
+        center: bool,
+        fragments: Fragments,
+    },
+    Polyhedron {
+        points: Vec<[f64; 3]>,
+        /// Indices of the points of each face.
+        faces: Vec<Vec<usize>>,
+        convexity: u32,
+    },
+    Square {
+        size: [f64; 2],
+        center: bool,
+    },
+    Circle {
+        r: f64,
+        fragments: Fragments,
+    },
+    Polygon {
+        points: Vec<[f64; 2]>,
+        /// Indices of the points of the outline and holes, or `None` to use all the points in
+        /// order.
+        paths: Option<Vec<Vec<usize>>>,
+        convexity: u32,
+    },
+    /// A node marked with `#`: shown in the preview, highlighted.
+    Highlight(Box<CsgNode>),
+    /// A node marked with `%`: shown transparent in the preview, but not part of the result.
+    Background(Box<CsgNode>),
+}
@@ -0,0 +1,222 @@
+//! Writes CSG trees in the `.csg` format of OpenSCAD.
+
+use super::{CsgNode, Fragments};
+use crate::interpreter::format_number;
+
+/// Writes a CSG tree like `openscad -o out.csg` does: one node per line, indented with tabs.
+///
+/// ```
+/// use rscad::csg::{to_csg, CsgNode};
+///
+/// let cube = CsgNode::Cube { size: [1.0, 2.0, 3.0], center: false };
+/// let root = CsgNode::Group(vec![CsgNode::Highlight(Box::new(cube))]);
+/// assert_eq!(
+///     to_csg(&root),
+///     "group() {\n\t#cube(size = [1, 2, 3], center = false);\n}\n"
+/// );
+/// ```
+pub fn to_csg(root: &CsgNode) -> String {
+    let mut out = String::new();
+    node(root, 0, &mut out);
+    out
+}
+
+fn node(node: &CsgNode, indent: usize, out: &mut String) {
+    out.extend(std::iter::repeat_n('\t', indent));
+    let mut node = node;
+    while let CsgNode::Highlight(child) | CsgNode::Background(child) = node {
+        out.push(match node {
+            CsgNode::Highlight(_) => '#',
+            _ => '%',
+        });
+        node = child;
+    }
+    let (header, children) = header(node);
+    out.push_str(&header);
+    match children {
+        None | Some([]) => out.push_str(";\n"),
+        Some(children) => {
+            out.push_str(" {\n");
+            for child in children {
+                self::node(child, indent + 1, out);
+            }
+            out.extend(std::iter::repeat_n('\t', indent));
+            out.push_str("}\n");
+        }
+    }
+}
+
+/// Returns the name and arguments of a node, and its children if it can have some.
+fn header(node: &CsgNode) -> (String, Option<&[CsgNode]>) {
+    match node {
+        CsgNode::Group(children) => ("group()".to_string(), Some(children)),
+        CsgNode::Union(children) => ("union()".to_string(), Some(children)),
+        CsgNode::Difference(children) => ("difference()".to_string(), Some(children)),
+        CsgNode::Intersection(children) => ("intersection()".to_string(), Some(children)),
+        CsgNode::Hull(children) => ("hull()".to_string(), Some(children)),
+        CsgNode::Minkowski {
+            convexity,
+            children,
+        } => (
+            format!("minkowski(convexity = {})", convexity),
+            Some(children),
+        ),
+        CsgNode::Multmatrix { matrix, children } => {
+            let rows: Vec<_> = matrix.iter().map(|row| vector(row)).collect();
+            let header = format!("multmatrix([{}])", rows.join(", "));
+            (header, Some(children))
+        }
+        CsgNode::Color { rgba, children } => (format!("color({})", vector(rgba)), Some(children)),
+        CsgNode::LinearExtrude {
+            height,
+            center,
+            convexity,
+            twist,
+            slices,
+            scale,
+            fragments,
+            children,
+        } => {
+            let mut header = format!(
+                "linear_extrude(height = {}, center = {}, convexity = {}",
+                format_number(*height),
+                center,
+                convexity
+            );
+            if let Some(twist) = twist {
+                header += &format!(", twist = {}", format_number(*twist));
+            }
+            if let Some(slices) = slices {
+                header += &format!(", slices = {}", slices);
+            }
+            header += &format!(", scale = {}, {})", vector(scale), special(fragments));
+            (header, Some(children))
+        }
+        CsgNode::RotateExtrude {
+            angle,
+            convexity,
+            fragments,
+            children,
+        } => {
+            let header = format!(
+                "rotate_extrude(angle = {}, convexity = {}, {})",
+                format_number(*angle),
+                convexity,
+                special(fragments)
+            );
+            (header, Some(children))
+        }
+        CsgNode::Import {
+            file,
+            layer,
+            origin,
+            scale,
+            convexity,
+            fragments,
+        } => {
+            // OpenSCAD also writes the time the file was modified, which is left as 0.
+            let header = format!(
+                "import(file = {}, layer = {}, origin = {}, scale = {}, convexity = {}, {}, \
+                 timestamp = 0)",
+                string(file),
+                string(layer),
+                vector(origin),
+                format_number(*scale),
+                convexity,
+                special(fragments)
+            );
+            (header, None)
+        }
+        CsgNode::Cube { size, center } => {
+            let header = format!("cube(size = {}, center = {})", vector(size), center);
+            (header, None)
+        }
+        CsgNode::Sphere { r, fragments } => {
+            let header = format!("sphere({}, r = {})", special(fragments), format_number(*r));
+            (header, None)
+        }
+        CsgNode::Cylinder {
+            h,
+            r1,
+            r2,
+            center,
+            fragments,
+        } => {
+            let header = format!(
+                "cylinder({}, h = {}, r1 = {}, r2 = {}, center = {})",
+                special(fragments),
+                format_number(*h),
+                format_number(*r1),
+                format_number(*r2),
+                center
+            );
+            (header, None)
+        }
+        CsgNode::Polyhedron {
+            points,
+            faces,
+            convexity,
+        } => {
+            let header = format!(
+                "polyhedron(points = {}, faces = {}, convexity = {})",
+                list(points, |p| vector(p)),
+                list(faces, |f| indices(f)),
+                convexity
+            );
+            (header, None)
+        }
+        CsgNode::Square { size, center } => {
+            let header = format!("square(size = {}, center = {})", vector(size), center);
+            (header, None)
+        }
+        CsgNode::Circle { r, fragments } => {
+            let header = format!("circle({}, r = {})", special(fragments), format_number(*r));
+            (header, None)
+        }
+        CsgNode::Polygon {
+            points,
+            paths,
+            convexity,
+        } => {
+            let paths = match paths {
+                Some(paths) => list(paths, |p| indices(p)),
+                None => "undef".to_string(),
+            };
+            let header = format!(
+                "polygon(points = {}, paths = {}, convexity = {})",
+                list(points, |p| vector(p)),
+                paths,
+                convexity
+            );
+            (header, None)
+        }
+        CsgNode::Highlight(node) | CsgNode::Background(node) => header(node),
+    }
+}
+
+fn vector(values: &[f64]) -> String {
+    list(values, |x| format_number(*x))
+}
+
+fn indices(values: &[usize]) -> String {
+    list(values, usize::to_string)
+}
+
+fn list<T, F: Fn(&T) -> String>(values: &[T], f: F) -> String {
+    let values: Vec<_> = values.iter().map(f).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The special variables of a curved shape: `$fn = 0, $fa = 12, $fs = 2`.
+fn special(fragments: &Fragments) -> String {
+    format!(
+        "$fn = {}, $fa = {}, $fs = {}",
+        format_number(fragments.number),
+        format_number(fragments.angle),
+        format_number(fragments.size)
+    )
+}
@@ -0,0 +1,460 @@
+//! Lossless view of an OpenSCAD document.
+//!
+//! The regular parser drops comments and whitespace. A [`SyntaxTree`] keeps them:
+//!
+//! * Every byte of the source is covered by exactly one [`Token`], including whitespace and
+//!   comments (the "trivia").
+//! * Comments between statements are inserted in the AST as `StatementKind::Comment`. The
+//!   others are listed in [`SyntaxTree::unattached_comments`].
+//! * The original spelling of any node (like `1.50` or `0x1F`) is available from its span.
+//!
+//! Tools can then rewrite parts of a file with [`SyntaxTree::edit`], leaving everything else
+//! exactly as the author wrote it.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::ast::{Statement, StatementKind};
+use crate::span::Span;
+use crate::Error;
+
+/// The category of a token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// Spaces, tabs and line breaks.
+    Whitespace,
+    /// A `// ...` comment, without the line break.
+    LineComment,
+    /// A `/* ... */` comment.
+    BlockComment,
+    /// A number literal.
+    Number,
+    /// A string literal, including the quotes.
+    String,
+    /// An identifier.
+    Identifier,
+    /// A reserved word, like `module` or `for`.
+    Keyword,
+    /// An operator or delimiter, like `+`, `<=` or `{`.
+    Punctuation,
+    /// An `include <...>` directive.
+    Include,
+    /// A `use <...>` directive.
+    Use,
+    /// Anything else.
+    Unknown,
+}
+
+impl TokenKind {
+    /// Returns `true` for whitespace and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment
+        )
+    }
+
+    /// Returns `true` for comments.
+    pub fn is_comment(self) -> bool {
+        matches!(self, TokenKind::LineComment | TokenKind::BlockComment)
+    }
+}
+
+/// A piece of source text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Token<'input> {
+    /// What kind of token this is.
+    pub kind: TokenKind,
+    /// The exact source text.
+    pub text: &'input str,
+    /// Where this token is.
+    pub span: Span,
+}
+
+const KEYWORDS: &[&str] = &[
+    "module", "function", "if", "else", "for", "let", "each", "echo", "assert", "true", "false",
+    "undef",
+];
+
+const PUNCTUATION: &[&str] = &[
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "^", "<", ">", "=",
+    "!", "~", "&", "|", "?", ":", ";", ",", ".", "(", ")", "[", "]", "{", "}", "#",
+];
+
+/// Splits the source into tokens, trivia included.
+///
+/// Concatenating the text of all tokens gives back the source.
+pub fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < source.len() {
+        let (kind, len) = next_token(&source[start..]);
+        let end = start + len;
+        tokens.push(Token {
+            kind,
+            text: &source[start..end],
+            span: Span::new(start, end),
+        });
+        start = end;
+    }
+    tokens
+}
+
+/// Finds the kind and length of the token at the start of `rest`.
+fn next_token(rest: &str) -> (TokenKind, usize) {
+    let first = rest.chars().next().unwrap();
+
+    if first.is_whitespace() {
+        let len = rest
+            .find(|c: char| !c.is_whitespace())
+            .unwrap_or(rest.len());
+        return (TokenKind::Whitespace, len);
+    }
+
+    if rest.starts_with("//") {
+        let len = rest.find(&['\n', '\r'][..]).unwrap_or(rest.len());
+        return (TokenKind::LineComment, len);
+    }
+
+    // Like in the grammar, an unterminated comment is not a comment.
+    if let Some(len) = rest
+        .strip_prefix("/*")
+        .and_then(|comment| comment.find("*/"))
+    {
+        return (TokenKind::BlockComment, len + 4);
+    }
+
+    // Like in the grammar, an unterminated string is not a string.
+    if let Some(len) = rest.strip_prefix('"').and_then(string_length) {
+        return (TokenKind::String, len);
+    }
+
+    for &(keyword, kind) in [("include", TokenKind::Include), ("use", TokenKind::Use)].iter() {
+        if let Some(len) = directive_length(rest, keyword) {
+            return (kind, len);
+        }
+    }
+
+    // Identifiers and numbers can both start with a digit: keep the longest.
+    let number = number_length(rest);
+    let identifier = identifier_length(rest);
+    if number > 0 && number >= identifier {
+        return (TokenKind::Number, number);
+    }
+    if identifier > 0 {
+        let kind = if KEYWORDS.contains(&&rest[..identifier]) {
+            TokenKind::Keyword
+        } else {
+            TokenKind::Identifier
+        };
+        return (kind, identifier);
+    }
+
+    if let Some(punctuation) = PUNCTUATION.iter().find(|p| rest.starts_with(*p)) {
+        return (TokenKind::Punctuation, punctuation.len());
+    }
+
+    (TokenKind::Unknown, first.len_utf8())
+}
+
+/// Length of a string literal after its opening quote, or `None` if it is not terminated. Like in
+/// the grammar, strings can span several lines.
+fn string_length(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some(i + 2),
+            '\\' => {
+                chars.next();
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Length of an `include <...>` or `use <...>` directive, if there is one.
+fn directive_length(rest: &str, keyword: &str) -> Option<usize> {
+    let after = rest.strip_prefix(keyword)?;
+    let path = after.trim_start();
+    let path = path.strip_prefix('<')?;
+    let end = path.find(&['<', '>', '\n'][..])?;
+    if !path[end..].starts_with('>') {
+        return None;
+    }
+    Some(rest.len() - path.len() + end + 1)
+}
+
+fn digits_length(s: &str, radix: u32) -> usize {
+    s.find(|c: char| !c.is_digit(radix)).unwrap_or(s.len())
+}
+
+/// Length of a number literal, or 0.
+fn number_length(rest: &str) -> usize {
+    let bytes = rest.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'0' && (bytes[1] == b'x' || bytes[1] == b'X') {
+        let hex = digits_length(&rest[2..], 16);
+        if hex > 0 {
+            return 2 + hex;
+        }
+    }
+
+    let mut len = digits_length(rest, 10);
+    if rest[len..].starts_with('.') {
+        let decimals = digits_length(&rest[len + 1..], 10);
+        if len == 0 && decimals == 0 {
+            return 0;
+        }
+        len += 1 + decimals;
+    }
+    if len == 0 {
+        return 0;
+    }
+
+    // Optional exponent.
+    let exponent = &rest[len..];
+    if exponent.starts_with(&['e', 'E'][..]) {
+        let sign = if exponent[1..].starts_with(&['+', '-'][..]) {
+            1
+        } else {
+            0
+        };
+        let digits = digits_length(&exponent[1 + sign..], 10);
+        if digits > 0 {
+            len += 1 + sign + digits;
+        }
+    }
+
+    len
+}
+
+/// Length of an identifier (or keyword), or 0.
+///
+/// Like in the grammar, identifiers start with ASCII letters, digits, `_` or `$`, and go on with
+/// any letters, digits or `_`.
+fn identifier_length(rest: &str) -> usize {
+    let start = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(rest.len());
+    if start == 0 {
+        return 0;
+    }
+    start
+        + rest[start..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len() - start)
+}
+
+/// Error returned by [`SyntaxTree::edit`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// Two edits overlap, with their spans.
+    Overlapping(Span, Span),
+    /// The span of an edit is not in the source, is reversed, or does not start and end between
+    /// characters.
+    InvalidSpan(Span),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::Overlapping(first, second) => write!(
+                f,
+                "overlapping edits at {}..{} and {}..{}",
+                first.start, first.end, second.start, second.end
+            ),
+            EditError::InvalidSpan(span) => {
+                write!(f, "invalid edit span {}..{}", span.start, span.end)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// A parsed document that remembers everything about its source.
+#[derive(Clone, Debug)]
+pub struct SyntaxTree<'input> {
+    /// The original source.
+    pub source: &'input str,
+    /// All tokens in the source, trivia included.
+    pub tokens: Vec<Token<'input>>,
+    /// The parsed statements, with comments between statements included.
+    pub statements: Vec<Statement<'input>>,
+    /// Comments that could not be inserted in `statements`, because they are not between
+    /// statements: inside an expression or a parameter list, or between the parts of a
+    /// statement, like before an `else`.
+    pub unattached_comments: Vec<Token<'input>>,
+}
+
+/// Parses a document without losing any information.
+pub fn parse_lossless(source: &str) -> Result<SyntaxTree<'_>, Error> {
+    let tokens = tokenize(source);
+    let mut statements = crate::parse(source)?;
+
+    let comments: Vec<Token> = tokens
+        .iter()
+        .copied()
+        .filter(|t| t.kind.is_comment())
+        .collect();
+    let mut unattached_comments = Vec::new();
+    insert_comments(&mut statements, &comments, &mut unattached_comments);
+    unattached_comments.sort_by_key(|c| c.span.start);
+
+    Ok(SyntaxTree {
+        source,
+        tokens,
+        statements,
+        unattached_comments,
+    })
+}
+
+/// Returns the comments lying inside the given span.
+fn comments_in<'input>(comments: &[Token<'input>], span: Span) -> Vec<Token<'input>> {
+    comments
+        .iter()
+        .copied()
+        .filter(|c| span.start <= c.span.start && c.span.end <= span.end)
+        .collect()
+}
+
+/// Adds the comments lying between the given statements, and recurse into their blocks.
+///
+/// `comments` are all the comments inside the parent of `statements`. Those that cannot be
+/// inserted are added to `unattached`.
+fn insert_comments<'input>(
+    statements: &mut Vec<Statement<'input>>,
+    comments: &[Token<'input>],
+    unattached: &mut Vec<Token<'input>>,
+) {
+    for statement in statements.iter_mut() {
+        let inner = comments_in(comments, statement.span);
+        if !inner.is_empty() {
+            insert_comments_in_children(statement, &inner, unattached);
+        }
+    }
+
+    let outside: Vec<Token> = comments
+        .iter()
+        .copied()
+        .filter(|c| {
+            !statements
+                .iter()
+                .any(|s| s.span.start <= c.span.start && c.span.end <= s.span.end)
+        })
+        .collect();
+
+    for comment in outside {
+        let position = statements
+            .iter()
+            .position(|s| s.span.start >= comment.span.end)
+            .unwrap_or(statements.len());
+        let kind = StatementKind::Comment(Cow::Borrowed(comment.text));
+        let comment = Statement {
+            kind,
+            span: comment.span,
+        };
+        statements.insert(position, comment);
+    }
+}
+
+/// Adds the comments inside a statement to the blocks it contains.
+///
+/// Comments outside of the child statements, like in the parameters of a module call, are
+/// added to `unattached`.
+fn insert_comments_in_children<'input>(
+    statement: &mut Statement<'input>,
+    comments: &[Token<'input>],
+    unattached: &mut Vec<Token<'input>>,
+) {
+    let children: Vec<&mut Statement<'input>> = match &mut statement.kind {
+        StatementKind::StatementList(statements) => {
+            return insert_comments(statements, comments, unattached)
+        }
+        StatementKind::Modifier(_, child)
+        | StatementKind::ModuleDefinition { body: child, .. }
+        | StatementKind::Let(_, child)
+        | StatementKind::For { body: child, .. } => vec![child],
+        StatementKind::ModuleCall(call) => vec![&mut call.child],
+        StatementKind::If {
+            if_true, if_false, ..
+        } => vec![if_true, if_false],
+        _ => vec![],
+    };
+
+    unattached.extend(comments.iter().copied().filter(|c| {
+        !children
+            .iter()
+            .any(|s| s.span.start <= c.span.start && c.span.end <= s.span.end)
+    }));
+    for child in children {
+        let inner = comments_in(comments, child.span);
+        if !inner.is_empty() {
+            insert_comments_in_children(child, &inner, unattached);
+        }
+    }
+}
+
+impl<'input> SyntaxTree<'input> {
+    /// Returns the original text for the given span.
+    pub fn text(&self, span: Span) -> &'input str {
+        span.text(self.source)
+    }
+
+    /// Returns the trivia right before the given span.
+    ///
+    /// This includes comments describing the node on the lines above it.
+    pub fn leading_trivia(&self, span: Span) -> &[Token<'input>] {
+        let end = self.tokens.partition_point(|t| t.span.end <= span.start);
+        let start = self.tokens[..end]
+            .iter()
+            .rposition(|t| !t.kind.is_trivia())
+            .map_or(0, |i| i + 1);
+        &self.tokens[start..end]
+    }
+
+    /// Returns the trivia right after the given span, up to the end of the line.
+    ///
+    /// This includes a comment on the same line as the end of the node.
+    pub fn trailing_trivia(&self, span: Span) -> &[Token<'input>] {
+        let start = self.tokens.partition_point(|t| t.span.start < span.end);
+        let mut end = start;
+        for token in &self.tokens[start..] {
+            if !token.kind.is_trivia() {
+                break;
+            }
+            end += 1;
+            if token.kind == TokenKind::Whitespace && token.text.contains('\n') {
+                break;
+            }
+        }
+        &self.tokens[start..end]
+    }
+
+    /// Returns the source with the given spans replaced.
+    ///
+    /// Everything outside of the edited spans is kept exactly as-is. Edits must not overlap, and
+    /// their spans must be valid in the source.
+    pub fn edit<S: AsRef<str>>(&self, edits: &[(Span, S)]) -> Result<String, EditError> {
+        if let Some((span, _)) = edits.iter().find(|(span, _)| {
+            span.start > span.end || self.source.get(span.start..span.end).is_none()
+        }) {
+            return Err(EditError::InvalidSpan(*span));
+        }
+        let mut edits: Vec<_> = edits.iter().collect();
+        edits.sort_by_key(|(span, _)| span.start);
+
+        let mut result = String::with_capacity(self.source.len());
+        let mut previous = Span::new(0, 0);
+        for (span, replacement) in edits {
+            if span.start < previous.end {
+                return Err(EditError::Overlapping(previous, *span));
+            }
+            result.push_str(&self.source[previous.end..span.start]);
+            result.push_str(replacement.as_ref());
+            previous = *span;
+        }
+        result.push_str(&self.source[previous.end..]);
+        Ok(result)
+    }
+}
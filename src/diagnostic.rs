@@ -0,0 +1,344 @@
+//! Warnings and errors found in a valid document, for example while resolving names.
+//!
+//! Each step collects its [`Diagnostic`]s in a [`Diagnostics`] list, returned alongside its
+//! result. They can be printed for a terminal with [`Diagnostics::render`], or exported with
+//! [`Diagnostics::to_json`] for other tools.
+
+use std::fmt::{self, Write};
+
+use crate::span::{LineIndex, Span};
+
+/// How bad a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Severity {
+    /// The document cannot be evaluated as written.
+    Error,
+    /// The document can be evaluated, but probably not as intended.
+    Warning,
+}
+
+impl Severity {
+    /// Returns the name of this severity: `error` or `warning`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Identifies the kind of diagnostic, independently of its message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Code {
+    /// A variable is used, but never defined.
+    UnknownVariable,
+    /// A function is called, but never defined.
+    UnknownFunction,
+    /// A field other than `x`, `y` or `z` is accessed.
+    UnknownField,
+    /// A variable is assigned more than once in the same scope.
+    Reassignment,
+    /// A value is given where a variable name is expected: `let (1) ...`
+    MissingName,
+    /// The update of a C-style loop assigns a variable that is not a loop variable.
+    InvalidLoopUpdate,
+    /// A named argument is not a parameter of the module or function called.
+    UnknownArgument,
+    /// The same named argument is given more than once.
+    DuplicateArgument,
+    /// An operator is applied to values it is not defined for: `1 + "a"`
+    UndefinedOperation,
+    /// A variable is used before it is assigned.
+    UnassignedVariable,
+    /// A value that is not a function is called.
+    NotAFunction,
+    /// The condition of an `assert` is false.
+    AssertionFailed,
+    /// Functions call each other too deeply, or a loop runs for too long.
+    RecursionLimit,
+    /// The bounds of a range are not numbers, or are in the wrong order.
+    InvalidRange,
+    /// A loop goes over a range with too many values.
+    RangeTooLarge,
+    /// A module is instantiated, but never defined.
+    UnknownModule,
+    /// An argument of a builtin module has the wrong type or value.
+    InvalidArgument,
+}
+
+impl Code {
+    /// Returns the name of this code, like `unknown-variable`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Code::UnknownVariable => "unknown-variable",
+            Code::UnknownFunction => "unknown-function",
+            Code::UnknownField => "unknown-field",
+            Code::Reassignment => "reassignment",
+            Code::MissingName => "missing-name",
+            Code::InvalidLoopUpdate => "invalid-loop-update",
+            Code::UnknownArgument => "unknown-argument",
+            Code::DuplicateArgument => "duplicate-argument",
+            Code::UndefinedOperation => "undefined-operation",
+            Code::UnassignedVariable => "unassigned-variable",
+            Code::NotAFunction => "not-a-function",
+            Code::AssertionFailed => "assertion-failed",
+            Code::RecursionLimit => "recursion-limit",
+            Code::InvalidRange => "invalid-range",
+            Code::RangeTooLarge => "range-too-large",
+            Code::UnknownModule => "unknown-module",
+            Code::InvalidArgument => "invalid-argument",
+        }
+    }
+}
+
+/// Additional information about a diagnostic, like where something was first defined.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note {
+    /// What this note says.
+    pub message: String,
+    /// What this note is about, if anything in particular.
+    pub span: Option<Span>,
+}
+
+/// A problem found in the document.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    /// How bad it is.
+    pub severity: Severity,
+    /// What kind of problem it is.
+    pub code: Code,
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// Where the problem is.
+    pub span: Span,
+    /// Related information.
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    /// Creates a new warning.
+    pub fn warning<M: Into<String>>(code: Code, message: M, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            code,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Creates a new error.
+    pub fn error<M: Into<String>>(code: Code, message: M, span: Span) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            ..Diagnostic::warning(code, message, span)
+        }
+    }
+
+    /// Adds a note to this diagnostic.
+    pub fn with_note<M: Into<String>>(mut self, message: M, span: Option<Span>) -> Self {
+        let message = message.into();
+        self.notes.push(Note { message, span });
+        self
+    }
+
+    /// Prints this diagnostic with the line of `source` it points to, underlined.
+    pub fn render(&self, source: &str) -> String {
+        let index = LineIndex::new(source);
+        let start = index.position(self.span.start);
+        let line_number = start.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+        let line = index.line(start.line);
+
+        // Underline the span, but never past the end of the line.
+        let offset = start.column - 1;
+        let line_length = line.chars().count();
+        let width = source
+            .get(self.span.start..self.span.end)
+            .map_or(1, |text| text.chars().count())
+            .min(line_length.saturating_sub(offset))
+            .max(1);
+
+        let mut output = String::new();
+        let _ = writeln!(output, "{}", self);
+        let _ = writeln!(output, "{}--> {}:{}", gutter, start.line, start.column);
+        let _ = writeln!(output, "{} |", gutter);
+        let _ = writeln!(output, "{} | {}", line_number, line);
+        let _ = write!(
+            output,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(offset),
+            "^".repeat(width)
+        );
+        for note in &self.notes {
+            let _ = write!(output, "\n{} = note: {}", gutter, note.message);
+            if let Some(span) = note.span {
+                let position = index.position(span.start);
+                let _ = write!(output, " ({}:{})", position.line, position.column);
+            }
+        }
+        output
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.severity.as_str(),
+            self.code.as_str(),
+            self.message
+        )
+    }
+}
+
+/// A list of diagnostics, in the order they were found.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostics {
+    list: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Diagnostics::default()
+    }
+
+    /// Adds a diagnostic at the end of the list.
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.list.push(diagnostic);
+    }
+
+    /// Adds all the diagnostics of `other` at the end of the list.
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.list.extend(other.list);
+    }
+
+    /// Returns the number of diagnostics.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Returns `true` if there is no diagnostic at all.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Returns `true` if there is at least one error.
+    pub fn has_errors(&self) -> bool {
+        self.list.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    /// Iterates over the diagnostics, in order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.list.iter()
+    }
+
+    /// Prints every diagnostic for a terminal, with the source they point to.
+    pub fn render(&self, source: &str) -> String {
+        let rendered: Vec<_> = self.iter().map(|d| d.render(source)).collect();
+        rendered.join("\n\n")
+    }
+
+    /// Exports the diagnostics as a JSON array, with line/column positions in `source`.
+    ///
+    /// Each diagnostic is an object like:
+    ///
+    /// ```json
+    /// {
+    ///   "severity": "warning",
+    ///   "code": "unknown-variable",
+    ///   "message": "unknown variable `x`",
+    ///   "span": {"start": 4, "end": 5},
+    ///   "line": 1,
+    ///   "column": 5,
+    ///   "notes": [{"message": "...", "span": null}]
+    /// }
+    /// ```
+    ///
+    /// Lines and columns start at 1; columns count characters.
+    pub fn to_json(&self, source: &str) -> String {
+        let index = LineIndex::new(source);
+        let span = |span: Span| format!(r#"{{"start":{},"end":{}}}"#, span.start, span.end);
+
+        let diagnostics: Vec<_> = self
+            .iter()
+            .map(|d| {
+                let position = index.position(d.span.start);
+                let notes: Vec<_> = d
+                    .notes
+                    .iter()
+                    .map(|note| {
+                        format!(
+                            r#"{{"message":{},"span":{}}}"#,
+                            json_string(&note.message),
+                            note.span.map_or_else(|| "null".to_string(), span)
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"{{"severity":"{}","code":"{}","message":{},"span":{},"line":{},"column":{},"notes":[{}]}}"#,
+                    d.severity.as_str(),
+                    d.code.as_str(),
+                    json_string(&d.message),
+                    span(d.span),
+                    position.line,
+                    position.column,
+                    notes.join(",")
+                )
+            })
+            .collect();
+        format!("[{}]", diagnostics.join(","))
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Diagnostic;
+    type IntoIter = std::slice::Iter<'a, Diagnostic>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl From<Vec<Diagnostic>> for Diagnostics {
+    fn from(list: Vec<Diagnostic>) -> Self {
+        Diagnostics { list }
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(result, "\\u{:04x}", c as u32);
+            }
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -0,0 +1,427 @@
+//! Errors reported when reading OpenSCAD documents.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::span::{LineIndex, Position, Span};
+
+type Token<'input> = lalrpop_util::lexer::Token<'input>;
+type LalrpopError<'input> = lalrpop_util::ParseError<usize, Token<'input>, UserError>;
+type ErrorRecovery<'input> = lalrpop_util::ErrorRecovery<usize, Token<'input>, UserError>;
+
+/// Any error returned by this crate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The document is not valid OpenSCAD syntax.
+    ///
+    /// Contains every syntax error found, in order. Never empty.
+    Parse(Vec<ParseError>),
+    /// A file, or one of the files it includes or uses, could not be loaded.
+    Load(LoadError),
+    /// The document cannot be formatted without losing some of its comments.
+    ///
+    /// Contains every comment that cannot be kept, in order. Never empty.
+    Format(Vec<FormatError>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    error.fmt(f)?;
+                }
+                Ok(())
+            }
+            Error::Load(error) => error.fmt(f),
+            Error::Format(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "\n\n")?;
+                    }
+                    error.fmt(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<LoadError> for Error {
+    fn from(error: LoadError) -> Self {
+        Error::Load(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(vec![error])
+    }
+}
+
+impl From<Vec<ParseError>> for Error {
+    fn from(errors: Vec<ParseError>) -> Self {
+        Error::Parse(errors)
+    }
+}
+
+/// What went wrong while parsing.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    /// Found a token that cannot appear here.
+    UnexpectedToken(String),
+    /// Found a token after the end of the document.
+    ExtraToken(String),
+    /// Reached the end of the document too early.
+    UnexpectedEof,
+    /// A string literal is missing its closing quote.
+    UnterminatedString,
+    /// A `/* */` comment is missing its closing `*/`.
+    UnterminatedComment,
+    /// A number literal could not be read.
+    InvalidNumber(String),
+    /// An unknown or malformed escape sequence in a string literal.
+    InvalidEscape(String),
+    /// A character that cannot start any token.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedToken(token) => write!(f, "unexpected token `{}`", token),
+            ParseErrorKind::ExtraToken(token) => write!(f, "extra token `{}`", token),
+            ParseErrorKind::UnexpectedEof => write!(f, "unexpected end of file"),
+            ParseErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            ParseErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            ParseErrorKind::InvalidNumber(number) => {
+                write!(f, "invalid number literal `{}`", number)
+            }
+            ParseErrorKind::InvalidEscape(escape) => {
+                write!(f, "invalid escape sequence `{}`", escape)
+            }
+            ParseErrorKind::InvalidCharacter(c) => write!(f, "invalid character `{}`", c),
+        }
+    }
+}
+
+/// A syntax error in an OpenSCAD document.
+///
+/// The `Display` implementation prints the offending line with the error underlined.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    /// What went wrong.
+    pub kind: ParseErrorKind,
+    /// Where it went wrong.
+    pub span: Span,
+    /// Line and column of the start of the span.
+    pub position: Position,
+    /// Human-readable descriptions of the tokens that would have been accepted.
+    pub expected: Vec<String>,
+    /// The source line containing the start of the span.
+    line: String,
+}
+
+impl ParseError {
+    /// Creates a new error of the given kind, located in `source`.
+    pub fn new(kind: ParseErrorKind, span: Span, expected: Vec<String>, source: &str) -> Self {
+        let index = LineIndex::new(source);
+        let position = index.position(span.start);
+        let line = index.line(position.line).to_string();
+        ParseError {
+            kind,
+            span,
+            position,
+            expected,
+            line,
+        }
+    }
+
+    /// Returns the source line containing the error.
+    pub fn source_line(&self) -> &str {
+        &self.line
+    }
+
+    pub(crate) fn from_lalrpop(error: LalrpopError, source: &str) -> Self {
+        use lalrpop_util::ParseError as E;
+
+        let (kind, span, expected) = match error {
+            E::InvalidToken { location } => {
+                let rest = &source[location..];
+                let c = rest.chars().next().unwrap_or(' ');
+                if c == '"' {
+                    let end = rest.find('\n').unwrap_or(rest.len());
+                    let span = Span::new(location, location + end);
+                    (ParseErrorKind::UnterminatedString, span, vec![])
+                } else {
+                    let span = Span::new(location, location + c.len_utf8());
+                    (ParseErrorKind::InvalidCharacter(c), span, vec![])
+                }
+            }
+            E::UnrecognizedEOF { location, expected } => (
+                ParseErrorKind::UnexpectedEof,
+                Span::new(location, location),
+                expected,
+            ),
+            E::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => {
+                if source[start..].starts_with("/*") {
+                    let span = Span::new(start, source.len());
+                    (ParseErrorKind::UnterminatedComment, span, vec![])
+                } else {
+                    let kind = ParseErrorKind::UnexpectedToken(token.1.to_string());
+                    (kind, Span::new(start, end), expected)
+                }
+            }
+            E::ExtraToken {
+                token: (start, token, end),
+            } => (
+                ParseErrorKind::ExtraToken(token.1.to_string()),
+                Span::new(start, end),
+                vec![],
+            ),
+            E::User {
+                error: UserError { kind, span },
+            } => (kind, span, vec![]),
+        };
+
+        let mut human_expected: Vec<String> = Vec::new();
+        for token in expected.iter().map(|t| describe_token(t)) {
+            if !human_expected.contains(&token) {
+                human_expected.push(token);
+            }
+        }
+
+        ParseError::new(kind, span, human_expected, source)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "error: {}", self.kind)?;
+        snippet(f, self.span, self.position, &self.line)?;
+        match self.expected.as_slice() {
+            [] => Ok(()),
+            [token] => write!(f, " expected {}", token),
+            tokens => write!(f, " expected one of {}", tokens.join(", ")),
+        }
+    }
+}
+
+/// Prints the line at `position`, with the span underlined, without the final line break.
+fn snippet(f: &mut fmt::Formatter, span: Span, position: Position, line: &str) -> fmt::Result {
+    let line_number = position.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    // Underline the span, but never past the end of the line.
+    let offset = position.column - 1;
+    let line_length = line.chars().count();
+    let width = span.len().min(line_length.saturating_sub(offset)).max(1);
+
+    writeln!(f, "{}--> {}:{}", gutter, position.line, position.column)?;
+    writeln!(f, "{} |", gutter)?;
+    writeln!(f, "{} | {}", line_number, line)?;
+    write!(
+        f,
+        "{} | {}{}",
+        gutter,
+        " ".repeat(offset),
+        "^".repeat(width)
+    )
+}
+
+impl std::error::Error for ParseError {}
+
+/// A comment that the formatter cannot keep.
+///
+/// Only comments between statements are kept: the formatter refuses comments inside
+/// expressions, parameter lists, or between the parts of a statement, like before an `else`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatError {
+    /// Where the comment is.
+    pub span: Span,
+    /// Line and column of the start of the comment.
+    pub position: Position,
+    /// The source line containing the start of the comment.
+    line: String,
+}
+
+impl FormatError {
+    /// Creates an error for the comment at `span`, in `source`.
+    pub fn new(span: Span, source: &str) -> Self {
+        let index = LineIndex::new(source);
+        let position = index.position(span.start);
+        let line = index.line(position.line).to_string();
+        FormatError {
+            span,
+            position,
+            line,
+        }
+    }
+
+    /// Returns the source line containing the comment.
+    pub fn source_line(&self) -> &str {
+        &self.line
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "error: cannot keep this comment when formatting")?;
+        snippet(f, self.span, self.position, &self.line)?;
+        write!(f, " only comments between statements can be formatted")
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Converts the errors recovered during parsing, followed by the fatal one, if any.
+///
+/// An unterminated comment swallows the rest of the document, so any error after it is dropped.
+pub(crate) fn collect_errors(
+    recovered: Vec<ErrorRecovery>,
+    fatal: Option<LalrpopError>,
+    source: &str,
+) -> Vec<ParseError> {
+    let mut errors: Vec<ParseError> = recovered
+        .into_iter()
+        .map(|recovery| recovery.error)
+        .chain(fatal)
+        .map(|error| ParseError::from_lalrpop(error, source))
+        .collect();
+
+    if let Some(i) = errors
+        .iter()
+        .position(|e| e.kind == ParseErrorKind::UnterminatedComment)
+    {
+        errors.truncate(i + 1);
+    }
+
+    errors
+}
+
+/// Returns the span of source skipped while recovering from an error.
+pub(crate) fn recovery_span(recovery: &ErrorRecovery) -> Span {
+    use lalrpop_util::ParseError as E;
+
+    let (start, end) = match recovery.error {
+        E::InvalidToken { location } | E::UnrecognizedEOF { location, .. } => (location, location),
+        E::UnrecognizedToken {
+            token: (start, _, end),
+            ..
+        }
+        | E::ExtraToken {
+            token: (start, _, end),
+        } => (start, end),
+        E::User { ref error } => (error.span.start, error.span.end),
+    };
+    let end = recovery
+        .dropped_tokens
+        .last()
+        .map_or(end, |&(_, _, dropped_end)| dropped_end.max(end));
+    Span::new(start, end)
+}
+
+/// Error raised from within the grammar actions.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct UserError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl UserError {
+    /// Error for a number literal that could not be read.
+    pub fn invalid_number<T>(
+        text: &str,
+        start: usize,
+        end: usize,
+    ) -> lalrpop_util::ParseError<usize, T, Self> {
+        let kind = ParseErrorKind::InvalidNumber(text.to_string());
+        let span = Span::new(start, end);
+        lalrpop_util::ParseError::User {
+            error: UserError { kind, span },
+        }
+    }
+}
+
+/// Turns a lalrpop terminal name into something a human can read.
+fn describe_token(token: &str) -> String {
+    if token == "FloatLiteral" || token == "HexLiteral" {
+        "number".to_string()
+    } else if token.contains("[$_a-zA-Z0-9]") {
+        "identifier".to_string()
+    } else if token.starts_with("r#\"include") {
+        "`include <...>`".to_string()
+    } else if token.starts_with("r#\"use") {
+        "`use <...>`".to_string()
+    } else if token.starts_with("r#\"\\\"") {
+        "string".to_string()
+    } else if token.len() >= 2 && token.starts_with('"') && token.ends_with('"') {
+        format!("`{}`", &token[1..token.len() - 1])
+    } else {
+        token.to_string()
+    }
+}
+
+/// What went wrong while loading a file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadErrorKind {
+    /// No file with this name was found in the search path.
+    NotFound(String),
+    /// The file could not be read.
+    Read(PathBuf, String),
+    /// The file is not valid OpenSCAD syntax.
+    Parse(PathBuf, Vec<ParseError>),
+    /// The file is already being loaded: it includes itself, maybe indirectly.
+    Cycle(PathBuf),
+}
+
+impl fmt::Display for LoadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadErrorKind::NotFound(name) => write!(f, "cannot find file `{}`", name),
+            LoadErrorKind::Read(path, message) => {
+                write!(f, "cannot read {}: {}", path.display(), message)
+            }
+            LoadErrorKind::Parse(path, errors) => {
+                write!(f, "cannot parse {}", path.display())?;
+                for error in errors {
+                    write!(f, "\n\n{}", error)?;
+                }
+                Ok(())
+            }
+            LoadErrorKind::Cycle(path) => write!(f, "{} includes itself", path.display()),
+        }
+    }
+}
+
+/// An error while loading a file, and the files it includes or uses.
+///
+/// The `Display` implementation prints the chain of files that led to the error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadError {
+    /// What went wrong.
+    pub kind: LoadErrorKind,
+    /// Files being loaded when the error happened, from the first one to the one with the error.
+    pub chain: Vec<PathBuf>,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {}", self.kind)?;
+        for (i, path) in self.chain.iter().rev().enumerate() {
+            let from = if i == 0 { "in" } else { "loaded from" };
+            write!(f, "\n  {} {}", from, path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
@@ -0,0 +1,740 @@
+//! Source formatter.
+//!
+//! Prints an AST back as OpenSCAD source, in a deterministic layout:
+//! formatting the same statements always gives the same text, and parsing the result gives
+//! back the same statements.
+//!
+//! [`format_source`] also keeps the comments from the original document, as well as single
+//! blank lines between statements. It refuses documents with comments elsewhere than between
+//! statements, rather than dropping them.
+
+use crate::ast::{
+    Expr, ExprKind, ListElement, Modifier, ModuleCall, Opcode, ParameterDefinition, ParameterValue,
+    Statement, StatementKind,
+};
+use crate::{Error, FormatError};
+
+/// Where to put opening braces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BraceStyle {
+    /// `module foo() {`
+    SameLine,
+    /// `module foo()` then `{` on its own line.
+    NextLine,
+}
+
+/// Options controlling the output of the formatter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// Number of spaces per indentation level.
+    pub indent_width: usize,
+    /// Lists (vectors, arguments) longer than this are split on multiple lines.
+    pub max_line_length: usize,
+    /// Where to put opening braces.
+    pub brace_style: BraceStyle,
+    /// Add a comma after the last element of vectors split on multiple lines.
+    pub trailing_commas: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 4,
+            max_line_length: 100,
+            brace_style: BraceStyle::SameLine,
+            trailing_commas: false,
+        }
+    }
+}
+
+/// Formats a list of statements.
+pub fn format(statements: &[Statement], options: &FormatOptions) -> String {
+    let formatter = Formatter {
+        options,
+        source: None,
+        echo: false,
+    };
+    let mut out = String::new();
+    formatter.statements(statements, 0, &mut out);
+    out
+}
+
+/// Formats an OpenSCAD document, keeping its comments.
+///
+/// Returns [`Error::Format`] if some comments are not between statements, like in an expression,
+/// since they would be lost.
+pub fn format_source(source: &str, options: &FormatOptions) -> Result<String, Error> {
+    let tree = crate::cst::parse_lossless(source)?;
+    if !tree.unattached_comments.is_empty() {
+        let errors = tree
+            .unattached_comments
+            .iter()
+            .map(|comment| FormatError::new(comment.span, source))
+            .collect();
+        return Err(Error::Format(errors));
+    }
+    let formatter = Formatter {
+        options,
+        source: Some(source),
+        echo: false,
+    };
+    let mut out = String::new();
+    formatter.statements(&tree.statements, 0, &mut out);
+    Ok(out)
+}
+
+/// Formats a single expression on one line.
+pub fn format_expr(expr: &Expr) -> String {
+    one_line(expr, false)
+}
+
+/// Formats a single expression on one line like OpenSCAD echoes it, with every binary and
+/// ternary operation in parentheses: `function(x) (x + 1)`.
+pub fn echo_expr(expr: &Expr) -> String {
+    one_line(expr, true)
+}
+
+fn one_line(expr: &Expr, echo: bool) -> String {
+    let options = FormatOptions {
+        max_line_length: usize::MAX,
+        ..FormatOptions::default()
+    };
+    let formatter = Formatter {
+        options: &options,
+        source: None,
+        echo,
+    };
+    formatter.expr(expr, 0, 0)
+}
+
+/// Binding strength of expressions, from loosest to tightest.
+///
+/// Mirrors the precedence ladder of the grammar.
+mod precedence {
+    /// `let`, function literals, `echo`, `assert` and the ternary operator.
+    pub const LOOSE: u8 = 0;
+    pub const OR: u8 = 1;
+    pub const AND: u8 = 2;
+    pub const EQUALITY: u8 = 3;
+    pub const COMPARISON: u8 = 4;
+    pub const BIT_OR: u8 = 5;
+    pub const BIT_AND: u8 = 6;
+    pub const SHIFT: u8 = 7;
+    pub const ADDITION: u8 = 8;
+    pub const MULTIPLICATION: u8 = 9;
+    pub const UNARY: u8 = 10;
+    pub const POWER: u8 = 11;
+    pub const TERM: u8 = 12;
+}
+
+fn opcode(op: &Opcode) -> (&'static str, u8) {
+    use precedence::*;
+    let precedence = match op {
+        Opcode::Mul | Opcode::Div | Opcode::Rem => MULTIPLICATION,
+        Opcode::Add | Opcode::Sub => ADDITION,
+        Opcode::Equal | Opcode::NotEqual => EQUALITY,
+        Opcode::Gt | Opcode::Gte | Opcode::Lt | Opcode::Lte => COMPARISON,
+        Opcode::Pow => POWER,
+        Opcode::ShiftLeft | Opcode::ShiftRight => SHIFT,
+        Opcode::BitAnd => BIT_AND,
+        Opcode::BitOr => BIT_OR,
+    };
+    (op.symbol(), precedence)
+}
+
+fn expr_precedence(expr: &Expr) -> u8 {
+    use precedence::*;
+    match &expr.kind {
+        ExprKind::Let(..)
+        | ExprKind::Lambda { .. }
+        | ExprKind::Echo(..)
+        | ExprKind::Assert(..)
+        | ExprKind::Ternary { .. } => LOOSE,
+        ExprKind::Or(..) => OR,
+        ExprKind::And(..) => AND,
+        ExprKind::Op(_, op, _) => opcode(op).1,
+        ExprKind::Negative(_) | ExprKind::Not(_) | ExprKind::BitNot(_) => UNARY,
+        ExprKind::Number(n) if n.is_sign_negative() => UNARY,
+        _ => TERM,
+    }
+}
+
+/// Whether the expression is a binary or ternary operation.
+fn is_operation(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Or(..) | ExprKind::And(..) | ExprKind::Op(..) | ExprKind::Ternary { .. }
+    )
+}
+
+/// Formats a number so it parses back to the same value.
+fn number(n: f64) -> String {
+    if n.is_infinite() {
+        // Too large to be represented, so it parses back as infinity.
+        let sign = if n < 0.0 { "-" } else { "" };
+        format!("{}1e999", sign)
+    } else if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n)
+    } else {
+        format!("{:?}", n)
+    }
+}
+
+/// Formats a string literal, escaping what needs to be.
+fn string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            c if c.is_control() && (c as u32) < 0x80 => {
+                result.push_str(&format!("\\x{:02x}", c as u32))
+            }
+            c if c.is_control() => result.push_str(&format!("\\U{:06x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn modifier(m: Modifier) -> char {
+    match m {
+        Modifier::Disable => '*',
+        Modifier::ShowOnly => '!',
+        Modifier::Highlight => '#',
+        Modifier::Transparent => '%',
+    }
+}
+
+/// Returns the column at the end of `text`, if it starts at `column`.
+fn end_column(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(i) => text[i + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+/// Returns `true` if the statement ends with an `if` without `else`.
+///
+/// An `else` following such a statement would be attached to the wrong `if`.
+fn is_open(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::If { if_false, .. } => {
+            matches!(if_false.kind, StatementKind::NoOp) || is_open(if_false)
+        }
+        StatementKind::ModuleCall(call) => is_open(&call.child),
+        StatementKind::For { body, .. }
+        | StatementKind::Let(_, body)
+        | StatementKind::Modifier(_, body) => is_open(body),
+        _ => false,
+    }
+}
+
+/// Same as `is_open`, for list comprehensions.
+fn is_open_element(element: &ListElement) -> bool {
+    match element {
+        ListElement::Expr(_) => false,
+        ListElement::If { if_false, .. } => match if_false {
+            Some(if_false) => is_open_element(if_false),
+            None => true,
+        },
+        ListElement::Each(body)
+        | ListElement::For { body, .. }
+        | ListElement::ForC { body, .. }
+        | ListElement::Let { body, .. } => is_open_element(body),
+    }
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    /// Original source, used to keep blank lines and trailing comments.
+    source: Option<&'a str>,
+    /// Whether every operation is in parentheses, like OpenSCAD echoes expressions.
+    echo: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn indentation(&self, indent: usize) -> String {
+        " ".repeat(indent * self.options.indent_width)
+    }
+
+    fn fits(&self, column: usize, text: &str) -> bool {
+        !text.contains('\n') && column + text.chars().count() <= self.options.max_line_length
+    }
+
+    /// Formats statements, each on its own line.
+    fn statements(&self, statements: &[Statement], indent: usize, out: &mut String) {
+        let mut previous: Option<&Statement> = None;
+        for statement in statements {
+            let newlines = match (previous, self.source) {
+                (Some(previous), Some(source)) => source[previous.span.end..statement.span.start]
+                    .matches('\n')
+                    .count(),
+                _ => 1,
+            };
+            previous = Some(statement);
+
+            if newlines == 0 && matches!(statement.kind, StatementKind::Comment(_)) {
+                // Keep trailing comments on the same line.
+                out.pop();
+                out.push(' ');
+                out.push_str(self.statement(statement, indent).trim_start());
+                out.push('\n');
+                continue;
+            }
+            if newlines > 1 {
+                out.push('\n');
+            }
+            out.push_str(&self.statement(statement, indent));
+            out.push('\n');
+        }
+    }
+
+    /// Formats a statement, starting with indentation, without the final line break.
+    fn statement(&self, statement: &Statement, indent: usize) -> String {
+        let prefix = self.indentation(indent);
+        let column = prefix.len();
+        let mut out = prefix;
+
+        match &statement.kind {
+            StatementKind::VariableDeclaration(name, value) => {
+                let head = format!("{} = ", name);
+                let value = self.expr(value, indent, column + head.len());
+                out.push_str(&head);
+                out.push_str(&value);
+                out.push(';');
+            }
+            StatementKind::Modifier(m, child) => {
+                out.push(modifier(*m));
+                let child = self.statement(child, indent);
+                out.push_str(child.trim_start());
+            }
+            StatementKind::StatementList(statements) => {
+                out.push_str(&self.block(statements, indent));
+            }
+            StatementKind::NoOp => out.push(';'),
+            StatementKind::Error => out.push_str("/* error */;"),
+            StatementKind::ModuleDefinition { name, args, body } => {
+                let head = format!("module {}(", name);
+                let args = self.arguments(args, indent, column + head.len());
+                out.push_str(&head);
+                out.push_str(&args);
+                out.push(')');
+                out.push_str(&self.child(body, indent));
+            }
+            StatementKind::FunctionDefinition(name, args, body) => {
+                let head = format!("function {}(", name);
+                let args = self.arguments(args, indent, column + head.len());
+                out.push_str(&head);
+                out.push_str(&args);
+                out.push_str(") = ");
+                let column = end_column(column, &out);
+                out.push_str(&self.expr(body, indent, column));
+                out.push(';');
+            }
+            StatementKind::Include(path) => out.push_str(&format!("include <{}>", path)),
+            StatementKind::Use(path) => out.push_str(&format!("use <{}>", path)),
+            StatementKind::ModuleCall(ModuleCall {
+                function,
+                params,
+                child,
+            }) => {
+                out.push_str(&self.call(function, params, indent, column));
+                out.push_str(&self.child(child, indent));
+            }
+            StatementKind::Let(lets, body) => {
+                let params: Vec<_> = lets.iter().flat_map(|l| l.vars.iter().cloned()).collect();
+                out.push_str(&self.call("let ", &params, indent, column));
+                out.push_str(&self.child(body, indent));
+            }
+            StatementKind::For { variables, body } => {
+                out.push_str(&self.call("for ", variables, indent, column));
+                out.push_str(&self.child(body, indent));
+            }
+            StatementKind::Comment(text) => out.push_str(text.trim_end()),
+            StatementKind::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                out.push_str("if (");
+                let column = end_column(column, &out);
+                out.push_str(&self.expr(condition, indent, column));
+                out.push(')');
+
+                let has_else = !matches!(if_false.kind, StatementKind::NoOp);
+                if has_else && is_open(if_true) {
+                    // Braces are needed so the `else` is not stolen by a nested `if`.
+                    let block = vec![(**if_true).clone()];
+                    out.push_str(&self.opening_brace(indent));
+                    out.push_str(&self.block_content(&block, indent));
+                } else {
+                    out.push_str(&self.child(if_true, indent));
+                }
+
+                if has_else {
+                    if out.ends_with('}') && self.options.brace_style == BraceStyle::SameLine {
+                        out.push_str(" else");
+                    } else {
+                        out.push('\n');
+                        out.push_str(&self.indentation(indent));
+                        out.push_str("else");
+                    }
+                    if matches!(if_false.kind, StatementKind::If { .. }) {
+                        out.push(' ');
+                        out.push_str(self.statement(if_false, indent).trim_start());
+                    } else {
+                        out.push_str(&self.child(if_false, indent));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Formats what comes after a module call or definition.
+    fn child(&self, child: &Statement, indent: usize) -> String {
+        match &child.kind {
+            StatementKind::NoOp => ";".to_string(),
+            StatementKind::StatementList(statements) => {
+                let mut out = self.opening_brace(indent);
+                out.push_str(&self.block_content(statements, indent));
+                out
+            }
+            _ => format!(" {}", self.statement(child, indent).trim_start()),
+        }
+    }
+
+    /// Formats the `{` opening a block, with the space or line break before it.
+    fn opening_brace(&self, indent: usize) -> String {
+        match self.options.brace_style {
+            BraceStyle::SameLine => " {".to_string(),
+            BraceStyle::NextLine => format!("\n{}{{", self.indentation(indent)),
+        }
+    }
+
+    /// Formats a bare `{ }` block.
+    fn block(&self, statements: &[Statement], indent: usize) -> String {
+        format!("{{{}", self.block_content(statements, indent))
+    }
+
+    /// Formats the inside of a block, after the opening brace, up to the closing brace.
+    fn block_content(&self, statements: &[Statement], indent: usize) -> String {
+        if statements.is_empty() {
+            return "}".to_string();
+        }
+        let mut out = "\n".to_string();
+        self.statements(statements, indent + 1, &mut out);
+        out.push_str(&self.indentation(indent));
+        out.push('}');
+        out
+    }
+
+    /// Formats a call like `name(a, b = 2)`.
+    fn call(&self, name: &str, params: &[ParameterValue], indent: usize, column: usize) -> String {
+        let column = end_column(column, name) + 1;
+        let items: Vec<_> = params
+            .iter()
+            .map(|p| move |formatter: &Self, indent, column| formatter.parameter(p, indent, column))
+            .collect();
+        format!("{}({})", name, self.list(&items, indent, column, false))
+    }
+
+    fn parameter(&self, param: &ParameterValue, indent: usize, column: usize) -> String {
+        match &param.name {
+            Some(name) => {
+                let head = format!("{} = ", name);
+                let value = self.expr(&param.value, indent, column + head.len());
+                head + &value
+            }
+            None => self.expr(&param.value, indent, column),
+        }
+    }
+
+    fn arguments(&self, args: &[ParameterDefinition], indent: usize, column: usize) -> String {
+        let items: Vec<_> = args
+            .iter()
+            .map(|arg| {
+                move |formatter: &Self, indent, column| match &arg.default_value {
+                    Some(value) => {
+                        let head = format!("{} = ", arg.name);
+                        let value = formatter.expr(value, indent, column + head.len());
+                        head + &value
+                    }
+                    None => arg.name.to_string(),
+                }
+            })
+            .collect();
+        self.list(&items, indent, column, false)
+    }
+
+    /// Formats comma-separated items, either on one line, or one per line if too long.
+    ///
+    /// Does not include the delimiters.
+    fn list<F>(&self, items: &[F], indent: usize, column: usize, vector: bool) -> String
+    where
+        F: Fn(&Self, usize, usize) -> String,
+    {
+        // First try everything on a single line.
+        let mut flat = String::new();
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                flat.push_str(", ");
+            }
+            let column = end_column(column, &flat);
+            flat.push_str(&item(self, indent, column));
+        }
+        // Leave room for the closing delimiter.
+        if items.is_empty() || self.fits(column + 1, &flat) {
+            return flat;
+        }
+
+        let inner = self.indentation(indent + 1);
+        let mut out = String::new();
+        for (i, item) in items.iter().enumerate() {
+            out.push('\n');
+            out.push_str(&inner);
+            out.push_str(&item(self, indent + 1, inner.len()));
+            if i + 1 < items.len() || (vector && self.options.trailing_commas) {
+                out.push(',');
+            }
+        }
+        out.push('\n');
+        out.push_str(&self.indentation(indent));
+        out
+    }
+
+    /// Formats an operand, adding parentheses if it binds looser than `min`.
+    fn operand(&self, expr: &Expr, min: u8, indent: usize, column: usize) -> String {
+        if expr_precedence(expr) < min && !(self.echo && is_operation(expr)) {
+            format!("({})", self.expr(expr, indent, column + 1))
+        } else {
+            self.expr(expr, indent, column)
+        }
+    }
+
+    /// Formats an expression starting at the given column.
+    fn expr(&self, expr: &Expr, indent: usize, column: usize) -> String {
+        use precedence::*;
+
+        match &expr.kind {
+            ExprKind::Undef => "undef".to_string(),
+            ExprKind::Boolean(b) => b.to_string(),
+            ExprKind::Number(n) => number(*n),
+            ExprKind::Text(text) => string(text),
+            ExprKind::Variable(name) => name.to_string(),
+            ExprKind::Negative(e) => format!("-{}", self.operand(e, UNARY, indent, column + 1)),
+            ExprKind::Not(e) => format!("!{}", self.operand(e, UNARY, indent, column + 1)),
+            ExprKind::BitNot(e) => format!("~{}", self.operand(e, UNARY, indent, column + 1)),
+            ExprKind::Or(a, b) => self.binary(a, "||", b, OR, indent, column),
+            ExprKind::And(a, b) => self.binary(a, "&&", b, AND, indent, column),
+            ExprKind::Op(a, op, b) => {
+                let (symbol, level) = opcode(op);
+                if *op == Opcode::Pow {
+                    // Right-associative, and the left side must be a term.
+                    let left = self.operand(a, TERM, indent, column);
+                    let column = end_column(column, &left) + 1;
+                    let right = self.operand(b, UNARY, indent, column);
+                    self.operation(format!("{}^{}", left, right))
+                } else {
+                    self.binary(a, symbol, b, level, indent, column)
+                }
+            }
+            ExprKind::Function(call) => {
+                let function = self.operand(&call.function, TERM, indent, column);
+                self.call(&function, &call.parameters, indent, column)
+            }
+            ExprKind::Lambda { args, body } => {
+                let args = self.arguments(args, indent, column + 9);
+                let head = format!("function({}) ", args);
+                let column = end_column(column, &head);
+                head + &self.expr(body, indent, column)
+            }
+            ExprKind::Echo(params, body) => self.prefixed("echo", params, body, indent, column),
+            ExprKind::Assert(params, body) => self.prefixed("assert", params, body, indent, column),
+            ExprKind::Let(lets, body) => {
+                let mut out = String::new();
+                for l in lets {
+                    let column = end_column(column, &out);
+                    out.push_str(&self.call("let ", &l.vars, indent, column));
+                    out.push(' ');
+                }
+                // A nested `let` would be merged with this one.
+                let column = end_column(column, &out);
+                let min = if matches!(body.kind, ExprKind::Let(..)) {
+                    TERM
+                } else {
+                    LOOSE
+                };
+                out.push_str(&self.operand(body, min, indent, column));
+                out
+            }
+            ExprKind::Ternary {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let mut out = self.operand(condition, OR, indent, column);
+                out.push_str(" ? ");
+                let column = end_column(column, &out);
+                out.push_str(&self.expr(if_true, indent, column));
+                out.push_str(" : ");
+                let column = end_column(column, &out);
+                out.push_str(&self.expr(if_false, indent, column));
+                self.operation(out)
+            }
+            ExprKind::Vector(values) => {
+                let items: Vec<_> = values
+                    .iter()
+                    .map(|v| move |f: &Self, indent, column| f.expr(v, indent, column))
+                    .collect();
+                format!("[{}]", self.list(&items, indent, column + 1, true))
+            }
+            ExprKind::ListComprehension(elements) => {
+                let items: Vec<_> = elements
+                    .iter()
+                    .map(|e| move |f: &Self, indent, column| f.element(e, indent, column))
+                    .collect();
+                format!("[{}]", self.list(&items, indent, column + 1, true))
+            }
+            ExprKind::FieldAccess { parent, field } => {
+                format!("{}.{}", self.operand(parent, TERM, indent, column), field)
+            }
+            ExprKind::ArrayAccess { array, index } => {
+                let array = self.operand(array, TERM, indent, column);
+                let column = end_column(column, &array) + 1;
+                format!("{}[{}]", array, self.expr(index, indent, column))
+            }
+            ExprKind::Range {
+                start,
+                end,
+                increment,
+            } => {
+                let mut out = "[".to_string();
+                out.push_str(&self.expr(start, indent, column + 1));
+                if let Some(increment) = increment {
+                    out.push_str(" : ");
+                    let column = end_column(column, &out);
+                    out.push_str(&self.expr(increment, indent, column));
+                }
+                out.push_str(" : ");
+                let column = end_column(column, &out);
+                out.push_str(&self.expr(end, indent, column));
+                out.push(']');
+                out
+            }
+        }
+    }
+
+    fn binary(
+        &self,
+        a: &Expr,
+        symbol: &str,
+        b: &Expr,
+        level: u8,
+        indent: usize,
+        column: usize,
+    ) -> String {
+        // Left-associative: the right side must bind tighter.
+        let left = self.operand(a, level, indent, column);
+        let column = end_column(column, &left) + symbol.len() + 2;
+        let right = self.operand(b, level + 1, indent, column);
+        self.operation(format!("{} {} {}", left, symbol, right))
+    }
+
+    /// Puts a binary or ternary operation in parentheses when echoing.
+    fn operation(&self, out: String) -> String {
+        if self.echo {
+            format!("({})", out)
+        } else {
+            out
+        }
+    }
+
+    /// Formats `echo(...) body` or `assert(...) body`.
+    fn prefixed(
+        &self,
+        name: &str,
+        params: &[ParameterValue],
+        body: &Expr,
+        indent: usize,
+        column: usize,
+    ) -> String {
+        let mut out = self.call(name, params, indent, column);
+        out.push(' ');
+        let column = end_column(column, &out);
+        out.push_str(&self.expr(body, indent, column));
+        out
+    }
+
+    /// Formats an element of a list comprehension.
+    fn element(&self, element: &ListElement, indent: usize, column: usize) -> String {
+        let (head, body) = match element {
+            ListElement::Expr(expr) => return self.expr(expr, indent, column),
+            ListElement::Each(body) => ("each ".to_string(), body),
+            ListElement::For { variables, body } => {
+                (self.call("for ", variables, indent, column) + " ", body)
+            }
+            ListElement::ForC {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let mut head = "for (".to_string();
+                let params = |params: &[ParameterValue], head: &mut String| {
+                    for (i, param) in params.iter().enumerate() {
+                        if i > 0 {
+                            head.push_str(", ");
+                        }
+                        let column = end_column(column, head);
+                        head.push_str(&self.parameter(param, indent, column));
+                    }
+                };
+                params(init, &mut head);
+                head.push_str("; ");
+                let condition = self.expr(condition, indent, end_column(column, &head));
+                head.push_str(&condition);
+                head.push_str("; ");
+                params(update, &mut head);
+                head.push_str(") ");
+                (head, body)
+            }
+            ListElement::Let { vars, body } => {
+                (self.call("let ", vars, indent, column) + " ", body)
+            }
+            ListElement::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let mut out = "if (".to_string();
+                out.push_str(&self.expr(condition, indent, column + 4));
+                out.push_str(") ");
+                let column = end_column(column, &out);
+                let needs_parens = if_false.is_some() && is_open_element(if_true);
+                if needs_parens {
+                    out.push('(');
+                    out.push_str(&self.element(if_true, indent, column + 1));
+                    out.push(')');
+                } else {
+                    out.push_str(&self.element(if_true, indent, column));
+                }
+                if let Some(if_false) = if_false {
+                    out.push_str(" else ");
+                    let column = end_column(column, &out);
+                    out.push_str(&self.element(if_false, indent, column));
+                }
+                return out;
+            }
+        };
+
+        let column = end_column(column, &head);
+        head + &self.element(body, indent, column)
+    }
+}
@@ -0,0 +1,145 @@
+//! Access to the files read by a document: included and used files, imported meshes, ...
+//!
+//! All file access goes through a [`FileSystem`], so a project can be loaded from memory (for
+//! tests, or a server-side renderer) with a [`MemoryFileSystem`], instead of the real file
+//! system with [`StdFileSystem`].
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where files are read from.
+pub trait FileSystem: Send + Sync {
+    /// Reads the whole content of a file.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Returns `true` if the path points to an existing file.
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// Returns the canonical path of an existing file.
+    ///
+    /// Two paths to the same file must give the same result: it is used to cache files and
+    /// detect cycles.
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Reads the whole content of a text file.
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        String::from_utf8(self.read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// The real file system, through `std::fs`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        path.canonicalize()
+    }
+
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Files kept in memory.
+///
+/// Paths are normalized, so `lib/../main.scad` and `main.scad` are the same file. There is no
+/// current directory: relative paths stay relative.
+///
+/// ```
+/// use rscad::fs::{FileSystem, MemoryFileSystem};
+/// use std::path::Path;
+///
+/// let mut fs = MemoryFileSystem::new();
+/// fs.insert("project/main.scad", "include <lib/utils.scad>");
+/// assert!(fs.is_file(Path::new("project/lib/../main.scad")));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFileSystem {
+    /// Content of each file, by normalized path.
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFileSystem {
+    /// Creates an empty file system.
+    pub fn new() -> Self {
+        MemoryFileSystem::default()
+    }
+
+    /// Adds a file, or replaces its content.
+    pub fn insert<P: AsRef<Path>, C: Into<Vec<u8>>>(&mut self, path: P, content: C) {
+        self.files.insert(normalize(path.as_ref()), content.into());
+    }
+
+    /// Removes a file, returning its content.
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Option<Vec<u8>> {
+        self.files.remove(&normalize(path.as_ref()))
+    }
+}
+
+impl From<HashMap<PathBuf, String>> for MemoryFileSystem {
+    fn from(files: HashMap<PathBuf, String>) -> Self {
+        let mut fs = MemoryFileSystem::new();
+        for (path, content) in files {
+            fs.insert(path, content);
+        }
+        fs
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(&normalize(path))
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = normalize(path);
+        if self.files.contains_key(&path) {
+            Ok(path)
+        } else {
+            Err(not_found(&path))
+        }
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    let message = format!("{} not found", path.display());
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+/// Removes `.` and resolves `..` in a path, without looking at the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
+                }
+                // `/..` is still `/`
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => (),
+                _ => result.push(component),
+            },
+            component => result.push(component),
+        }
+    }
+    result
+}
@@ -1,29 +1,250 @@
+//! Evaluates resolved documents.
+
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+
+use crate::parser::{Expr, Function, Id, Module, Scope};
+
+mod bind;
+mod color;
+mod eval;
+mod functions;
+mod instantiate;
+mod modules;
+mod ops;
+mod random;
+mod value;
+
+pub use self::bind::{bind, Argument, Bound};
+pub use self::eval::{Aborted, Evaluator, MAX_DEPTH, MAX_ITERATIONS};
+pub use self::instantiate::{instantiate, Output};
+pub use self::value::{Closure, RangeIter, Value};
+
+pub(crate) use self::value::format_number;
 
 /// Context for the interpreter.
 ///
-/// Contains values for variables and modules
+/// Contains the values of the variables of one scope. Regular variables are looked up
+/// lexically, through `parent`, while special variables (like `$fn`) are looked up
+/// dynamically, through `caller`.
+///
+/// Variables are assigned once, in order, while the context is shared: function literals keep
+/// a reference to the context they are defined in, so they can call themselves.
+#[derive(Debug)]
 pub struct Context {
-    modules: HashMap<String, Module>,
-    variables: HashMap<String, Value>,
+    /// Value of each variable in the scope, empty until assigned.
+    variables: Vec<OnceLock<Value>>,
+    /// Special variables of the scope, with their index in `variables`.
+    special_variables: Vec<(String, usize)>,
+    /// Special variables set when creating this context, for example by named arguments.
+    specials: HashMap<String, Value>,
+    /// Functions defined in the scope.
+    functions: Vec<Arc<Function>>,
+    /// Modules defined in the scope.
+    modules: Vec<Arc<Module>>,
+    /// Top-level contexts of the libraries used by the document, for its top-level context.
+    libraries: Vec<Arc<Context>>,
+    /// Context where the scope was defined.
     parent: Option<Arc<Context>>,
+    /// Context this one was called from.
+    caller: Option<Arc<Context>>,
 }
 
-pub enum Value {
-    Bool(bool),
-    Number(f64),
-    Text(String),
-}
+impl Context {
+    /// Creates the top-level context, with the default value of special variables.
+    pub fn root() -> Self {
+        let specials = [
+            ("$fn", Value::Number(0.0)),
+            ("$fa", Value::Number(12.0)),
+            ("$fs", Value::Number(2.0)),
+            ("$t", Value::Number(0.0)),
+            ("$preview", Value::Bool(false)),
+        ];
+        Context {
+            variables: Vec::new(),
+            special_variables: Vec::new(),
+            specials: specials
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            functions: Vec::new(),
+            modules: Vec::new(),
+            libraries: Vec::new(),
+            parent: None,
+            caller: None,
+        }
+    }
 
-impl Value {
-    pub fn as_bool(&self) -> bool {
-        match *self {
-            Value::Bool(b) => b,
-            Value::Number(x) => x.is_normal(),
-            Value::Text(ref txt) => !txt.is_empty(),
+    /// Creates the context for the top-level scope of a document, using the given libraries.
+    ///
+    /// `libraries` are the top-level contexts of [`crate::parser::Document::libraries`], in
+    /// order.
+    pub fn document(scope: &Scope, libraries: Vec<Arc<Context>>) -> Self {
+        let root = Arc::new(Context::root());
+        Context {
+            libraries,
+            ..Context::new(scope, Arc::clone(&root), root)
         }
     }
-}
 
-pub struct Module {}
+    /// Creates the context for a scope defined in `parent`, and instantiated from `caller`.
+    ///
+    /// For a module body, `parent` is where the module is defined. For blocks, loops or children
+    /// of a module call, `parent` is where they are written. In both cases, `caller` is where
+    /// they are being evaluated.
+    pub fn new(scope: &Scope, parent: Arc<Context>, caller: Arc<Context>) -> Self {
+        Context {
+            variables: scope.variables.iter().map(|_| OnceLock::new()).collect(),
+            special_variables: scope.specials.clone(),
+            specials: HashMap::new(),
+            functions: scope.functions.clone(),
+            modules: scope.modules.clone(),
+            libraries: Vec::new(),
+            parent: Some(parent),
+            caller: Some(caller),
+        }
+    }
+
+    /// Creates the context for the variables of a `let` or a loop in an expression, which have
+    /// no scope of their own.
+    pub fn locals(variables: usize, parent: Arc<Context>, caller: Arc<Context>) -> Self {
+        Context {
+            variables: (0..variables).map(|_| OnceLock::new()).collect(),
+            ..Context::new(&Scope::default(), parent, caller)
+        }
+    }
+
+    /// Creates the context for the body of a module or function, defined in `parent` and called
+    /// from `caller`, or for the body of a `for` or `let` module.
+    ///
+    /// `arguments` are the values of the parameters or loop variables, which are the first
+    /// variables of the scope, with default values already applied. Parameters assigned again in the body are left
+    /// for the body to assign. `specials` are the other special variables set by the call.
+    pub fn with_arguments(
+        scope: &Scope,
+        arguments: Vec<Value>,
+        specials: Vec<(String, Value)>,
+        parent: Arc<Context>,
+        caller: Arc<Context>,
+    ) -> Self {
+        let mut context = Context::new(scope, parent, caller);
+        for (name, value) in specials {
+            context.set_special(&name, value);
+        }
+        for (id, value) in arguments.into_iter().enumerate() {
+            if let Some(Expr::Extern) = scope.variables.get(id) {
+                context.set_variable(id, value);
+            }
+        }
+        context
+    }
+
+    /// Assigns the variable with the given index in the current scope.
+    ///
+    /// If it is a special variable, it becomes visible to everything called from this context.
+    /// Variables can only be assigned once: assigning one again has no effect.
+    pub fn set_variable(&self, id: usize, value: Value) {
+        let _ = self.variables[id].set(value);
+    }
+
+    /// Sets a special variable, for example from a named parameter like `$fn = 32`.
+    pub fn set_special(&mut self, name: &str, value: Value) {
+        self.specials.insert(name.to_string(), value);
+    }
+
+    /// Returns the value of a variable, if it was assigned.
+    pub fn variable(&self, id: &Id) -> Option<&Value> {
+        self.ancestor(id.depth)?.variables.get(id.id)?.get()
+    }
+
+    /// Returns the value of a special variable, from the closest context in the call stack.
+    pub fn special(&self, name: &str) -> Option<&Value> {
+        // In a loop rather than recursively: the call stack can be deep.
+        let mut context = self;
+        loop {
+            let value = context
+                .special_variables
+                .iter()
+                .rev()
+                .filter(|(n, _)| n == name)
+                .find_map(|&(_, id)| context.variables[id].get())
+                .or_else(|| context.specials.get(name));
+            if value.is_some() {
+                return value;
+            }
+            context = context.caller.as_ref()?;
+        }
+    }
+
+    /// Returns the function with the given id, with the context it is defined in.
+    pub fn function(self: &Arc<Self>, id: &Id) -> Option<(&Arc<Function>, &Arc<Context>)> {
+        let context = self.shared_ancestor(id.depth)?;
+        Some((context.functions.get(id.id)?, context))
+    }
+
+    /// Returns the module with the given id, with the context it is defined in.
+    pub fn module(self: &Arc<Self>, id: &Id) -> Option<(&Arc<Module>, &Arc<Context>)> {
+        let context = self.shared_ancestor(id.depth)?;
+        Some((context.modules.get(id.id)?, context))
+    }
+
+    /// Returns the top-level context of a library used by the document.
+    pub fn library(&self, library: usize) -> Option<&Arc<Context>> {
+        match &self.parent {
+            Some(parent) if self.libraries.is_empty() => parent.library(library),
+            _ => self.libraries.get(library),
+        }
+    }
+
+    /// Replaces the caller with a context holding only the special variables visible from it.
+    ///
+    /// Used for tail calls, so that a function calling itself in a loop does not keep every
+    /// previous call alive.
+    fn detach_caller(&mut self) {
+        if let Some(caller) = self.caller.take() {
+            let mut specials = HashMap::new();
+            caller.collect_specials(&mut specials);
+            self.caller = Some(Arc::new(Context {
+                specials,
+                ..Context::root()
+            }));
+        }
+    }
+
+    /// Adds the special variables visible from this context to `specials`, unless they are
+    /// already there.
+    fn collect_specials(&self, specials: &mut HashMap<String, Value>) {
+        let mut context = Some(self);
+        while let Some(current) = context {
+            for (name, id) in current.special_variables.iter().rev() {
+                if let Some(value) = current.variables[*id].get() {
+                    specials
+                        .entry(name.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+            for (name, value) in &current.specials {
+                specials
+                    .entry(name.clone())
+                    .or_insert_with(|| value.clone());
+            }
+            context = current.caller.as_deref();
+        }
+    }
+
+    /// Returns the context `depth` levels up, through parents.
+    fn ancestor(&self, depth: usize) -> Option<&Context> {
+        match depth {
+            0 => Some(self),
+            _ => self.parent.as_ref()?.ancestor(depth - 1),
+        }
+    }
+
+    /// Like `ancestor`, but keeps the context shared.
+    fn shared_ancestor(self: &Arc<Self>, depth: usize) -> Option<&Arc<Context>> {
+        match depth {
+            0 => Some(self),
+            _ => self.parent.as_ref()?.shared_ancestor(depth - 1),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Binds the arguments of a call to the parameters of a module or function.
+
+use crate::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::span::Span;
+
+/// An argument given to a module or function, already evaluated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Argument<T> {
+    /// Name of the parameter, for named arguments: `r = 2`.
+    pub name: Option<String>,
+    /// Value of the argument.
+    pub value: T,
+    /// Where the argument comes from.
+    pub span: Span,
+}
+
+/// Arguments bound to the parameters of a module or function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bound<T> {
+    /// Value of each parameter, in order, or `None` if no argument was given for it.
+    pub values: Vec<Option<T>>,
+    /// Special variables given as named arguments, like `$fn = 8`, that are not parameters.
+    ///
+    /// They are set for the body of the module or function, and everything it calls.
+    pub specials: Vec<(String, T)>,
+}
+
+impl<T> Bound<T> {
+    /// Returns the value of each parameter, calling `default` with the index of those that were
+    /// not given.
+    pub fn with_defaults<F: FnMut(usize) -> T>(self, mut default: F) -> Vec<T> {
+        self.values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| value.unwrap_or_else(|| default(i)))
+            .collect()
+    }
+}
+
+/// Binds arguments to parameters with OpenSCAD's rules.
+///
+/// * Positional arguments are bound first, to the parameters in order. Extra positional
+///   arguments are ignored.
+/// * Named arguments are bound next, by name, replacing a positional argument for the same
+///   parameter. A name given more than once is reported as a warning, and its last value is
+///   used.
+/// * Named arguments that are not parameters are reported as warnings and ignored, unless they
+///   are special variables (`$fn`): they are returned in [`Bound::specials`].
+///
+/// `callee` is the name of the module or function, for diagnostics.
+pub fn bind<T>(
+    callee: &str,
+    parameters: &[&str],
+    arguments: Vec<Argument<T>>,
+    diagnostics: &mut Diagnostics,
+) -> Bound<T> {
+    let mut values: Vec<Option<T>> = parameters.iter().map(|_| None).collect();
+    let mut specials = Vec::new();
+
+    let (positional, named): (Vec<_>, Vec<_>) =
+        arguments.into_iter().partition(|arg| arg.name.is_none());
+
+    for (value, arg) in values.iter_mut().zip(positional) {
+        *value = Some(arg.value);
+    }
+
+    let mut seen: Vec<(String, Span)> = Vec::new();
+    for arg in named {
+        let name = arg.name.unwrap_or_default();
+        let duplicate = match seen.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, previous)) => {
+                let message = format!(
+                    "argument `{}` given more than once to `{}`, only the last value is used",
+                    name, callee
+                );
+                let diagnostic = Diagnostic::warning(Code::DuplicateArgument, message, arg.span)
+                    .with_note("previously given here", Some(*previous));
+                diagnostics.push(diagnostic);
+                *previous = arg.span;
+                true
+            }
+            None => {
+                seen.push((name.clone(), arg.span));
+                false
+            }
+        };
+
+        if let Some(i) = parameters.iter().position(|&p| p == name) {
+            values[i] = Some(arg.value);
+        } else if name.starts_with('$') {
+            match specials.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, value)) => *value = arg.value,
+                None => specials.push((name, arg.value)),
+            }
+        } else if !duplicate {
+            let message = format!("`{}` is not a parameter of `{}`", name, callee);
+            let mut diagnostic = Diagnostic::warning(Code::UnknownArgument, message, arg.span);
+            if !parameters.is_empty() {
+                let names: Vec<_> = parameters.iter().map(|p| format!("`{}`", p)).collect();
+                let note = format!("parameters are {}", names.join(", "));
+                diagnostic = diagnostic.with_note(note, None);
+            }
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    Bound { values, specials }
+}
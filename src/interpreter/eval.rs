@@ -0,0 +1,712 @@
+//! Evaluates expressions.
+
+use std::sync::Arc;
+
+use super::random::Mt19937;
+use super::{bind, ops, Argument, Bound, Closure, Context, RangeIter, Value};
+use crate::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::parser::{
+    Axis, Document, Expr, Function, Id, ListElement, ParameterDefinition, ParameterValue, Scope,
+};
+use crate::span::Span;
+
+/// How deeply function calls can be nested. Calls that are the last operation of a function
+/// (tail calls) do not count.
+///
+/// The stack grows as needed, so the limit holds on any thread.
+pub const MAX_DEPTH: usize = 1000;
+
+/// When less stack than this is left, evaluation continues on a new stack segment.
+const STACK_RED_ZONE: usize = 256 * 1024;
+/// Size of the stack segments allocated when the stack runs low.
+const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Runs `f`, on a new stack segment if the current one is almost full.
+///
+/// Evaluation and instantiation are recursive: this is called at each level so that deep
+/// recursion, up to [`MAX_DEPTH`], does not overflow the stack.
+pub(super) fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, f)
+}
+
+/// How many times a loop can run, or a function can call itself in tail position.
+pub const MAX_ITERATIONS: usize = 1_000_000;
+
+/// Evaluation was stopped by an error, like a failed `assert`.
+///
+/// The error is in [`Evaluator::diagnostics`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Aborted;
+
+/// Evaluates expressions, collecting diagnostics and the output of `echo`.
+///
+/// Operations that are not defined, like `1 + "a"`, give `undef` with a warning, and
+/// evaluation goes on. Only errors, like a failed `assert`, stop it.
+///
+/// ```
+/// use rscad::interpreter::{Evaluator, Value};
+/// use rscad::parser::Id;
+///
+/// let document = rscad::parser::parse_document(rscad::parse("a = [1, 2] * 3;").unwrap(), vec![]);
+/// let mut evaluator = Evaluator::new();
+/// let context = evaluator.document(&document).unwrap();
+/// assert_eq!(
+///     context.variable(&Id { depth: 0, id: 0 }),
+///     Some(&Value::Vector(vec![Value::Number(3.0), Value::Number(6.0)]))
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct Evaluator {
+    pub(super) diagnostics: Diagnostics,
+    echoes: Vec<String>,
+    /// Where the expression being evaluated comes from: expressions have no span of their own.
+    pub(super) span: Span,
+    /// Number of nested function calls.
+    pub(super) depth: usize,
+    /// Top-level context of each library already evaluated.
+    libraries: Vec<(Arc<Document>, Arc<Context>)>,
+    /// Generator for `rands` without a seed, created on first use.
+    pub(super) random: Option<Mt19937>,
+}
+
+/// Result of an expression, where a function call in tail position is not evaluated yet.
+enum Tail {
+    Value(Value),
+    /// Evaluates the body of the function in the context.
+    Call(Arc<Function>, Arc<Context>),
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Problems found so far.
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Lines printed by `echo` so far, like OpenSCAD prints them: `ECHO: "a", b = 1`.
+    pub fn echoes(&self) -> &[String] {
+        &self.echoes
+    }
+
+    /// Returns the problems found, dropping the rest.
+    pub fn into_diagnostics(self) -> Diagnostics {
+        self.diagnostics
+    }
+
+    /// Creates the top-level context of a document, and assigns its variables.
+    ///
+    /// Libraries are evaluated first, once per evaluator even if several documents use them.
+    pub fn document(&mut self, document: &Document) -> Result<Arc<Context>, Aborted> {
+        let libraries = document
+            .libraries
+            .iter()
+            .map(|library| self.library(library))
+            .collect::<Result<_, _>>()?;
+        let context = Arc::new(Context::document(&document.scope, libraries));
+        self.assign_variables(&document.scope, &context)?;
+        Ok(context)
+    }
+
+    fn library(&mut self, library: &Arc<Document>) -> Result<Arc<Context>, Aborted> {
+        let evaluated = self.libraries.iter().find(|(d, _)| Arc::ptr_eq(d, library));
+        if let Some((_, context)) = evaluated {
+            return Ok(Arc::clone(context));
+        }
+        let context = self.document(library)?;
+        self.libraries
+            .push((Arc::clone(library), Arc::clone(&context)));
+        Ok(context)
+    }
+
+    /// Assigns the variables of `scope` in `context`, in order.
+    ///
+    /// Parameters and loop variables (`Expr::Extern`) are given by the caller, and skipped.
+    pub fn assign_variables(
+        &mut self,
+        scope: &Scope,
+        context: &Arc<Context>,
+    ) -> Result<(), Aborted> {
+        for (id, expr) in scope.variables.iter().enumerate() {
+            if let Expr::Extern = expr {
+                continue;
+            }
+            let span = scope.variable_spans.get(id).copied().unwrap_or_default();
+            let value = self.eval_at(expr, span, context)?;
+            context.set_variable(id, value);
+        }
+        Ok(())
+    }
+
+    /// Evaluates an expression in a context.
+    pub fn eval(&mut self, expr: &Expr, context: &Arc<Context>) -> Result<Value, Aborted> {
+        grow_stack(|| match self.eval_tail(expr, context)? {
+            Tail::Value(value) => Ok(value),
+            Tail::Call(function, context) => self.call(function, context),
+        })
+    }
+
+    /// Evaluates an expression coming from `span`, which is used for diagnostics.
+    pub fn eval_at(
+        &mut self,
+        expr: &Expr,
+        span: Span,
+        context: &Arc<Context>,
+    ) -> Result<Value, Aborted> {
+        let outer = std::mem::replace(&mut self.span, span);
+        let value = self.eval(expr, context);
+        self.span = outer;
+        value
+    }
+
+    /// Evaluates the arguments of a call.
+    pub fn arguments(
+        &mut self,
+        arguments: &[ParameterValue],
+        context: &Arc<Context>,
+    ) -> Result<Vec<Argument<Value>>, Aborted> {
+        arguments
+            .iter()
+            .map(|arg| {
+                Ok(Argument {
+                    name: arg.name.clone(),
+                    value: self.eval_at(&arg.value, arg.span, context)?,
+                    span: arg.span,
+                })
+            })
+            .collect()
+    }
+
+    /// Evaluates the body of a function, then the functions it calls in tail position, in a
+    /// loop rather than recursively.
+    fn call(&mut self, function: Arc<Function>, context: Arc<Context>) -> Result<Value, Aborted> {
+        if self.depth >= MAX_DEPTH {
+            let message = format!(
+                "recursion too deep calling `{}`, more than {} nested calls",
+                function.name, MAX_DEPTH
+            );
+            return Err(self.abort(Code::RecursionLimit, message));
+        }
+        self.depth += 1;
+        let (mut function, mut context) = (function, context);
+        let mut iterations = 0;
+        let result = loop {
+            match self.eval_tail(&function.body, &context) {
+                Ok(Tail::Call(_, _)) if iterations == MAX_ITERATIONS => {
+                    let message = format!(
+                        "`{}` called itself more than {} times",
+                        function.name, MAX_ITERATIONS
+                    );
+                    break Err(self.abort(Code::RecursionLimit, message));
+                }
+                Ok(Tail::Call(next, mut next_context)) => {
+                    iterations += 1;
+                    if let Some(next_context) = Arc::get_mut(&mut next_context) {
+                        next_context.detach_caller();
+                    }
+                    function = next;
+                    context = next_context;
+                }
+                Ok(Tail::Value(value)) => break Ok(value),
+                Err(aborted) => break Err(aborted),
+            }
+        };
+        self.depth -= 1;
+        result
+    }
+
+    /// Evaluates an expression, except for a function call in tail position.
+    fn eval_tail(&mut self, expr: &Expr, context: &Arc<Context>) -> Result<Tail, Aborted> {
+        let mut expr = expr;
+        let mut context = Arc::clone(context);
+        let value = loop {
+            break match expr {
+                Expr::Ternary {
+                    condition,
+                    if_true,
+                    if_false,
+                } => {
+                    let condition = self.eval(condition, &context)?;
+                    expr = if condition.as_bool() {
+                        if_true
+                    } else {
+                        if_false
+                    };
+                    continue;
+                }
+                Expr::Let(variables, body) => {
+                    context = self.define_locals(variables, &context)?;
+                    expr = body;
+                    continue;
+                }
+                Expr::Echo(arguments, body) => {
+                    self.echo(arguments, &context)?;
+                    expr = body;
+                    continue;
+                }
+                Expr::Assert(arguments, body) => {
+                    self.assert(arguments, &context)?;
+                    expr = body;
+                    continue;
+                }
+                Expr::Function(id, arguments) => match context.function(id) {
+                    Some((function, definition)) => {
+                        let (function, definition) = (Arc::clone(function), Arc::clone(definition));
+                        return self.prepare_call(&function, &definition, arguments, &context);
+                    }
+                    None => Value::Undef,
+                },
+                Expr::LibraryFunction(id, arguments) => {
+                    let library = context.library(id.library).map(Arc::clone);
+                    let found = library.as_ref().and_then(|library| {
+                        library.function(&Id {
+                            depth: 0,
+                            id: id.id,
+                        })
+                    });
+                    match found {
+                        Some((function, definition)) => {
+                            let (function, definition) =
+                                (Arc::clone(function), Arc::clone(definition));
+                            return self.prepare_call(&function, &definition, arguments, &context);
+                        }
+                        None => Value::Undef,
+                    }
+                }
+                Expr::Call(callee, arguments) => match self.eval(callee, &context)? {
+                    Value::Function(closure) => {
+                        let Closure {
+                            function,
+                            context: definition,
+                            ..
+                        } = &*closure;
+                        return self.prepare_call(function, definition, arguments, &context);
+                    }
+                    value => {
+                        let message = format!("cannot call a {}", value.type_name());
+                        self.warn(Code::NotAFunction, message);
+                        Value::Undef
+                    }
+                },
+                _ => self.eval_value(expr, &context)?,
+            };
+        };
+        Ok(Tail::Value(value))
+    }
+
+    /// Evaluates the expressions that `eval_tail` does not handle itself.
+    fn eval_value(&mut self, expr: &Expr, context: &Arc<Context>) -> Result<Value, Aborted> {
+        Ok(match expr {
+            Expr::Undef | Expr::Extern => Value::Undef,
+            Expr::Boolean(b) => Value::Bool(*b),
+            Expr::Number(x) => Value::Number(*x),
+            Expr::Text(text) => Value::Text(text.clone()),
+            Expr::Negative(expr) => {
+                let value = self.eval(expr, context)?;
+                ops::negate(&value).unwrap_or_else(|| self.undefined_unary("-", &value))
+            }
+            Expr::Not(expr) => Value::Bool(!self.eval(expr, context)?.as_bool()),
+            Expr::BitNot(expr) => {
+                let value = self.eval(expr, context)?;
+                ops::bit_not(&value).unwrap_or_else(|| self.undefined_unary("~", &value))
+            }
+            Expr::Variable(id) => match context.variable(id) {
+                Some(value) => value.clone(),
+                None => {
+                    let message = "variable used before it is assigned, its value is undef";
+                    self.warn(Code::UnassignedVariable, message);
+                    Value::Undef
+                }
+            },
+            Expr::SpecialVariable(name) => match context.special(name) {
+                Some(value) => value.clone(),
+                None => {
+                    let message = format!("unknown variable `{}`", name);
+                    self.warn(Code::UnknownVariable, message);
+                    Value::Undef
+                }
+            },
+            Expr::Lambda { function, text } => Value::Function(Arc::new(Closure {
+                function: Arc::clone(function),
+                text: text.clone(),
+                context: Arc::clone(context),
+            })),
+            Expr::ListComprehension(elements) => {
+                let mut values = Vec::new();
+                for element in elements {
+                    self.list_element(element, context, &mut values)?;
+                }
+                Value::Vector(values)
+            }
+            Expr::Vector(exprs) => Value::Vector(
+                exprs
+                    .iter()
+                    .map(|expr| self.eval(expr, context))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Op(a, op, b) => {
+                let a = self.eval(a, context)?;
+                let b = self.eval(b, context)?;
+                ops::binary(op, &a, &b).unwrap_or_else(|| {
+                    let message = format!(
+                        "undefined operation ({} {} {})",
+                        a.type_name(),
+                        op.symbol(),
+                        b.type_name()
+                    );
+                    self.warn(Code::UndefinedOperation, message);
+                    Value::Undef
+                })
+            }
+            Expr::Or(a, b) => {
+                Value::Bool(self.eval(a, context)?.as_bool() || self.eval(b, context)?.as_bool())
+            }
+            Expr::And(a, b) => {
+                Value::Bool(self.eval(a, context)?.as_bool() && self.eval(b, context)?.as_bool())
+            }
+            Expr::FieldAccess { parent, field } => {
+                let index = match field {
+                    Axis::X => 0.0,
+                    Axis::Y => 1.0,
+                    Axis::Z => 2.0,
+                };
+                index_value(&self.eval(parent, context)?, &Value::Number(index))
+            }
+            Expr::ArrayAccess { array, index } => {
+                let array = self.eval(array, context)?;
+                index_value(&array, &self.eval(index, context)?)
+            }
+            Expr::Range {
+                start,
+                end,
+                increment,
+            } => self.range(start, increment.as_deref(), end, context)?,
+            Expr::Builtin(function, arguments) => {
+                let arguments = self.arguments(arguments, context)?;
+                self.builtin(*function, arguments)
+            }
+            Expr::Ternary { .. }
+            | Expr::Let(..)
+            | Expr::Echo(..)
+            | Expr::Assert(..)
+            | Expr::Function(..)
+            | Expr::LibraryFunction(..)
+            | Expr::Call(..) => self.eval(expr, context)?,
+        })
+    }
+
+    /// Binds the arguments of a call, and creates the context of the function body.
+    fn prepare_call(
+        &mut self,
+        function: &Arc<Function>,
+        definition: &Arc<Context>,
+        arguments: &[ParameterValue],
+        caller: &Arc<Context>,
+    ) -> Result<Tail, Aborted> {
+        let context = self.call_context(
+            &function.name,
+            &function.parameters,
+            &function.scope,
+            arguments,
+            definition,
+            caller,
+        )?;
+        Ok(Tail::Call(Arc::clone(function), Arc::new(context)))
+    }
+
+    /// Evaluates the arguments of a call to a user function or module, and creates the context
+    /// of its body, `scope`, with the parameters bound.
+    ///
+    /// Default values are evaluated in `definition`, the context where the callee is defined.
+    pub(super) fn call_context(
+        &mut self,
+        callee: &str,
+        parameters: &[ParameterDefinition],
+        scope: &Scope,
+        arguments: &[ParameterValue],
+        definition: &Arc<Context>,
+        caller: &Arc<Context>,
+    ) -> Result<Context, Aborted> {
+        let arguments = self.arguments(arguments, caller)?;
+        let names: Vec<_> = parameters.iter().map(|p| p.name.as_str()).collect();
+        let Bound { values, specials } = bind(callee, &names, arguments, &mut self.diagnostics);
+
+        let mut arguments = Vec::with_capacity(values.len());
+        for (value, parameter) in values.into_iter().zip(parameters) {
+            let value = match (value, &parameter.default_value) {
+                (Some(value), _) => value,
+                (None, Some(default)) => self.eval(default, definition)?,
+                (None, None) => Value::Undef,
+            };
+            arguments.push(value);
+        }
+        Ok(Context::with_arguments(
+            scope,
+            arguments,
+            specials,
+            Arc::clone(definition),
+            Arc::clone(caller),
+        ))
+    }
+
+    /// Defines variables one after the other in a new context, as resolved by `let`.
+    fn define_locals(
+        &mut self,
+        values: &[Expr],
+        context: &Arc<Context>,
+    ) -> Result<Arc<Context>, Aborted> {
+        let locals = Arc::new(Context::locals(
+            values.len(),
+            Arc::clone(context),
+            Arc::clone(context),
+        ));
+        for (id, value) in values.iter().enumerate() {
+            let value = self.eval(value, &locals)?;
+            locals.set_variable(id, value);
+        }
+        Ok(locals)
+    }
+
+    pub(super) fn echo(
+        &mut self,
+        arguments: &[ParameterValue],
+        context: &Arc<Context>,
+    ) -> Result<(), Aborted> {
+        let values: Vec<_> = self
+            .arguments(arguments, context)?
+            .into_iter()
+            .map(|arg| match arg.name {
+                Some(name) => format!("{} = {}", name, arg.value),
+                None => arg.value.to_string(),
+            })
+            .collect();
+        self.echoes.push(format!("ECHO: {}", values.join(", ")));
+        Ok(())
+    }
+
+    /// Stops evaluation if the condition is false.
+    pub(super) fn assert(
+        &mut self,
+        arguments: &[ParameterValue],
+        context: &Arc<Context>,
+    ) -> Result<(), Aborted> {
+        let arguments = self.arguments(arguments, context)?;
+        let parameters = ["condition", "message"];
+        let bound = bind("assert", &parameters, arguments, &mut self.diagnostics);
+        let mut values = bound.values.into_iter();
+        let condition = values.next().flatten().unwrap_or(Value::Undef);
+        if condition.as_bool() {
+            return Ok(());
+        }
+        let message = match values.next().flatten() {
+            Some(Value::Text(message)) => format!("assertion failed: {}", message),
+            Some(message) => format!("assertion failed: {}", message),
+            None => "assertion failed".to_string(),
+        };
+        Err(self.abort(Code::AssertionFailed, message))
+    }
+
+    fn range(
+        &mut self,
+        start: &Expr,
+        step: Option<&Expr>,
+        end: &Expr,
+        context: &Arc<Context>,
+    ) -> Result<Value, Aborted> {
+        let start = self.eval(start, context)?;
+        let step = match step {
+            Some(step) => Some(self.eval(step, context)?),
+            None => None,
+        };
+        let end = self.eval(end, context)?;
+        Ok(match (start, step, end) {
+            (Value::Number(start), None, Value::Number(end)) if start > end => {
+                let message = "range start is greater than its end, the bounds are swapped";
+                self.warn(Code::InvalidRange, message);
+                Value::Range {
+                    start: end,
+                    step: 1.0,
+                    end: start,
+                }
+            }
+            (Value::Number(start), None, Value::Number(end)) => Value::Range {
+                start,
+                step: 1.0,
+                end,
+            },
+            (Value::Number(start), Some(Value::Number(step)), Value::Number(end)) => {
+                Value::Range { start, step, end }
+            }
+            _ => {
+                self.warn(Code::InvalidRange, "the bounds of a range must be numbers");
+                Value::Undef
+            }
+        })
+    }
+
+    fn list_element(
+        &mut self,
+        element: &ListElement,
+        context: &Arc<Context>,
+        values: &mut Vec<Value>,
+    ) -> Result<(), Aborted> {
+        match element {
+            ListElement::Expr(expr) => values.push(self.eval(expr, context)?),
+            ListElement::Each(element) => {
+                let mut each = Vec::new();
+                self.list_element(element, context, &mut each)?;
+                for value in each {
+                    values.extend(self.iteration_values(value));
+                }
+            }
+            ListElement::For { ranges, body } => {
+                self.for_each(ranges, body, context, &mut Vec::new(), values)?;
+            }
+            ListElement::ForC {
+                init,
+                condition,
+                update,
+                body,
+            } => {
+                let mut locals = self.define_locals(init, context)?;
+                let mut iterations = 0;
+                while self.eval(condition, &locals)?.as_bool() {
+                    if iterations == MAX_ITERATIONS {
+                        let message = format!("loop ran more than {} times", MAX_ITERATIONS);
+                        return Err(self.abort(Code::RecursionLimit, message));
+                    }
+                    iterations += 1;
+                    self.list_element(body, &locals, values)?;
+
+                    // Updates all see the values of the previous iteration.
+                    let next =
+                        Context::locals(init.len(), Arc::clone(context), Arc::clone(context));
+                    for (id, expr) in update {
+                        next.set_variable(*id, self.eval(expr, &locals)?);
+                    }
+                    for id in 0..init.len() {
+                        let previous = locals.variable(&Id { depth: 0, id }).cloned();
+                        next.set_variable(id, previous.unwrap_or(Value::Undef));
+                    }
+                    locals = Arc::new(next);
+                }
+            }
+            ListElement::If {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                if self.eval(condition, context)?.as_bool() {
+                    self.list_element(if_true, context, values)?;
+                } else if let Some(if_false) = if_false {
+                    self.list_element(if_false, context, values)?;
+                }
+            }
+            ListElement::Let { vars, body } => {
+                let locals = self.define_locals(vars, context)?;
+                self.list_element(body, &locals, values)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs nested loops over `ranges`, the first being the outermost, evaluating `body` for
+    /// each combination of values.
+    ///
+    /// `assigned` are the values of the outer loops: each range can use them.
+    fn for_each(
+        &mut self,
+        ranges: &[Expr],
+        body: &ListElement,
+        context: &Arc<Context>,
+        assigned: &mut Vec<Value>,
+        values: &mut Vec<Value>,
+    ) -> Result<(), Aborted> {
+        let locals = Context::locals(ranges.len(), Arc::clone(context), Arc::clone(context));
+        for (id, value) in assigned.iter().enumerate() {
+            locals.set_variable(id, value.clone());
+        }
+        let locals = Arc::new(locals);
+
+        let range = match ranges.get(assigned.len()) {
+            Some(range) => self.eval(range, &locals)?,
+            None => return self.list_element(body, &locals, values),
+        };
+        for value in self.iteration_values(range) {
+            assigned.push(value);
+            let result = self.for_each(ranges, body, context, assigned, values);
+            assigned.pop();
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Returns the values a loop or `each` goes over: the elements of a vector or range, the
+    /// characters of a string, nothing for `undef`, and any other value itself.
+    pub(super) fn iteration_values(&mut self, value: Value) -> Vec<Value> {
+        match value {
+            Value::Vector(values) => values,
+            Value::Range { start, step, end } => {
+                let range = RangeIter::new(start, step, end);
+                if range.len() > MAX_ITERATIONS {
+                    let message = format!(
+                        "range has {} values, more than the maximum of {}",
+                        range.len(),
+                        MAX_ITERATIONS
+                    );
+                    self.warn(Code::RangeTooLarge, message);
+                    return Vec::new();
+                }
+                range.map(Value::Number).collect()
+            }
+            Value::Text(text) => text.chars().map(|c| Value::Text(c.to_string())).collect(),
+            Value::Undef => Vec::new(),
+            value => vec![value],
+        }
+    }
+
+    fn undefined_unary(&mut self, op: &str, value: &Value) -> Value {
+        let message = format!("undefined operation ({}{})", op, value.type_name());
+        self.warn(Code::UndefinedOperation, message);
+        Value::Undef
+    }
+
+    pub(super) fn warn<M: Into<String>>(&mut self, code: Code, message: M) {
+        let diagnostic = Diagnostic::warning(code, message, self.span);
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Reports an error that stops evaluation.
+    pub(super) fn abort<M: Into<String>>(&mut self, code: Code, message: M) -> Aborted {
+        let diagnostic = Diagnostic::error(code, message, self.span);
+        self.diagnostics.push(diagnostic);
+        Aborted
+    }
+}
+
+/// Returns an element of a vector, a character of a string, or the start, step or end of a
+/// range (indices 0, 1 and 2).
+///
+/// Indices are truncated to integers. Anything else, including indices out of range, is
+/// `undef`.
+fn index_value(array: &Value, index: &Value) -> Value {
+    let index = match index {
+        // Checked before truncating, so that `-0.5` is out of range like in OpenSCAD.
+        Value::Number(index) if *index >= 0.0 => index.trunc(),
+        _ => return Value::Undef,
+    };
+    let element = match array {
+        Value::Vector(values) => values.get(index as usize).cloned(),
+        Value::Text(text) => text
+            .chars()
+            .nth(index as usize)
+            .map(|c| Value::Text(c.to_string())),
+        Value::Range { start, step, end } => [*start, *step, *end]
+            .get(index as usize)
+            .map(|&x| Value::Number(x)),
+        _ => None,
+    };
+    element.unwrap_or(Value::Undef)
+}
@@ -0,0 +1,434 @@
+//! Functions built into OpenSCAD, like `sin` or `max`.
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use super::random::Mt19937;
+use super::{bind, Argument, Evaluator, Value, MAX_ITERATIONS};
+use crate::diagnostic::Code;
+use crate::parser::BuiltinFunction;
+
+/// `sqrt(3) / 2`, the sine of 60°.
+const SQRT_3_2: f64 = 0.866_025_403_784_438_6;
+/// `1 / sqrt(3)`, the tangent of 30°.
+const FRAC_1_SQRT_3: f64 = 0.577_350_269_189_625_7;
+/// `sqrt(3)`, the tangent of 60°.
+const SQRT_3: f64 = 1.732_050_807_568_877_2;
+
+/// Returns the parameters of a builtin function. `min` and `max` have none: they take any
+/// number of positional arguments.
+fn parameters(function: BuiltinFunction) -> &'static [&'static str] {
+    use BuiltinFunction as F;
+    match function {
+        F::Atan2 => &["y", "x"],
+        F::Pow => &["base", "exponent"],
+        F::Log => &["base", "x"],
+        F::Norm => &["v"],
+        F::Cross => &["u", "v"],
+        F::Rands => &["min_value", "max_value", "value_count", "seed_value"],
+        F::Min | F::Max => &[],
+        _ => &["x"],
+    }
+}
+
+/// Returns `true` if the parameter with the given index can be left out.
+fn is_optional(function: BuiltinFunction, index: usize) -> bool {
+    matches!(
+        (function, index),
+        (BuiltinFunction::Log, 0) | (BuiltinFunction::Rands, 3)
+    )
+}
+
+impl Evaluator {
+    /// Calls a builtin function.
+    ///
+    /// Arguments are bound to the [`parameters`] of the function like for user functions.
+    /// Missing or invalid arguments give `undef`, with a warning.
+    pub(super) fn builtin(
+        &mut self,
+        function: BuiltinFunction,
+        arguments: Vec<Argument<Value>>,
+    ) -> Value {
+        use BuiltinFunction as F;
+
+        if let F::Min | F::Max = function {
+            return self.min_max(function, arguments);
+        }
+
+        let positional = arguments.iter().filter(|arg| arg.name.is_none()).count();
+        let parameters = parameters(function);
+        let mut values = bind(
+            function.name(),
+            parameters,
+            arguments,
+            &mut self.diagnostics,
+        )
+        .values;
+        // With a single positional argument, `log` takes the number, not the base.
+        if let (F::Log, 1, [base @ Some(_), x @ None]) = (function, positional, &mut values[..]) {
+            std::mem::swap(base, x);
+        }
+        if positional > parameters.len() {
+            self.wrong_count(function, positional);
+            return Value::Undef;
+        }
+        let missing = values
+            .iter()
+            .enumerate()
+            .position(|(i, value)| value.is_none() && !is_optional(function, i));
+        if let Some(i) = missing {
+            let message = format!(
+                "`{}` is missing argument `{}`",
+                function.name(),
+                parameters[i]
+            );
+            self.warn(Code::InvalidArgument, message);
+            return Value::Undef;
+        }
+        let values: Vec<Value> = values.into_iter().flatten().collect();
+
+        let unary: fn(f64) -> f64 = match function {
+            F::Min | F::Max => unreachable!(),
+            F::Norm => return self.norm(&values[0]),
+            F::Cross => return self.cross(&values[0], &values[1]),
+            F::Rands => return self.rands(&values),
+            F::Atan2 => return self.math(function, &values, |x| to_degrees(x[0].atan2(x[1]))),
+            F::Pow => return self.math(function, &values, |x| x[0].powf(x[1])),
+            F::Log if values.len() == 2 => {
+                return self.math(function, &values, |x| x[1].ln() / x[0].ln())
+            }
+            F::Log => f64::log10,
+            F::Sin => sin_degrees,
+            F::Cos => cos_degrees,
+            F::Tan => tan_degrees,
+            F::Asin => |x: f64| to_degrees(x.asin()),
+            F::Acos => |x: f64| to_degrees(x.acos()),
+            F::Atan => |x: f64| to_degrees(x.atan()),
+            F::Abs => f64::abs,
+            F::Ceil => f64::ceil,
+            F::Floor => f64::floor,
+            // Halfway cases away from 0, like C.
+            F::Round => f64::round,
+            F::Sign => |x: f64| {
+                if x < 0.0 {
+                    -1.0
+                } else if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            },
+            F::Sqrt => f64::sqrt,
+            F::Exp => f64::exp,
+            F::Ln => f64::ln,
+        };
+        self.math(function, &values, |x| unary(x[0]))
+    }
+
+    /// Calls a function of numbers.
+    fn math<F: Fn(&[f64]) -> f64>(
+        &mut self,
+        function: BuiltinFunction,
+        values: &[Value],
+        f: F,
+    ) -> Value {
+        match self.numbers(function, values) {
+            Some(numbers) => Value::Number(f(&numbers)),
+            None => Value::Undef,
+        }
+    }
+
+    /// `min` and `max`, of a vector or of their positional arguments.
+    fn min_max(&mut self, function: BuiltinFunction, arguments: Vec<Argument<Value>>) -> Value {
+        let (positional, named): (Vec<_>, Vec<_>) =
+            arguments.into_iter().partition(|arg| arg.name.is_none());
+        // Named arguments are not parameters: only report them.
+        bind(function.name(), &[], named, &mut self.diagnostics);
+        let values: Vec<Value> = positional.into_iter().map(|arg| arg.value).collect();
+
+        let values = match &values[..] {
+            [Value::Vector(vector)] if !vector.is_empty() => vector,
+            [Value::Vector(_)] => {
+                let message = format!("`{}` of an empty vector", function.name());
+                self.warn(Code::InvalidArgument, message);
+                return Value::Undef;
+            }
+            [] => {
+                self.wrong_count(function, 0);
+                return Value::Undef;
+            }
+            values => values,
+        };
+        let numbers = match self.numbers(function, values) {
+            Some(numbers) => numbers,
+            None => return Value::Undef,
+        };
+        let mut best = numbers[0];
+        for &x in &numbers[1..] {
+            let better = match function {
+                BuiltinFunction::Min => x < best,
+                _ => x > best,
+            };
+            if better {
+                best = x;
+            }
+        }
+        Value::Number(best)
+    }
+
+    /// Length of a vector.
+    fn norm(&mut self, value: &Value) -> Value {
+        let vector = match value {
+            Value::Vector(vector) => vector,
+            value => {
+                self.wrong_type(BuiltinFunction::Norm, "a vector", value);
+                return Value::Undef;
+            }
+        };
+        match self.numbers(BuiltinFunction::Norm, vector) {
+            Some(numbers) => Value::Number(numbers.iter().map(|x| x * x).sum::<f64>().sqrt()),
+            None => Value::Undef,
+        }
+    }
+
+    /// Cross product of 3D vectors, or the z coordinate of the cross product of 2D vectors.
+    fn cross(&mut self, a: &Value, b: &Value) -> Value {
+        let (a, b) = match (a, b) {
+            (Value::Vector(a), Value::Vector(b)) => (a, b),
+            (Value::Vector(_), value) | (value, _) => {
+                self.wrong_type(BuiltinFunction::Cross, "a vector", value);
+                return Value::Undef;
+            }
+        };
+        if a.len() != b.len() || !(a.len() == 2 || a.len() == 3) {
+            let message = format!(
+                "`cross` of vectors of sizes {} and {}, instead of 2 or 3",
+                a.len(),
+                b.len()
+            );
+            self.warn(Code::InvalidArgument, message);
+            return Value::Undef;
+        }
+        let (a, b) = match (
+            self.numbers(BuiltinFunction::Cross, a),
+            self.numbers(BuiltinFunction::Cross, b),
+        ) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Value::Undef,
+        };
+        if let [ax, ay] = a[..] {
+            return Value::Number(ax * b[1] - ay * b[0]);
+        }
+        Value::Vector(vec![
+            Value::Number(a[1] * b[2] - a[2] * b[1]),
+            Value::Number(a[2] * b[0] - a[0] * b[2]),
+            Value::Number(a[0] * b[1] - a[1] * b[0]),
+        ])
+    }
+
+    /// `rands(min, max, count, seed)`: random numbers between `min` and `max`.
+    ///
+    /// Numbers come from a Mersenne Twister like in OpenSCAD. With a seed, which is truncated
+    /// to an integer, the numbers are always the same.
+    fn rands(&mut self, values: &[Value]) -> Value {
+        let numbers = match self.numbers(BuiltinFunction::Rands, values) {
+            Some(numbers) => numbers,
+            None => return Value::Undef,
+        };
+
+        // Like OpenSCAD, infinite bounds are replaced with huge numbers, that can be subtracted.
+        let bound = |x: f64, infinite: f64| if x.is_finite() { x } else { infinite };
+        let mut min = bound(numbers[0], -f64::MAX / 2.0);
+        let mut max = bound(numbers[1], f64::MAX / 2.0);
+        if !numbers[0].is_finite() || !numbers[1].is_finite() {
+            let message = "bounds of `rands` must be finite numbers";
+            self.warn(Code::InvalidArgument, message);
+        }
+        if max < min {
+            std::mem::swap(&mut min, &mut max);
+        }
+
+        let count = numbers[2].abs();
+        if count.is_nan() || count > MAX_ITERATIONS as f64 {
+            let message = format!(
+                "`rands` can give up to {} numbers, not {}",
+                MAX_ITERATIONS, numbers[2]
+            );
+            self.warn(Code::InvalidArgument, message);
+            return Value::Undef;
+        }
+
+        let mut seeded;
+        let random = match numbers.get(3) {
+            Some(&seed) => {
+                seeded = Mt19937::new(seed as i64 as u32);
+                &mut seeded
+            }
+            None => self.random.get_or_insert_with(Mt19937::from_time),
+        };
+        let numbers = (0..count as usize)
+            .map(|_| Value::Number(random.uniform(min, max)))
+            .collect();
+        Value::Vector(numbers)
+    }
+
+    /// Returns the values as numbers, or warns about the first that is not one.
+    fn numbers(&mut self, function: BuiltinFunction, values: &[Value]) -> Option<Vec<f64>> {
+        let mut numbers = Vec::with_capacity(values.len());
+        for value in values {
+            match value {
+                Value::Number(x) => numbers.push(*x),
+                value => {
+                    self.wrong_type(function, "a number", value);
+                    return None;
+                }
+            }
+        }
+        Some(numbers)
+    }
+
+    fn wrong_type(&mut self, function: BuiltinFunction, expected: &str, value: &Value) {
+        let message = format!(
+            "`{}` expects {}, not {}",
+            function.name(),
+            expected,
+            value.type_name()
+        );
+        self.warn(Code::InvalidArgument, message);
+    }
+
+    fn wrong_count(&mut self, function: BuiltinFunction, count: usize) {
+        use BuiltinFunction as F;
+        let expected = match function {
+            F::Atan2 | F::Pow | F::Cross => "2 arguments",
+            F::Log => "1 or 2 arguments",
+            F::Rands => "3 or 4 arguments",
+            F::Min | F::Max => "a vector or numbers",
+            _ => "1 argument",
+        };
+        let message = format!(
+            "`{}` takes {}, but {} were given",
+            function.name(),
+            expected,
+            count
+        );
+        self.warn(Code::InvalidArgument, message);
+    }
+}
+
+/// Converts radians to degrees, rounding like OpenSCAD.
+fn to_degrees(x: f64) -> f64 {
+    x * 180.0 / PI
+}
+
+/// Converts degrees to radians, rounding like OpenSCAD.
+fn to_radians(x: f64) -> f64 {
+    x * PI / 180.0
+}
+
+/// Brings an angle in `[0, period)`.
+fn normalize(x: f64, period: f64) -> f64 {
+    if (0.0..period).contains(&x) {
+        x
+    } else if (-period..period).contains(&x) {
+        x + period
+    } else {
+        let x = x % period;
+        if x < 0.0 {
+            x + period
+        } else {
+            x
+        }
+    }
+}
+
+/// Sine of an angle in degrees, computed like OpenSCAD: exact for multiples of 30°, and
+/// symmetric.
+pub(super) fn sin_degrees(x: f64) -> f64 {
+    let mut x = normalize(x, 360.0);
+    let oppose = x >= 180.0;
+    if oppose {
+        x -= 180.0;
+    }
+    if x > 90.0 {
+        x = 180.0 - x;
+    }
+    let sine = if x < 45.0 {
+        if x == 30.0 {
+            0.5
+        } else {
+            to_radians(x).sin()
+        }
+    } else if x == 45.0 {
+        FRAC_1_SQRT_2
+    } else if x == 60.0 {
+        SQRT_3_2
+    } else {
+        // Infinite and NaN angles end up here, giving NaN.
+        to_radians(90.0 - x).cos()
+    };
+    if oppose {
+        -sine
+    } else {
+        sine
+    }
+}
+
+/// Cosine of an angle in degrees, computed like OpenSCAD: exact for multiples of 30°, and
+/// symmetric.
+pub(super) fn cos_degrees(x: f64) -> f64 {
+    let mut x = normalize(x, 360.0);
+    let mut oppose = x >= 180.0;
+    if oppose {
+        x -= 180.0;
+    }
+    if x > 90.0 {
+        x = 180.0 - x;
+        oppose = !oppose;
+    }
+    let cosine = if x > 45.0 {
+        if x == 60.0 {
+            0.5
+        } else {
+            to_radians(90.0 - x).sin()
+        }
+    } else if x == 45.0 {
+        FRAC_1_SQRT_2
+    } else if x == 30.0 {
+        SQRT_3_2
+    } else {
+        to_radians(x).cos()
+    };
+    if oppose {
+        -cosine
+    } else {
+        cosine
+    }
+}
+
+/// Tangent of an angle in degrees, computed like OpenSCAD: exact for multiples of 30° and 45°,
+/// and infinite at 90°.
+fn tan_degrees(x: f64) -> f64 {
+    let mut x = normalize(x, 180.0);
+    let oppose = x > 90.0;
+    if oppose {
+        x = 180.0 - x;
+    }
+    let tangent = if x == 0.0 {
+        0.0
+    } else if x == 30.0 {
+        FRAC_1_SQRT_3
+    } else if x == 45.0 {
+        1.0
+    } else if x == 60.0 {
+        SQRT_3
+    } else if x == 90.0 {
+        f64::INFINITY
+    } else {
+        to_radians(x).tan()
+    };
+    if oppose {
+        -tangent
+    } else {
+        tangent
+    }
+}
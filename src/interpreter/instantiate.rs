@@ -0,0 +1,438 @@
+//! Instantiates the items of resolved scopes, giving a CSG tree.
+
+use std::sync::Arc;
+
+use super::eval::grow_stack;
+use super::modules::{self, Arguments};
+use super::{bind, Aborted, Context, Evaluator, Value, MAX_DEPTH};
+use crate::ast::Modifier;
+use crate::csg::{CsgNode, Fragments};
+use crate::diagnostic::{Code, Diagnostics};
+use crate::parser::{Document, Expr, Id, Item, ItemType, Module, Scope};
+
+/// Result of interpreting a document.
+#[derive(Debug, Default)]
+pub struct Output {
+    /// The CSG tree, or `None` if interpretation was stopped by an error.
+    pub root: Option<CsgNode>,
+    /// Lines printed by `echo`.
+    pub echoes: Vec<String>,
+    /// Problems found while interpreting the document.
+    pub diagnostics: Diagnostics,
+}
+
+/// Interprets a resolved document into a CSG tree.
+///
+/// The root is a group of the top-level items, or the first item marked with `!`.
+///
+/// ```
+/// use rscad::csg::CsgNode;
+///
+/// let source = "module twice() { children(); children(); } twice() square(2);";
+/// let document = rscad::parser::parse_document(rscad::parse(source).unwrap(), vec![]);
+/// let output = rscad::interpreter::instantiate(&document);
+/// let square = CsgNode::Square { size: [2.0, 2.0], center: false };
+/// assert_eq!(
+///     output.root,
+///     Some(CsgNode::Group(vec![CsgNode::Group(vec![
+///         CsgNode::Group(vec![square.clone()]),
+///         CsgNode::Group(vec![square]),
+///     ])]))
+/// );
+/// ```
+pub fn instantiate(document: &Document) -> Output {
+    let mut interpreter = Interpreter {
+        evaluator: Evaluator::new(),
+        show_only: None,
+    };
+    let root = interpreter.document(document).ok();
+    let Interpreter { evaluator, .. } = interpreter;
+    Output {
+        root,
+        echoes: evaluator.echoes().to_vec(),
+        diagnostics: evaluator.into_diagnostics(),
+    }
+}
+
+/// Children given to the user module being instantiated, for `children()`.
+struct Children<'a> {
+    /// Scope of the children, where the module is instantiated.
+    scope: &'a Scope,
+    /// Context the module is instantiated in.
+    context: Arc<Context>,
+    /// Children of the module that context belongs to, if any.
+    outer: Option<&'a Children<'a>>,
+}
+
+struct Interpreter {
+    evaluator: Evaluator,
+    /// The first item marked with `!`, which replaces the root.
+    show_only: Option<CsgNode>,
+}
+
+impl Interpreter {
+    fn document(&mut self, document: &Document) -> Result<CsgNode, Aborted> {
+        let context = self.evaluator.document(document)?;
+        let nodes = self.items(&document.scope, &context, None)?;
+        Ok(self.show_only.take().unwrap_or(CsgNode::Group(nodes)))
+    }
+
+    /// Assigns the variables of a scope, and instantiates its items.
+    fn scope(
+        &mut self,
+        scope: &Scope,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<Vec<CsgNode>, Aborted> {
+        self.evaluator.assign_variables(scope, context)?;
+        self.items(scope, context, children)
+    }
+
+    fn items(
+        &mut self,
+        scope: &Scope,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<Vec<CsgNode>, Aborted> {
+        let mut nodes = Vec::new();
+        for item in &scope.items {
+            nodes.extend(self.item(item, context, children)?);
+        }
+        Ok(nodes)
+    }
+
+    /// Instantiates an item with its modifiers, giving nothing if it is disabled with `*`.
+    fn item(
+        &mut self,
+        item: &Item,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<Option<CsgNode>, Aborted> {
+        if item.modifiers.contains(&Modifier::Disable) {
+            return Ok(None);
+        }
+        let outer = std::mem::replace(&mut self.evaluator.span, item.span);
+        let node = grow_stack(|| self.instantiate(item, context, children));
+        self.evaluator.span = outer;
+        let mut node = match node? {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+
+        for modifier in item.modifiers.iter().rev() {
+            node = match modifier {
+                Modifier::Highlight => CsgNode::Highlight(Box::new(node)),
+                Modifier::Transparent => CsgNode::Background(Box::new(node)),
+                Modifier::ShowOnly => {
+                    if self.show_only.is_none() {
+                        self.show_only = Some(node.clone());
+                    }
+                    node
+                }
+                Modifier::Disable => node,
+            };
+        }
+        Ok(Some(node))
+    }
+
+    fn instantiate(
+        &mut self,
+        item: &Item,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<Option<CsgNode>, Aborted> {
+        let node = match &item.ty {
+            ItemType::User(id) => match context.module(id) {
+                Some((module, definition)) => {
+                    let (module, definition) = (Arc::clone(module), Arc::clone(definition));
+                    self.module(&module, &definition, item, context, children)?
+                }
+                None => return Ok(None),
+            },
+            ItemType::Library(id) => {
+                let library = context.library(id.library).map(Arc::clone);
+                let found = library.as_ref().and_then(|library| {
+                    library.module(&Id {
+                        depth: 0,
+                        id: id.id,
+                    })
+                });
+                match found {
+                    Some((module, definition)) => {
+                        let (module, definition) = (Arc::clone(module), Arc::clone(definition));
+                        self.module(&module, &definition, item, context, children)?
+                    }
+                    None => return Ok(None),
+                }
+            }
+            ItemType::Extern(name) => return self.builtin(name, item, context, children),
+            ItemType::If {
+                condition,
+                if_false,
+            } => {
+                let scope = if self.evaluator.eval(condition, context)?.as_bool() {
+                    &item.child
+                } else {
+                    &**if_false
+                };
+                let context = Arc::new(Context::new(
+                    scope,
+                    Arc::clone(context),
+                    Arc::clone(context),
+                ));
+                CsgNode::Group(self.scope(scope, &context, children)?)
+            }
+            ItemType::For(ranges) => {
+                let mut groups = Vec::new();
+                self.for_each(
+                    ranges,
+                    item,
+                    context,
+                    children,
+                    &mut Vec::new(),
+                    &mut groups,
+                )?;
+                CsgNode::Group(groups.into_iter().flatten().collect())
+            }
+            ItemType::IntersectionFor(ranges) => {
+                let mut groups = Vec::new();
+                self.for_each(
+                    ranges,
+                    item,
+                    context,
+                    children,
+                    &mut Vec::new(),
+                    &mut groups,
+                )?;
+                CsgNode::Intersection(groups.into_iter().map(CsgNode::Group).collect())
+            }
+            ItemType::Let(values) => {
+                // Each value can use the previous ones, but the body can assign them again.
+                let sequence = Arc::new(Context::new(
+                    &item.child,
+                    Arc::clone(context),
+                    Arc::clone(context),
+                ));
+                let mut assigned = Vec::new();
+                for (id, value) in values.iter().enumerate() {
+                    let value = self.evaluator.eval(value, &sequence)?;
+                    sequence.set_variable(id, value.clone());
+                    assigned.push(value);
+                }
+                let locals = Arc::new(Context::with_arguments(
+                    &item.child,
+                    assigned,
+                    Vec::new(),
+                    Arc::clone(context),
+                    Arc::clone(context),
+                ));
+                CsgNode::Group(self.scope(&item.child, &locals, children)?)
+            }
+        };
+        Ok(Some(node))
+    }
+
+    /// Instantiates a user module, with the children of the item.
+    fn module(
+        &mut self,
+        module: &Module,
+        definition: &Arc<Context>,
+        item: &Item,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<CsgNode, Aborted> {
+        if self.evaluator.depth >= MAX_DEPTH {
+            let message = format!(
+                "recursion too deep instantiating `{}`, more than {} nested modules",
+                module.name, MAX_DEPTH
+            );
+            return Err(self.evaluator.abort(Code::RecursionLimit, message));
+        }
+        let mut body = self.evaluator.call_context(
+            &module.name,
+            &module.parameters,
+            &module.body,
+            &item.params,
+            definition,
+            context,
+        )?;
+        let count = Value::Number(item.child.items.len() as f64);
+        body.set_special("$children", count);
+        let body = Arc::new(body);
+        let frame = Children {
+            scope: &item.child,
+            context: Arc::clone(context),
+            outer: children,
+        };
+
+        self.evaluator.depth += 1;
+        let nodes = self.scope(&module.body, &body, Some(&frame));
+        self.evaluator.depth -= 1;
+        Ok(CsgNode::Group(nodes?))
+    }
+
+    /// Instantiates a module built into OpenSCAD, or `children()`, `echo()` and `assert()`.
+    fn builtin(
+        &mut self,
+        name: &str,
+        item: &Item,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<Option<CsgNode>, Aborted> {
+        match name {
+            "children" => return self.children(item, context, children).map(Some),
+            "echo" | "assert" => {
+                if name == "echo" {
+                    self.evaluator.echo(&item.params, context)?;
+                } else {
+                    self.evaluator.assert(&item.params, context)?;
+                }
+                let locals = Context::new(&item.child, Arc::clone(context), Arc::clone(context));
+                let nodes = self.scope(&item.child, &Arc::new(locals), children)?;
+                return Ok(Some(CsgNode::Group(nodes)));
+            }
+            _ => (),
+        }
+        let parameters = match modules::parameters(name) {
+            Some(parameters) => parameters,
+            None => {
+                let message = format!("unknown module `{}`", name);
+                self.evaluator.warn(Code::UnknownModule, message);
+                return Ok(None);
+            }
+        };
+
+        let arguments = self.evaluator.arguments(&item.params, context)?;
+        let bound = bind(name, parameters, arguments, &mut self.evaluator.diagnostics);
+        let mut locals = Context::new(&item.child, Arc::clone(context), Arc::clone(context));
+        for (name, value) in &bound.specials {
+            locals.set_special(name, value.clone());
+        }
+        let fragments = fragments(&locals);
+        let nodes = self.scope(&item.child, &Arc::new(locals), children)?;
+
+        let arguments = Arguments {
+            name,
+            values: bound.values,
+            diagnostics: &mut self.evaluator.diagnostics,
+            span: item.span,
+            fragments,
+        };
+        Ok(Some(modules::instantiate(arguments, nodes)))
+    }
+
+    /// Instantiates the children of the current user module: all of them, or those selected by
+    /// an index, a vector of indices or a range.
+    fn children(
+        &mut self,
+        item: &Item,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+    ) -> Result<CsgNode, Aborted> {
+        let arguments = self.evaluator.arguments(&item.params, context)?;
+        let bound = bind(
+            "children",
+            &["index"],
+            arguments,
+            &mut self.evaluator.diagnostics,
+        );
+        let frame = match children {
+            Some(frame) => frame,
+            None => return Ok(CsgNode::Group(Vec::new())),
+        };
+
+        let count = frame.scope.items.len();
+        let indices: Vec<usize> = match bound.values.into_iter().next().flatten() {
+            None => (0..count).collect(),
+            Some(index) => {
+                let mut indices = Vec::new();
+                for value in self.evaluator.iteration_values(index) {
+                    match value {
+                        Value::Number(i) if i >= 0.0 && (i as usize) < count => {
+                            indices.push(i as usize)
+                        }
+                        value => {
+                            let message = format!(
+                                "invalid child index {}, there are {} children",
+                                value, count
+                            );
+                            self.evaluator.warn(Code::InvalidArgument, message);
+                        }
+                    }
+                }
+                indices
+            }
+        };
+
+        let locals = Arc::new(Context::new(
+            frame.scope,
+            Arc::clone(&frame.context),
+            Arc::clone(context),
+        ));
+        self.evaluator.assign_variables(frame.scope, &locals)?;
+        let mut nodes = Vec::new();
+        for index in indices {
+            let item = &frame.scope.items[index];
+            nodes.extend(self.item(item, &locals, frame.outer)?);
+        }
+        Ok(CsgNode::Group(nodes))
+    }
+
+    /// Runs nested loops over `ranges`, instantiating the child of the item for each combination
+    /// of values, and adding the nodes of each iteration to `groups`.
+    ///
+    /// `assigned` are the values of the outer loops: each range can use them.
+    fn for_each(
+        &mut self,
+        ranges: &[Expr],
+        item: &Item,
+        context: &Arc<Context>,
+        children: Option<&Children>,
+        assigned: &mut Vec<Value>,
+        groups: &mut Vec<Vec<CsgNode>>,
+    ) -> Result<(), Aborted> {
+        let range = match ranges.get(assigned.len()) {
+            Some(range) => range,
+            None => {
+                // The body can assign the loop variables again.
+                let locals = Arc::new(Context::with_arguments(
+                    &item.child,
+                    assigned.clone(),
+                    Vec::new(),
+                    Arc::clone(context),
+                    Arc::clone(context),
+                ));
+                groups.push(self.scope(&item.child, &locals, children)?);
+                return Ok(());
+            }
+        };
+
+        let locals = Context::new(&item.child, Arc::clone(context), Arc::clone(context));
+        for (id, value) in assigned.iter().enumerate() {
+            locals.set_variable(id, value.clone());
+        }
+        let range = self.evaluator.eval(range, &Arc::new(locals))?;
+        for value in self.evaluator.iteration_values(range) {
+            assigned.push(value);
+            let result = self.for_each(ranges, item, context, children, assigned, groups);
+            assigned.pop();
+            result?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the values of `$fn`, `$fa` and `$fs` in a context, or their default if they are not
+/// numbers.
+fn fragments(context: &Context) -> Fragments {
+    let number = |name, default| match context.special(name) {
+        Some(Value::Number(x)) => *x,
+        _ => default,
+    };
+    let default = Fragments::default();
+    Fragments {
+        number: number("$fn", default.number),
+        angle: number("$fa", default.angle),
+        size: number("$fs", default.size),
+    }
+}
@@ -0,0 +1,469 @@
+//! Modules built into OpenSCAD, like `cube` or `translate`.
+
+use super::color::parse_color;
+use super::functions::{cos_degrees, sin_degrees};
+use super::Value;
+use crate::csg::{CsgNode, Fragments, Matrix, IDENTITY};
+use crate::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::span::Span;
+
+/// Returns the parameters of a builtin module, or `None` if there is no such module.
+pub(super) fn parameters(name: &str) -> Option<&'static [&'static str]> {
+    Some(match name {
+        "cube" => &["size", "center"],
+        "sphere" => &["r", "d"],
+        "cylinder" => &["h", "r1", "r2", "center", "r", "d", "d1", "d2"],
+        "polyhedron" => &["points", "faces", "convexity", "triangles"],
+        "square" => &["size", "center"],
+        "circle" => &["r", "d"],
+        "polygon" => &["points", "paths", "convexity"],
+        "translate" | "scale" | "mirror" => &["v"],
+        "rotate" => &["a", "v"],
+        "multmatrix" => &["m"],
+        "color" => &["c", "alpha"],
+        "linear_extrude" => &["height", "center", "convexity", "twist", "slices", "scale"],
+        "rotate_extrude" => &["angle", "convexity"],
+        "import" => &["file", "layer", "convexity", "origin", "scale"],
+        "minkowski" => &["convexity"],
+        "group" | "union" | "difference" | "intersection" | "hull" => &[],
+        _ => return None,
+    })
+}
+
+/// Arguments given to a builtin module, bound to its [`parameters`].
+pub(super) struct Arguments<'a> {
+    /// Name of the module.
+    pub name: &'a str,
+    /// Value of each parameter, if given.
+    pub values: Vec<Option<Value>>,
+    /// Where invalid arguments are reported.
+    pub diagnostics: &'a mut Diagnostics,
+    /// Where the module is instantiated.
+    pub span: Span,
+    /// Values of `$fn`, `$fa` and `$fs` for the module.
+    pub fragments: Fragments,
+}
+
+/// Creates the node of a builtin module, with the nodes of its children.
+///
+/// Invalid arguments are reported, and replaced with their default value.
+pub(super) fn instantiate(mut args: Arguments, children: Vec<CsgNode>) -> CsgNode {
+    match args.name {
+        "cube" => CsgNode::Cube {
+            size: match args.numbers("size", 3) {
+                Some(size) => [size[0], size[1], size[2]],
+                None => [args.number("size", 1.0); 3],
+            },
+            center: args.bool("center"),
+        },
+        "sphere" => CsgNode::Sphere {
+            r: args.radius("r", "d", 1.0),
+            fragments: args.fragments,
+        },
+        "cylinder" => {
+            let r = args.radius("r", "d", 1.0);
+            CsgNode::Cylinder {
+                h: args.number("h", 1.0),
+                r1: args.radius("r1", "d1", r),
+                r2: args.radius("r2", "d2", r),
+                center: args.bool("center"),
+                fragments: args.fragments,
+            }
+        }
+        "polyhedron" => {
+            let faces = match args.get("faces") {
+                Some(_) => "faces",
+                None => "triangles",
+            };
+            CsgNode::Polyhedron {
+                points: args
+                    .points("points", 3)
+                    .into_iter()
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect(),
+                faces: args.indices(faces).unwrap_or_default(),
+                convexity: args.convexity(),
+            }
+        }
+        "square" => CsgNode::Square {
+            size: match args.numbers("size", 2) {
+                Some(size) => [size[0], size[1]],
+                None => [args.number("size", 1.0); 2],
+            },
+            center: args.bool("center"),
+        },
+        "circle" => CsgNode::Circle {
+            r: args.radius("r", "d", 1.0),
+            fragments: args.fragments,
+        },
+        "polygon" => CsgNode::Polygon {
+            points: args
+                .points("points", 2)
+                .into_iter()
+                .map(|p| [p[0], p[1]])
+                .collect(),
+            paths: args.indices("paths"),
+            convexity: args.convexity(),
+        },
+        "translate" => {
+            let v = args.vector("v", 0.0);
+            let mut matrix = IDENTITY;
+            for (row, x) in matrix.iter_mut().zip(&v) {
+                row[3] = *x;
+            }
+            multmatrix(matrix, children)
+        }
+        "scale" => {
+            let v = match args.get("v") {
+                Some(Value::Number(x)) => [*x; 3],
+                _ => args.vector("v", 1.0),
+            };
+            multmatrix(linear(diagonal(v)), children)
+        }
+        "mirror" => {
+            let v = match args.get("v") {
+                Some(_) => args.vector("v", 0.0),
+                None => [1.0, 0.0, 0.0],
+            };
+            multmatrix(linear(mirror(v)), children)
+        }
+        "rotate" => {
+            let rotation = match args.get("a") {
+                Some(Value::Number(a)) => match args.numbers("v", 3) {
+                    Some(v) => axis_rotation(*a, [v[0], v[1], v[2]]),
+                    None => rotation_z(*a),
+                },
+                Some(Value::Vector(_)) => {
+                    let [x, y, z] = args.vector("a", 0.0);
+                    product(product(rotation_z(z), rotation_y(y)), rotation_x(x))
+                }
+                Some(_) => {
+                    args.invalid("a", "a number or a vector");
+                    diagonal([1.0; 3])
+                }
+                None => diagonal([1.0; 3]),
+            };
+            multmatrix(linear(rotation), children)
+        }
+        "multmatrix" => {
+            let mut matrix = IDENTITY;
+            match args.get("m") {
+                Some(Value::Vector(rows)) => {
+                    for (row, values) in matrix.iter_mut().zip(rows) {
+                        if let Value::Vector(values) = values {
+                            for (x, value) in row.iter_mut().zip(values) {
+                                if let Value::Number(value) = value {
+                                    *x = *value;
+                                }
+                            }
+                        }
+                    }
+                }
+                Some(_) => args.invalid("m", "a matrix"),
+                None => (),
+            }
+            multmatrix(matrix, children)
+        }
+        "color" => {
+            // Like OpenSCAD, an invalid color gives the default color, all -1.
+            let mut rgba = [-1.0, -1.0, -1.0, 1.0];
+            match args.get("c") {
+                Some(Value::Vector(values)) => {
+                    for (i, x) in rgba.iter_mut().enumerate() {
+                        *x = match values.get(i) {
+                            Some(Value::Number(value)) => *value,
+                            _ => 1.0,
+                        };
+                    }
+                }
+                Some(Value::Text(name)) => match parse_color(name) {
+                    Some(color) => rgba = color,
+                    None => {
+                        let message = format!("unknown color `{}`", name);
+                        args.warn(message);
+                    }
+                },
+                Some(_) => args.invalid("c", "a color name or vector"),
+                None => (),
+            }
+            if let Some(alpha) = args.optional_number("alpha") {
+                rgba[3] = alpha;
+            }
+            CsgNode::Color { rgba, children }
+        }
+        "linear_extrude" => CsgNode::LinearExtrude {
+            height: args.number("height", 100.0),
+            center: args.bool("center"),
+            convexity: args.convexity(),
+            twist: args.optional_number("twist"),
+            slices: args
+                .optional_number("slices")
+                .map(|slices| slices.max(1.0) as u32),
+            scale: match args.get("scale") {
+                Some(Value::Number(x)) => [*x; 2],
+                Some(_) => {
+                    let [x, y, _] = args.vector("scale", 1.0);
+                    [x, y]
+                }
+                None => [1.0; 2],
+            },
+            fragments: args.fragments,
+            children,
+        },
+        "rotate_extrude" => CsgNode::RotateExtrude {
+            // Like OpenSCAD, angles beyond a full turn are a full turn.
+            angle: match args.number("angle", 360.0) {
+                angle if angle <= -360.0 || angle > 360.0 => 360.0,
+                angle => angle,
+            },
+            convexity: match args.optional_number("convexity") {
+                Some(convexity) => convexity.max(1.0) as u32,
+                None => 2,
+            },
+            fragments: args.fragments,
+            children,
+        },
+        "import" => CsgNode::Import {
+            file: args.text("file"),
+            layer: args.text("layer"),
+            origin: {
+                let [x, y, _] = args.vector("origin", 0.0);
+                [x, y]
+            },
+            scale: args.number("scale", 1.0),
+            convexity: args.convexity(),
+            fragments: args.fragments,
+        },
+        "minkowski" => CsgNode::Minkowski {
+            convexity: args.number("convexity", 0.0).max(0.0) as u32,
+            children,
+        },
+        "hull" => CsgNode::Hull(children),
+        "union" => CsgNode::Union(children),
+        "difference" => CsgNode::Difference(children),
+        "intersection" => CsgNode::Intersection(children),
+        _ => CsgNode::Group(children),
+    }
+}
+
+impl Arguments<'_> {
+    /// Returns the value of a parameter, unless it was not given or is `undef`.
+    fn get(&self, parameter: &str) -> Option<&Value> {
+        let index = parameters(self.name)?
+            .iter()
+            .position(|&p| p == parameter)?;
+        self.values.get(index)?.as_ref().filter(|v| !v.is_undef())
+    }
+
+    fn warn(&mut self, message: String) {
+        let diagnostic = Diagnostic::warning(Code::InvalidArgument, message, self.span);
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Reports the value of a parameter as invalid.
+    fn invalid(&mut self, parameter: &str, expected: &str) {
+        let value = self
+            .get(parameter)
+            .map_or_else(String::new, Value::to_string);
+        let message = format!(
+            "`{}` of `{}` must be {}, not {}",
+            parameter, self.name, expected, value
+        );
+        self.warn(message);
+    }
+
+    fn optional_number(&mut self, parameter: &str) -> Option<f64> {
+        match self.get(parameter)? {
+            Value::Number(x) => Some(*x),
+            _ => {
+                self.invalid(parameter, "a number");
+                None
+            }
+        }
+    }
+
+    fn number(&mut self, parameter: &str, default: f64) -> f64 {
+        self.optional_number(parameter).unwrap_or(default)
+    }
+
+    fn text(&mut self, parameter: &str) -> String {
+        match self.get(parameter) {
+            Some(Value::Text(text)) => text.clone(),
+            Some(_) => {
+                self.invalid(parameter, "a string");
+                String::new()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn bool(&self, parameter: &str) -> bool {
+        self.get(parameter).is_some_and(Value::as_bool)
+    }
+
+    /// The radius given by `r`, or by the diameter `d`, which wins.
+    fn radius(&mut self, r: &str, d: &str, default: f64) -> f64 {
+        match self.optional_number(d) {
+            Some(d) => d / 2.0,
+            None => self.number(r, default),
+        }
+    }
+
+    fn convexity(&mut self) -> u32 {
+        self.number("convexity", 1.0).max(1.0) as u32
+    }
+
+    /// Returns a vector of `len` numbers, without reporting anything if it is not one.
+    fn numbers(&self, parameter: &str, len: usize) -> Option<Vec<f64>> {
+        match self.get(parameter)? {
+            Value::Vector(values) if values.len() == len => numbers(values),
+            _ => None,
+        }
+    }
+
+    /// Returns a 3D vector, where missing coordinates are `default`.
+    fn vector(&mut self, parameter: &str, default: f64) -> [f64; 3] {
+        let mut vector = [default; 3];
+        match self.get(parameter).and_then(|v| match v {
+            Value::Vector(values) => numbers(values),
+            _ => None,
+        }) {
+            Some(values) => {
+                for (x, value) in vector.iter_mut().zip(values) {
+                    *x = value;
+                }
+            }
+            None if self.get(parameter).is_some() => self.invalid(parameter, "a vector"),
+            None => (),
+        }
+        vector
+    }
+
+    /// Returns points with `dimensions` coordinates.
+    fn points(&mut self, parameter: &str, dimensions: usize) -> Vec<Vec<f64>> {
+        let points = match self.get(parameter) {
+            Some(Value::Vector(points)) => points
+                .iter()
+                .map(|point| match point {
+                    Value::Vector(point) if point.len() >= dimensions => {
+                        numbers(&point[..dimensions])
+                    }
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            Some(_) => None,
+            None => Some(Vec::new()),
+        };
+        points.unwrap_or_else(|| {
+            let expected = format!("a vector of points with {} coordinates", dimensions);
+            self.invalid(parameter, &expected);
+            Vec::new()
+        })
+    }
+
+    /// Returns lists of point indices, like the faces of a polyhedron.
+    fn indices(&mut self, parameter: &str) -> Option<Vec<Vec<usize>>> {
+        let index = |value: &Value| match value {
+            Value::Number(x) if *x >= 0.0 => Some(*x as usize),
+            _ => None,
+        };
+        let indices = match self.get(parameter)? {
+            Value::Vector(lists) => lists
+                .iter()
+                .map(|list| match list {
+                    Value::Vector(list) => list.iter().map(index).collect(),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        if indices.is_none() {
+            self.invalid(parameter, "a vector of vectors of indices");
+        }
+        indices
+    }
+}
+
+fn numbers(values: &[Value]) -> Option<Vec<f64>> {
+    values
+        .iter()
+        .map(|value| match value {
+            Value::Number(x) => Some(*x),
+            _ => None,
+        })
+        .collect()
+}
+
+fn multmatrix(matrix: Matrix, children: Vec<CsgNode>) -> CsgNode {
+    CsgNode::Multmatrix { matrix, children }
+}
+
+type Matrix3 = [[f64; 3]; 3];
+
+/// Embeds a linear transformation in a 4x4 matrix.
+fn linear(m: Matrix3) -> Matrix {
+    let mut matrix = IDENTITY;
+    for (row, values) in matrix.iter_mut().zip(&m) {
+        row[..3].copy_from_slice(values);
+    }
+    matrix
+}
+
+fn diagonal(v: [f64; 3]) -> Matrix3 {
+    [[v[0], 0.0, 0.0], [0.0, v[1], 0.0], [0.0, 0.0, v[2]]]
+}
+
+fn product(a: Matrix3, b: Matrix3) -> Matrix3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, x) in row.iter_mut().enumerate() {
+            *x = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn rotation_x(angle: f64) -> Matrix3 {
+    let (s, c) = (sin_degrees(angle), cos_degrees(angle));
+    [[1.0, 0.0, 0.0], [0.0, c, -s], [0.0, s, c]]
+}
+
+fn rotation_y(angle: f64) -> Matrix3 {
+    let (s, c) = (sin_degrees(angle), cos_degrees(angle));
+    [[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]]
+}
+
+fn rotation_z(angle: f64) -> Matrix3 {
+    let (s, c) = (sin_degrees(angle), cos_degrees(angle));
+    [[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]]
+}
+
+/// Rotation around an axis, or around z if the axis is null.
+fn axis_rotation(angle: f64, axis: [f64; 3]) -> Matrix3 {
+    let norm = axis.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return rotation_z(angle);
+    }
+    let [x, y, z] = [axis[0] / norm, axis[1] / norm, axis[2] / norm];
+    let (s, c) = (sin_degrees(angle), cos_degrees(angle));
+    let t = 1.0 - c;
+    [
+        [c + x * x * t, x * y * t - z * s, x * z * t + y * s],
+        [y * x * t + z * s, c + y * y * t, y * z * t - x * s],
+        [z * x * t - y * s, z * y * t + x * s, c + z * z * t],
+    ]
+}
+
+/// Reflection across the plane through the origin normal to `v`, or nothing if `v` is null.
+fn mirror(v: [f64; 3]) -> Matrix3 {
+    let norm2: f64 = v.iter().map(|x| x * x).sum();
+    let mut m = diagonal([1.0; 3]);
+    if norm2 > 0.0 {
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x -= 2.0 * v[i] * v[j] / norm2;
+            }
+        }
+    }
+    m
+}
@@ -0,0 +1,199 @@
+//! Operators on values, with OpenSCAD's rules.
+//!
+//! Operations return `None` when they are not defined for the given values, like adding a
+//! number and a string: the evaluator reports them, and uses `undef` instead.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use super::Value;
+use crate::ast::Opcode;
+
+/// Applies a binary operator.
+pub fn binary(op: &Opcode, a: &Value, b: &Value) -> Option<Value> {
+    match op {
+        Opcode::Add => elementwise(a, b, |a, b| a + b),
+        Opcode::Sub => elementwise(a, b, |a, b| a - b),
+        Opcode::Mul => multiply(a, b),
+        Opcode::Div => divide(a, b),
+        Opcode::Rem => numbers(a, b, |a, b| a % b),
+        Opcode::Pow => numbers(a, b, f64::powf),
+        Opcode::Equal => Some(Value::Bool(a == b)),
+        Opcode::NotEqual => Some(Value::Bool(a != b)),
+        Opcode::Lt => compare(a, b, Ordering::is_lt),
+        Opcode::Lte => compare(a, b, Ordering::is_le),
+        Opcode::Gt => compare(a, b, Ordering::is_gt),
+        Opcode::Gte => compare(a, b, Ordering::is_ge),
+        Opcode::BitAnd => integers(a, b, |a, b| Some(a & b)),
+        Opcode::BitOr => integers(a, b, |a, b| Some(a | b)),
+        Opcode::ShiftLeft => integers(a, b, |a, b| a.checked_shl(u32::try_from(b).ok()?)),
+        Opcode::ShiftRight => integers(a, b, |a, b| a.checked_shr(u32::try_from(b).ok()?)),
+    }
+}
+
+/// Negates a number, or each element of a vector.
+pub fn negate(value: &Value) -> Option<Value> {
+    match value {
+        Value::Number(x) => Some(Value::Number(-x)),
+        Value::Vector(values) => Some(Value::Vector(
+            values
+                .iter()
+                .map(|v| negate(v).unwrap_or(Value::Undef))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Inverts the bits of an integer.
+pub fn bit_not(value: &Value) -> Option<Value> {
+    match value {
+        Value::Number(x) => Some(Value::Number(!(*x as i64) as f64)),
+        _ => None,
+    }
+}
+
+fn numbers<F: Fn(f64, f64) -> f64>(a: &Value, b: &Value, f: F) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(Value::Number(f(*a, *b))),
+        _ => None,
+    }
+}
+
+/// Bitwise operators work on the integer part of numbers.
+fn integers<F: Fn(i64, i64) -> Option<i64>>(a: &Value, b: &Value, f: F) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            Some(Value::Number(f(*a as i64, *b as i64)? as f64))
+        }
+        _ => None,
+    }
+}
+
+/// Applies `f` to numbers, or to the elements of two vectors, up to the length of the shortest.
+///
+/// Elements that cannot be combined are `undef`.
+fn elementwise<F: Fn(f64, f64) -> f64 + Copy>(a: &Value, b: &Value, f: F) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(Value::Number(f(*a, *b))),
+        (Value::Vector(a), Value::Vector(b)) => Some(Value::Vector(
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| elementwise(a, b, f).unwrap_or(Value::Undef))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Applies `f` to each element of a vector, recursively.
+fn map_numbers<F: Fn(f64) -> f64 + Copy>(value: &Value, f: F) -> Value {
+    match value {
+        Value::Number(x) => Value::Number(f(*x)),
+        Value::Vector(values) => Value::Vector(values.iter().map(|v| map_numbers(v, f)).collect()),
+        _ => Value::Undef,
+    }
+}
+
+fn multiply(a: &Value, b: &Value) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(Value::Number(a * b)),
+        (Value::Vector(_), Value::Number(x)) => Some(map_numbers(a, |v| v * x)),
+        (Value::Number(x), Value::Vector(_)) => Some(map_numbers(b, |v| x * v)),
+        (Value::Vector(a), Value::Vector(b)) => match (a.first()?, b.first()?) {
+            (Value::Number(_), Value::Number(_)) if a.len() == b.len() => {
+                Some(dot(a, b).map_or(Value::Undef, Value::Number))
+            }
+            (Value::Vector(_), Value::Number(_)) => Some(matrix_vector(&matrix(a)?, &vector(b)?)?),
+            (Value::Number(_), Value::Vector(_)) => {
+                Some(matrix_vector(&transpose(&matrix(b)?), &vector(a)?)?)
+            }
+            (Value::Vector(_), Value::Vector(_)) => {
+                let (a, b) = (matrix(a)?, transpose(&matrix(b)?));
+                let rows = a
+                    .iter()
+                    .map(|row| matrix_vector(&b, row))
+                    .collect::<Option<_>>()?;
+                Some(Value::Vector(rows))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn divide(a: &Value, b: &Value) -> Option<Value> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Some(Value::Number(a / b)),
+        (Value::Vector(_), Value::Number(x)) => Some(map_numbers(a, |v| v / x)),
+        (Value::Number(x), Value::Vector(_)) => Some(map_numbers(b, |v| x / v)),
+        _ => None,
+    }
+}
+
+/// Sum of the products of the elements, or `None` if one is not a number.
+fn dot(a: &[Value], b: &[Value]) -> Option<f64> {
+    a.iter().zip(b).try_fold(0.0, |sum, pair| match pair {
+        (Value::Number(a), Value::Number(b)) => Some(sum + a * b),
+        _ => None,
+    })
+}
+
+/// Returns the numbers of a vector, if it only has numbers.
+fn vector(values: &[Value]) -> Option<Vec<f64>> {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::Number(x) => Some(*x),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the rows of a matrix, if all of them are vectors of numbers with the same length.
+fn matrix(values: &[Value]) -> Option<Vec<Vec<f64>>> {
+    let rows: Vec<_> = values
+        .iter()
+        .map(|row| match row {
+            Value::Vector(row) => vector(row),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    if rows.iter().all(|row| row.len() == rows[0].len()) {
+        Some(rows)
+    } else {
+        None
+    }
+}
+
+fn transpose(rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let columns = rows.first().map_or(0, Vec::len);
+    (0..columns)
+        .map(|j| rows.iter().map(|row| row[j]).collect())
+        .collect()
+}
+
+/// Multiplies a matrix by a column vector, which must have as many values as it has columns.
+fn matrix_vector(rows: &[Vec<f64>], column: &[f64]) -> Option<Value> {
+    if rows.iter().any(|row| row.len() != column.len()) {
+        return None;
+    }
+    let values = rows
+        .iter()
+        .map(|row| Value::Number(row.iter().zip(column).map(|(a, b)| a * b).sum()))
+        .collect();
+    Some(Value::Vector(values))
+}
+
+/// Orders numbers, strings or booleans; other values cannot be compared.
+///
+/// Like any comparison with `nan`, `f` is not called and the result is `false`.
+fn compare(a: &Value, b: &Value, f: fn(Ordering) -> bool) -> Option<Value> {
+    let ordering = match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => return None,
+    };
+    Some(Value::Bool(ordering.is_some_and(f)))
+}
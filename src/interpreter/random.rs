@@ -0,0 +1,83 @@
+//! Random numbers for `rands`, generated like OpenSCAD with the C++ standard library.
+
+use std::fmt;
+
+const N: usize = 624;
+const M: usize = 397;
+
+/// The 32-bit Mersenne Twister, `std::mt19937` in C++.
+#[derive(Clone)]
+pub(super) struct Mt19937 {
+    state: [u32; N],
+    /// Index of the next number in `state`.
+    index: usize,
+}
+
+impl Mt19937 {
+    pub(super) fn new(seed: u32) -> Self {
+        let mut state = [0; N];
+        state[0] = seed;
+        for i in 1..N {
+            let previous = state[i - 1];
+            state[i] = 1_812_433_253u32
+                .wrapping_mul(previous ^ (previous >> 30))
+                .wrapping_add(i as u32);
+        }
+        Mt19937 { state, index: N }
+    }
+
+    /// Seeds a generator from the time and process id, for `rands` without a seed.
+    pub(super) fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| {
+                duration.subsec_nanos() ^ duration.as_secs() as u32
+            });
+        Mt19937::new(nanos ^ std::process::id())
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        if self.index == N {
+            self.twist();
+        }
+        let mut y = self.state[self.index];
+        self.index += 1;
+        y ^= y >> 11;
+        y ^= (y << 7) & 0x9d2c_5680;
+        y ^= (y << 15) & 0xefc6_0000;
+        y ^ (y >> 18)
+    }
+
+    fn twist(&mut self) {
+        for i in 0..N {
+            let y = (self.state[i] & 0x8000_0000) | (self.state[(i + 1) % N] & 0x7fff_ffff);
+            let mut next = self.state[(i + M) % N] ^ (y >> 1);
+            if y & 1 != 0 {
+                next ^= 0x9908_b0df;
+            }
+            self.state[i] = next;
+        }
+        self.index = 0;
+    }
+
+    /// Returns a number between `min` included and `max` excluded, like
+    /// `std::uniform_real_distribution<double>` in libstdc++.
+    pub(super) fn uniform(&mut self, min: f64, max: f64) -> f64 {
+        // `std::generate_canonical` takes two numbers to fill the 53 bits of a double.
+        let low = f64::from(self.next_u32());
+        let high = f64::from(self.next_u32());
+        let canonical = (low + high * 4_294_967_296.0) / 18_446_744_073_709_551_616.0;
+        // Rounding can give 1, which is excluded.
+        let canonical = canonical.min(1.0 - f64::EPSILON / 2.0);
+        canonical * (max - min) + min
+    }
+}
+
+/// Only prints where the generator is: the state is large.
+impl fmt::Debug for Mt19937 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mt19937")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,258 @@
+//! Values computed by the interpreter.
+
+use std::fmt;
+use std::sync::Arc;
+
+use super::Context;
+use crate::parser::Function;
+
+/// A value computed at runtime.
+///
+/// `Display` prints it like OpenSCAD's `echo`: strings are quoted, numbers have at most 6
+/// significant digits.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Undef,
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    Vector(Vec<Value>),
+    /// `[start : step : end]`, whose values are computed when iterating.
+    Range {
+        start: f64,
+        step: f64,
+        end: f64,
+    },
+    /// A function literal.
+    Function(Arc<Closure>),
+}
+
+impl Value {
+    /// Returns `true` if the value is considered true in a condition.
+    ///
+    /// `undef`, `false`, `0`, `""` and `[]` are false, everything else is true, including `nan`
+    /// and all ranges.
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Value::Undef => false,
+            Value::Bool(b) => *b,
+            Value::Number(x) => *x != 0.0,
+            Value::Text(text) => !text.is_empty(),
+            Value::Vector(values) => !values.is_empty(),
+            Value::Range { .. } | Value::Function(_) => true,
+        }
+    }
+
+    /// Returns `true` if the value is `undef`.
+    pub fn is_undef(&self) -> bool {
+        matches!(self, Value::Undef)
+    }
+
+    /// Returns the name of the type of the value, for diagnostics.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Undef => "undefined",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::Text(_) => "string",
+            Value::Vector(_) => "vector",
+            Value::Range { .. } => "range",
+            Value::Function(_) => "function",
+        }
+    }
+}
+
+/// Values of different types are never equal.
+///
+/// Like OpenSCAD, `nan` is not equal to itself, and functions are only equal to themselves.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Undef, Value::Undef) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Text(a), Value::Text(b)) => a == b,
+            (Value::Vector(a), Value::Vector(b)) => a == b,
+            (
+                Value::Range { start, step, end },
+                Value::Range {
+                    start: start2,
+                    step: step2,
+                    end: end2,
+                },
+            ) => start == start2 && step == step2 && end == end2,
+            (Value::Function(a), Value::Function(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Undef => write!(f, "undef"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(x) => write!(f, "{}", format_number(*x)),
+            Value::Text(text) => {
+                // Escaped like OpenSCAD's `QuotedString`.
+                write!(f, "\"")?;
+                for c in text.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Value::Vector(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.fmt(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Range { start, step, end } => write!(
+                f,
+                "[{} : {} : {}]",
+                format_number(*start),
+                format_number(*step),
+                format_number(*end)
+            ),
+            Value::Function(closure) => write!(f, "{}", closure.text),
+        }
+    }
+}
+
+/// Formats a number like C's `printf("%g")`, which OpenSCAD uses, except that `-0` is `0`.
+pub(crate) fn format_number(x: f64) -> String {
+    if x.is_nan() {
+        return "nan".to_string();
+    } else if x == 0.0 {
+        return "0".to_string();
+    } else if x.is_infinite() {
+        return if x > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    // Round to 6 significant digits first: it can change the exponent (999999.5 is 1e+06).
+    let scientific = format!("{:.5e}", x);
+    let (mantissa, exponent) = scientific.split_at(scientific.find('e').unwrap());
+    let exponent: i32 = exponent[1..].parse().unwrap();
+
+    if (-4..6).contains(&exponent) {
+        let decimals = (5 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, x)).to_string()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_zeros(mantissa), sign, exponent.abs())
+    }
+}
+
+/// Removes the trailing zeros of the decimals, and the decimal point if nothing is left.
+fn trim_zeros(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+/// A function literal, along with the context it was evaluated in.
+pub struct Closure {
+    /// The function to call.
+    pub function: Arc<Function>,
+    /// Source of the function literal, printed by `echo`.
+    pub text: String,
+    /// Where the function literal was evaluated: the parent of the function scope.
+    pub context: Arc<Context>,
+}
+
+/// Only prints the source of the function: the context can be huge, and can even contain the
+/// function itself.
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Closure")
+            .field("text", &self.text)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Iterates over the values of a range, without storing them.
+///
+/// ```
+/// use rscad::interpreter::RangeIter;
+///
+/// let values: Vec<_> = RangeIter::new(0.0, 0.5, 2.0).collect();
+/// assert_eq!(values, vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeIter {
+    start: f64,
+    step: f64,
+    /// Index of the next value.
+    index: u32,
+    /// Number of values.
+    len: u32,
+}
+
+impl RangeIter {
+    /// Iterates over `[start : step : end]`.
+    ///
+    /// Like OpenSCAD, a range going in the other direction than `step` is empty, while a range
+    /// with infinite bounds or a step of 0 has `u32::MAX` values.
+    pub fn new(start: f64, step: f64, end: f64) -> Self {
+        let is_nan = start.is_nan() || step.is_nan() || end.is_nan();
+        let len = if is_nan || (step < 0.0 && start < end) || (step >= 0.0 && start > end) {
+            0
+        } else if start == end || step.is_infinite() {
+            1
+        } else if start.is_infinite() || end.is_infinite() || step == 0.0 {
+            u32::MAX
+        } else {
+            // Like OpenSCAD, round up by one ulp so that `[0 : 0.1 : 0.3]` ends with `0.3`.
+            let steps = ((end - start) / step).abs().next_up();
+            if steps >= f64::from(u32::MAX) {
+                u32::MAX
+            } else {
+                steps as u32 + 1
+            }
+        };
+        RangeIter {
+            start,
+            step,
+            index: 0,
+            len,
+        }
+    }
+}
+
+impl Iterator for RangeIter {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        if self.index < self.len {
+            // Also avoids `inf * 0` for a range with an infinite step.
+            let value = match self.index {
+                0 => self.start,
+                i => self.start + self.step * f64::from(i),
+            };
+            self.index += 1;
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.len - self.index) as usize;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for RangeIter {}
@@ -1,13 +1,66 @@
 #[macro_use]
 extern crate lalrpop_util;
 
-lalrpop_util::lalrpop_mod!(rscad);
+lalrpop_util::lalrpop_mod!(
+    #[allow(clippy::all, clippy::pedantic)]
+    rscad
+);
 
 pub mod ast;
-mod interpreter;
-mod parser;
+pub mod csg;
+pub mod cst;
+pub mod diagnostic;
+mod error;
+pub mod format;
+pub mod fs;
+pub mod interpreter;
+mod literal;
+pub mod load;
+pub mod parser;
+pub mod span;
+pub mod visit;
+
+pub use error::{Error, FormatError, LoadError, LoadErrorKind, ParseError, ParseErrorKind};
 
 /// Parse an OpenSCAD document and outputs the AST.
-pub fn parse<'a>(content: &'a str) -> Result<Vec<ast::Statement<'a>>, impl std::error::Error + 'a> {
-    rscad::DocumentParser::new().parse(content)
+///
+/// If the document has syntax errors, all of them are returned.
+pub fn parse<'a>(content: &'a str) -> Result<Vec<ast::Statement<'a>>, Error> {
+    let (statements, errors) = parse_partial(content);
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors.into())
+    }
+}
+
+/// Parse as much as possible from an OpenSCAD document.
+///
+/// Statements that could not be parsed are replaced with `StatementKind::Error`
+/// placeholders, and the corresponding errors are returned alongside.
+///
+/// Some errors (like an invalid character) stop the parser entirely; in this case,
+/// no statement is returned.
+pub fn parse_partial(content: &str) -> (Vec<ast::Statement<'_>>, Vec<ParseError>) {
+    let mut recovered = Vec::new();
+    let result = rscad::DocumentParser::new().parse(&mut recovered, content);
+
+    let (statements, fatal) = match result {
+        Ok(statements) => (statements, None),
+        Err(e) => (Vec::new(), Some(e)),
+    };
+    let errors = error::collect_errors(recovered, fatal, content);
+
+    (statements, errors)
+}
+
+/// Resolves the names in a parsed OpenSCAD document.
+///
+/// Every variable, function and module is replaced with the [`parser::Id`] of its definition,
+/// and statements are gathered into nested [`parser::Scope`]s.
+///
+/// Names that cannot be resolved are replaced with `undef`, and reported in the returned
+/// [`diagnostic::Diagnostics`] along with other suspicious code.
+pub fn resolve(statements: Vec<ast::Statement>) -> (parser::Scope, diagnostic::Diagnostics) {
+    parser::parse(statements)
 }
@@ -0,0 +1,82 @@
+//! Decoding of literal values from their source text.
+
+use std::borrow::Cow;
+
+use crate::error::{ParseErrorKind, UserError};
+use crate::span::Span;
+
+/// Replaces escape sequences in the content of a string literal.
+///
+/// `offset` is the position of `text` in the source, used to locate errors.
+///
+/// Supported escapes are `\n`, `\t`, `\r`, `\\`, `\"`, `\xNN` (up to `\x7F`), `\uNNNN` and
+/// `\UNNNNNN`. Text without any escape is returned as-is, without copy.
+pub(crate) fn unescape(text: &str, offset: usize) -> Result<Cow<'_, str>, UserError> {
+    if !text.contains('\\') {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.char_indices();
+    while let Some((start, c)) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        let invalid = |len: usize| {
+            // Include the whole character the escape ends in, if it is not ASCII.
+            let mut end = (start + len).min(text.len());
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            UserError {
+                kind: ParseErrorKind::InvalidEscape(text[start..end].to_string()),
+                span: Span::new(offset + start, offset + end),
+            }
+        };
+
+        // The lexer guarantees a backslash is always followed by something.
+        let (_, escape) = chars.next().ok_or_else(|| invalid(1))?;
+        let hex_digits = match escape {
+            'n' => {
+                result.push('\n');
+                continue;
+            }
+            't' => {
+                result.push('\t');
+                continue;
+            }
+            'r' => {
+                result.push('\r');
+                continue;
+            }
+            '\\' | '"' => {
+                result.push(escape);
+                continue;
+            }
+            'x' => 2,
+            'u' => 4,
+            'U' => 6,
+            _ => return Err(invalid(1 + escape.len_utf8())),
+        };
+
+        let len = 2 + hex_digits;
+        let digits = text
+            .get(start + 2..start + len)
+            .filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| invalid(len))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| invalid(len))?;
+        if escape == 'x' && code > 0x7f {
+            return Err(invalid(len));
+        }
+        result.push(std::char::from_u32(code).ok_or_else(|| invalid(len))?);
+
+        // Skip the digits we just read.
+        for _ in 0..hex_digits {
+            chars.next();
+        }
+    }
+
+    Ok(Cow::Owned(result))
+}
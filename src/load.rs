@@ -0,0 +1,247 @@
+//! Loads OpenSCAD files, along with the files they include or use.
+//!
+//! `include <file>` is replaced with the content of the file, while `use <file>` only imports
+//! its modules and functions (see [`Document::libraries`]).
+//!
+//! Files are looked up relative to the file including them, then in each directory of the
+//! `OPENSCADPATH` environment variable, then in the library paths given to the [`Loader`].
+//!
+//! Files are read from the real file system by default, or from any [`FileSystem`] given to
+//! [`Loader::with_file_system`].
+//!
+//! ```no_run
+//! let mut loader = rscad::load::Loader::new();
+//! loader.add_library_path("/usr/share/openscad/libraries");
+//!
+//! let document = loader.load("model.scad").unwrap();
+//! println!("{} libraries used", document.libraries.len());
+//! ```
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ast::{Statement, StatementKind};
+use crate::error::{Error, LoadError, LoadErrorKind};
+use crate::fs::{FileSystem, StdFileSystem};
+use crate::parser::{self, Document};
+
+/// Loads and resolves files, keeping them in cache.
+///
+/// Each file is parsed once, no matter how many times it is included, and resolved once, no
+/// matter how many files use it.
+pub struct Loader {
+    /// Where files are read from.
+    fs: Arc<dyn FileSystem>,
+    /// Directories searched after the directory of the including file.
+    library_paths: Vec<PathBuf>,
+    /// Statements of each file, by canonical path.
+    parsed: HashMap<PathBuf, Vec<Statement<'static>>>,
+    /// Resolved documents, by canonical path.
+    documents: HashMap<PathBuf, Arc<Document>>,
+}
+
+impl Default for Loader {
+    fn default() -> Self {
+        Loader::with_file_system(StdFileSystem)
+    }
+}
+
+impl Loader {
+    /// Creates a new loader, reading the real file system and searching the directories in
+    /// `OPENSCADPATH`.
+    pub fn new() -> Self {
+        let library_paths = std::env::var_os("OPENSCADPATH")
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        Loader {
+            library_paths,
+            ..Loader::default()
+        }
+    }
+
+    /// Creates a new loader, reading files from the given file system.
+    ///
+    /// `OPENSCADPATH` is ignored: only the library paths added later are searched.
+    pub fn with_file_system<F: FileSystem + 'static>(fs: F) -> Self {
+        Loader {
+            fs: Arc::new(fs),
+            library_paths: Vec::new(),
+            parsed: HashMap::new(),
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Returns the file system files are read from.
+    ///
+    /// Evaluating the document should use it as well, for example to import meshes.
+    pub fn file_system(&self) -> &Arc<dyn FileSystem> {
+        &self.fs
+    }
+
+    /// Adds a directory to search for included or used files, after the previous ones.
+    pub fn add_library_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.library_paths.push(path.into());
+    }
+
+    /// Loads a file, with all the files it includes and uses.
+    ///
+    /// The result is cached: loading the same file again returns the same document.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<Arc<Document>, Error> {
+        let path = path.as_ref();
+        let path = self.fs.canonicalize(path).map_err(|_| LoadError {
+            kind: LoadErrorKind::NotFound(path.display().to_string()),
+            chain: Vec::new(),
+        })?;
+        Ok(self.load_document(path, &mut Vec::new())?)
+    }
+
+    /// Loads and resolves a file, and the libraries it uses.
+    ///
+    /// `chain` lists the files being loaded, to detect include cycles.
+    fn load_document(
+        &mut self,
+        path: PathBuf,
+        chain: &mut Vec<PathBuf>,
+    ) -> Result<Arc<Document>, LoadError> {
+        if let Some(document) = self.documents.get(&path) {
+            return Ok(Arc::clone(document));
+        }
+
+        chain.push(path.clone());
+        let mut uses = Vec::new();
+        let statements = self.expand(&path, chain, &mut uses)?;
+        if !uses.is_empty() {
+            // Libraries using this file back get it resolved without its own libraries, which
+            // ends the recursion.
+            let placeholder = parser::parse_document(statements.clone(), Vec::new());
+            self.documents.insert(path.clone(), Arc::new(placeholder));
+        }
+        let libraries = uses
+            .into_iter()
+            .map(|library| self.load_document(library, chain))
+            .collect::<Result<_, _>>()
+            .inspect_err(|_| {
+                self.documents.remove(&path);
+            })?;
+        chain.pop();
+
+        let document = Arc::new(parser::parse_document(statements, libraries));
+        self.documents.insert(path, Arc::clone(&document));
+        Ok(document)
+    }
+
+    /// Returns the statements of a file, where `include <...>` are replaced with the statements
+    /// of the included files.
+    ///
+    /// The files used by any of them are added to `uses`.
+    fn expand(
+        &mut self,
+        path: &Path,
+        chain: &mut Vec<PathBuf>,
+        uses: &mut Vec<PathBuf>,
+    ) -> Result<Vec<Statement<'static>>, LoadError> {
+        let statements = self.parse_file(path, chain)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        statements
+            .into_iter()
+            .map(|statement| self.expand_statement(statement, dir, chain, uses))
+            .collect()
+    }
+
+    fn expand_statement(
+        &mut self,
+        statement: Statement<'static>,
+        dir: &Path,
+        chain: &mut Vec<PathBuf>,
+        uses: &mut Vec<PathBuf>,
+    ) -> Result<Statement<'static>, LoadError> {
+        let span = statement.span;
+        let kind = match statement.kind {
+            StatementKind::Include(name) => {
+                let path = self.find(&name, dir, chain)?;
+                check_cycle(&path, chain)?;
+
+                chain.push(path.clone());
+                let statements = self.expand(&path, chain, uses)?;
+                chain.pop();
+                StatementKind::StatementList(statements)
+            }
+            StatementKind::Use(name) => {
+                let path = self.find(&name, dir, chain)?;
+                if !uses.contains(&path) {
+                    uses.push(path);
+                }
+                StatementKind::Use(name)
+            }
+            // Includes can only appear in blocks and module bodies.
+            StatementKind::StatementList(statements) => StatementKind::StatementList(
+                statements
+                    .into_iter()
+                    .map(|statement| self.expand_statement(statement, dir, chain, uses))
+                    .collect::<Result<_, _>>()?,
+            ),
+            StatementKind::ModuleDefinition { name, args, body } => {
+                let body = Box::new(self.expand_statement(*body, dir, chain, uses)?);
+                StatementKind::ModuleDefinition { name, args, body }
+            }
+            kind => kind,
+        };
+        Ok(Statement { kind, span })
+    }
+
+    /// Returns the (cached) statements of a file, without expanding includes.
+    fn parse_file(
+        &mut self,
+        path: &Path,
+        chain: &[PathBuf],
+    ) -> Result<Vec<Statement<'static>>, LoadError> {
+        if let Some(statements) = self.parsed.get(path) {
+            return Ok(statements.clone());
+        }
+
+        let error = |kind| LoadError {
+            kind,
+            chain: chain.to_vec(),
+        };
+        let source = self
+            .fs
+            .read_to_string(path)
+            .map_err(|e| error(LoadErrorKind::Read(path.to_path_buf(), e.to_string())))?;
+        let statements: Vec<_> = match crate::parse(&source) {
+            Ok(statements) => statements.into_iter().map(Statement::into_owned).collect(),
+            Err(Error::Parse(errors)) => {
+                return Err(error(LoadErrorKind::Parse(path.to_path_buf(), errors)))
+            }
+            Err(Error::Load(e)) => return Err(e),
+            Err(Error::Format(_)) => unreachable!("parsing does not format"),
+        };
+
+        self.parsed.insert(path.to_path_buf(), statements.clone());
+        Ok(statements)
+    }
+
+    /// Finds an included or used file, and returns its canonical path.
+    fn find(&self, name: &str, dir: &Path, chain: &[PathBuf]) -> Result<PathBuf, LoadError> {
+        std::iter::once(dir)
+            .chain(self.library_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(name))
+            .find(|path| self.fs.is_file(path))
+            .and_then(|path| self.fs.canonicalize(&path).ok())
+            .ok_or_else(|| LoadError {
+                kind: LoadErrorKind::NotFound(name.to_string()),
+                chain: chain.to_vec(),
+            })
+    }
+}
+
+fn check_cycle(path: &Path, chain: &[PathBuf]) -> Result<(), LoadError> {
+    if chain.iter().any(|p| p == path) {
+        Err(LoadError {
+            kind: LoadErrorKind::Cycle(path.to_path_buf()),
+            chain: chain.to_vec(),
+        })
+    } else {
+        Ok(())
+    }
+}
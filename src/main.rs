@@ -0,0 +1,133 @@
+use std::io::Read;
+use std::process::exit;
+
+use rscad::format::{BraceStyle, FormatOptions};
+
+const USAGE: &str = "\
+Usage: rscad fmt [OPTIONS] [FILE]...
+
+Formats OpenSCAD files. Reads from the standard input if no file is given.
+
+Options:
+    --indent <N>            Spaces per indentation level (default: 4)
+    --max-width <N>         Maximum line length (default: 100)
+    --brace-style <STYLE>   `same-line` (default) or `next-line`
+    --trailing-commas       Add trailing commas to vectors split on multiple lines
+    -w, --write             Overwrite the files instead of printing the result
+    --check                 Exit with an error if a file is not formatted";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    exit(2);
+}
+
+fn number(value: Option<String>, option: &str) -> usize {
+    value
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| usage_error(&format!("`{}` expects a number", option)))
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("fmt") => (),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return;
+        }
+        Some(command) => usage_error(&format!("unknown command `{}`", command)),
+        None => usage_error("missing command"),
+    }
+
+    let mut options = FormatOptions::default();
+    let mut write = false;
+    let mut check = false;
+    let mut files = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--indent" => options.indent_width = number(args.next(), &arg),
+            "--max-width" => options.max_line_length = number(args.next(), &arg),
+            "--brace-style" => {
+                options.brace_style = match args.next().as_deref() {
+                    Some("same-line") => BraceStyle::SameLine,
+                    Some("next-line") => BraceStyle::NextLine,
+                    _ => usage_error("`--brace-style` expects `same-line` or `next-line`"),
+                }
+            }
+            "--trailing-commas" => options.trailing_commas = true,
+            "-w" | "--write" => write = true,
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option `{}`", arg)),
+            _ => files.push(arg),
+        }
+    }
+
+    if files.is_empty() {
+        if write {
+            usage_error("`--write` needs files");
+        }
+        let mut input = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut input) {
+            eprintln!("error: cannot read the standard input: {}", err);
+            exit(1);
+        }
+        match rscad::format::format_source(&input, &options) {
+            Ok(output) if check => {
+                if output != input {
+                    exit(1);
+                }
+            }
+            Ok(output) => print!("{}", output),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut failed = false;
+    for file in &files {
+        let input = match std::fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("error: cannot read {}: {}", file, err);
+                failed = true;
+                continue;
+            }
+        };
+        let output = match rscad::format::format_source(&input, &options) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                failed = true;
+                continue;
+            }
+        };
+
+        if check {
+            if output != input {
+                println!("{} is not formatted", file);
+                failed = true;
+            }
+        } else if write {
+            if output != input {
+                if let Err(err) = std::fs::write(file, output) {
+                    eprintln!("error: cannot write {}: {}", file, err);
+                    failed = true;
+                }
+            }
+        } else {
+            print!("{}", output);
+        }
+    }
+
+    if failed {
+        exit(1);
+    }
+}
@@ -1,25 +1,59 @@
+//! Resolved AST, where every name is replaced with the [`Id`] of what it refers to.
+//!
+//! Each module body, function, loop or block creates a new [`Scope`], listing the variables,
+//! functions and modules it declares along with the items it instantiates. Names are
+//! looked up in the current scope first, then in each parent scope in turn.
+//!
+//! Use [`crate::resolve`] to build a [`Scope`] from parsed statements. Names that cannot be
+//! resolved are reported as [`Diagnostics`].
+
 use crate::ast;
+use crate::diagnostic::{Code, Diagnostic, Diagnostics};
+use crate::span::Span;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Id {
     /// How deeply scoped is the variable?
     /// 0: current scope
     /// 1: parent scope
-    depth: usize,
+    pub depth: usize,
 
     /// In the given scope, what is the ID?
-    id: usize,
+    pub id: usize,
 }
 
 pub type VariableId = Id;
 pub type FunctionId = Id;
 pub type ModuleId = Id;
 
-#[derive(Clone, Debug)]
+/// Refers to a module or function of a library imported with `use <...>`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LibraryId {
+    /// Index of the library in `Document::libraries`.
+    pub library: usize,
+    /// Index of the module or function in the top-level scope of the library.
+    pub id: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Operator {}
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Axis {
     X,
     Y,
@@ -37,26 +71,136 @@ impl Axis {
     }
 }
 
+/// A function built into OpenSCAD.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum BuiltinFunction {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Abs,
+    Ceil,
+    Floor,
+    Round,
+    Sign,
+    Sqrt,
+    Pow,
+    Exp,
+    Ln,
+    Log,
+    Min,
+    Max,
+    Norm,
+    Cross,
+    Rands,
+}
+
+impl BuiltinFunction {
+    /// All the builtin functions.
+    pub const ALL: [BuiltinFunction; 22] = [
+        BuiltinFunction::Sin,
+        BuiltinFunction::Cos,
+        BuiltinFunction::Tan,
+        BuiltinFunction::Asin,
+        BuiltinFunction::Acos,
+        BuiltinFunction::Atan,
+        BuiltinFunction::Atan2,
+        BuiltinFunction::Abs,
+        BuiltinFunction::Ceil,
+        BuiltinFunction::Floor,
+        BuiltinFunction::Round,
+        BuiltinFunction::Sign,
+        BuiltinFunction::Sqrt,
+        BuiltinFunction::Pow,
+        BuiltinFunction::Exp,
+        BuiltinFunction::Ln,
+        BuiltinFunction::Log,
+        BuiltinFunction::Min,
+        BuiltinFunction::Max,
+        BuiltinFunction::Norm,
+        BuiltinFunction::Cross,
+        BuiltinFunction::Rands,
+    ];
+
+    /// Returns the builtin function with the given name, if any.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    /// Name of the function in OpenSCAD.
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinFunction::Sin => "sin",
+            BuiltinFunction::Cos => "cos",
+            BuiltinFunction::Tan => "tan",
+            BuiltinFunction::Asin => "asin",
+            BuiltinFunction::Acos => "acos",
+            BuiltinFunction::Atan => "atan",
+            BuiltinFunction::Atan2 => "atan2",
+            BuiltinFunction::Abs => "abs",
+            BuiltinFunction::Ceil => "ceil",
+            BuiltinFunction::Floor => "floor",
+            BuiltinFunction::Round => "round",
+            BuiltinFunction::Sign => "sign",
+            BuiltinFunction::Sqrt => "sqrt",
+            BuiltinFunction::Pow => "pow",
+            BuiltinFunction::Exp => "exp",
+            BuiltinFunction::Ln => "ln",
+            BuiltinFunction::Log => "log",
+            BuiltinFunction::Min => "min",
+            BuiltinFunction::Max => "max",
+            BuiltinFunction::Norm => "norm",
+            BuiltinFunction::Cross => "cross",
+            BuiltinFunction::Rands => "rands",
+        }
+    }
+}
+
 /// Represent a parsed expression.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Expr {
     Undef,
     Extern,
     Boolean(bool),
-    Number(f32),
+    Number(f64),
     Text(String),
     Negative(Box<Expr>),
     Not(Box<Expr>),
+    BitNot(Box<Expr>),
     Variable(VariableId),
+    /// A special variable like `$fn`, looked up through the call stack at evaluation time.
+    SpecialVariable(String),
     Echo(Vec<ParameterValue>, Box<Expr>),
     Assert(Vec<ParameterValue>, Box<Expr>),
-    Let(Vec<ParameterValue>, Box<Expr>),
+    /// Defines variables, one after the other, in a new scope for the expression.
+    Let(Vec<Expr>, Box<Expr>),
     Function(FunctionId, Vec<ParameterValue>),
-    ListComprehension {
-        lets: Vec<Expr>,
-        variables: Vec<ParameterValue>,
-        body: Box<Expr>,
+    /// Calls a function from a library.
+    LibraryFunction(LibraryId, Vec<ParameterValue>),
+    /// Calls a function built into OpenSCAD.
+    Builtin(BuiltinFunction, Vec<ParameterValue>),
+    /// Calls a function value, like a variable or a function literal.
+    Call(Box<Expr>, Vec<ParameterValue>),
+    /// A function literal.
+    Lambda {
+        function: Arc<Function>,
+        /// Text of the literal, printed when the function is echoed: `function(x) (x + 1)`.
+        text: String,
     },
+    ListComprehension(Vec<ListElement>),
     Vector(Vec<Expr>),
     Op(Box<Expr>, ast::Opcode, Box<Expr>),
     Or(Box<Expr>, Box<Expr>),
@@ -81,57 +225,228 @@ pub enum Expr {
     },
 }
 
-#[derive(Clone, Debug)]
+/// An element of a list comprehension.
+///
+/// Loops and lets introduce a new scope, where their variables come first.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ListElement {
+    Expr(Expr),
+    Each(Box<ListElement>),
+    /// Nested loops over each range, in order.
+    For {
+        ranges: Vec<Expr>,
+        body: Box<ListElement>,
+    },
+    ForC {
+        init: Vec<Expr>,
+        condition: Expr,
+        /// Index of the variable in the loop scope, and its new value.
+        update: Vec<(usize, Expr)>,
+        body: Box<ListElement>,
+    },
+    If {
+        condition: Expr,
+        if_true: Box<ListElement>,
+        if_false: Option<Box<ListElement>>,
+    },
+    Let {
+        vars: Vec<Expr>,
+        body: Box<ListElement>,
+    },
+}
+
+/// A function, either defined with `function f(x) = ...;` or as a literal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Function {
-    default_values: Vec<Option<Expr>>,
+    /// Name of the function, or `function` for function literals.
+    pub name: String,
 
-    // only used for variables
-    scope: Scope,
+    /// Parameters of the function, in order.
+    pub parameters: Vec<ParameterDefinition>,
 
-    body: Expr,
+    /// Scope of the function body. Its first variables are the parameters.
+    pub scope: Scope,
+
+    /// Value of the function.
+    pub body: Expr,
 }
 
-#[derive(Clone, Debug)]
+/// A module defined with `module m(x) { ... }`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Module {
-    default_values: Vec<Option<Expr>>,
-    body: Scope,
+    /// Name of the module.
+    pub name: String,
+    /// Parameters of the module, in order.
+    pub parameters: Vec<ParameterDefinition>,
+    /// Scope of the module body. Its first variables are the parameters.
+    pub body: Scope,
 }
 
+/// A parameter given to a function or module, possibly named.
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterValue {
-    name: Option<String>,
-    value: Expr,
+    /// Optional name for this parameter
+    pub name: Option<String>,
+    /// Value given to this parameter
+    pub value: Expr,
+    /// Where the value comes from.
+    pub span: Span,
 }
 
-#[derive(Clone, Debug)]
+/// Spans are ignored: two parameters are equal if they have the same name and value.
+impl PartialEq for ParameterValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.value == other.value
+    }
+}
+
+/// A parameter of a module or function, possibly with a default value.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ParameterDefinition {
-    default_value: Option<Expr>,
+    /// Name of the parameter.
+    pub name: String,
+    /// Default value, resolved in the scope of the definition.
+    pub default_value: Option<Expr>,
 }
 
-#[derive(Clone, Debug)]
-enum ItemType {
+/// What an item does.
+///
+/// Loops and lets introduce a new scope (the item child), where their variables come first.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ItemType {
+    /// Instantiates a module defined in the document.
     User(ModuleId),
+    /// Instantiates a module from a library.
+    Library(LibraryId),
+    /// Instantiates a module not defined in the document, like `cube` or `translate`.
     Extern(String),
+    /// Instantiates the child if the condition is true, or `if_false` otherwise.
+    If {
+        condition: Expr,
+        if_false: Box<Scope>,
+    },
+    /// Instantiates the child for each value of the ranges (nested loops if more than one).
+    For(Vec<Expr>),
+    /// Like `For`, but intersects the results instead of grouping them.
+    IntersectionFor(Vec<Expr>),
+    /// Defines variables, one after the other, for the child.
+    Let(Vec<Expr>),
 }
 
+/// An item instantiated in a scope: `translate([1, 0, 0]) cube();`, `for (i = [0:3]) ...`
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Item {
-    ty: ItemType,
-    params: Vec<Expr>,
-    child: Scope,
+    /// What this item does.
+    pub ty: ItemType,
+    /// Modifiers applied to this item, outermost first.
+    pub modifiers: Vec<ast::Modifier>,
+    /// Parameters given to the module, resolved in the current scope.
+    ///
+    /// Named parameters starting with `$` set special variables for the module and its children.
+    pub params: Vec<ParameterValue>,
+    /// Children of this item, in their own scope.
+    pub child: Scope,
+    /// Where the item is instantiated.
+    pub span: Span,
 }
 
+/// Spans are ignored: two items are equal if they instantiate the same things.
+impl PartialEq for Item {
+    fn eq(&self, other: &Self) -> bool {
+        self.ty == other.ty
+            && self.modifiers == other.modifiers
+            && self.params == other.params
+            && self.child == other.child
+    }
+}
+
+/// Everything declared and instantiated in a scope.
+///
+/// Variables, functions and modules are referred to by their index in this scope.
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Scope {
-    variables: Vec<Expr>,
-    functions: Vec<Function>,
-    modules: Vec<Module>,
-    items: Vec<Item>,
+    /// Value of each variable, in order of first assignment.
+    ///
+    /// Parameters and loop variables are `Expr::Extern`, their value is given by the caller,
+    /// unless they are assigned again in the scope.
+    pub variables: Vec<Expr>,
+    /// Where the value of each variable comes from, for diagnostics.
+    ///
+    /// Empty spans for parameters, which have no location.
+    pub variable_spans: Vec<Span>,
+    /// Functions defined in this scope.
+    pub functions: Vec<Arc<Function>>,
+    /// Modules defined in this scope.
+    pub modules: Vec<Arc<Module>>,
+    /// Items instantiated in this scope, in order.
+    pub items: Vec<Item>,
+    /// Special variables (like `$fn`) assigned in this scope, with their index in `variables`.
+    ///
+    /// They are not visible lexically: they are set for everything called from this scope.
+    pub specials: Vec<(String, usize)>,
+    /// Files included with `include <...>`.
+    ///
+    /// Always empty when loaded with [`crate::load::Loader`], which replaces them with the
+    /// content of the files.
+    pub includes: Vec<String>,
+    /// Files used with `use <...>`, as written in the source.
+    ///
+    /// See [`Document::libraries`] for the loaded files.
+    pub uses: Vec<String>,
+}
+
+/// Spans are ignored: two scopes are equal if they declare and instantiate the same things.
+impl PartialEq for Scope {
+    fn eq(&self, other: &Self) -> bool {
+        self.variables == other.variables
+            && self.functions == other.functions
+            && self.modules == other.modules
+            && self.items == other.items
+            && self.specials == other.specials
+            && self.includes == other.includes
+            && self.uses == other.uses
+    }
+}
+
+/// A resolved file, with the libraries it imports with `use <...>`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Document {
+    /// Top-level scope of the file.
+    pub scope: Scope,
+    /// Files imported with `use <...>`, in order.
+    ///
+    /// Their modules and functions can be used anywhere in the file, unless it defines its own
+    /// with the same name. The first library defining a name wins.
+    pub libraries: Vec<Arc<Document>>,
+    /// Index of each top-level module, by name.
+    pub modules: HashMap<String, usize>,
+    /// Index of each top-level function, by name.
+    pub functions: HashMap<String, usize>,
+    /// Problems found while resolving the file, but not its libraries.
+    pub diagnostics: Diagnostics,
 }
 
 fn parse_parameter_value(parameter: ast::ParameterValue, context: &Context) -> ParameterValue {
     ParameterValue {
-        name: parameter.name.map(str::to_string),
+        name: parameter.name.as_deref().map(str::to_string),
+        span: parameter.value.span,
         value: parse_expr(parameter.value, context),
     }
 }
@@ -139,22 +454,25 @@ fn parse_parameter_value(parameter: ast::ParameterValue, context: &Context) -> P
 fn parse_parameter_definitions(
     parameters: Vec<ast::ParameterDefinition>,
     context: &mut Context,
-) -> Vec<Option<Expr>> {
+) -> Vec<ParameterDefinition> {
     parameters
         .into_iter()
         .map(|param| {
             // Insert an entry in the scope variable (will be filled later)
-            context.add_variable(param.name, |_| Expr::Extern);
+            context.add_variable(&param.name, Span::default(), |_| Expr::Extern);
 
             // And save the default value in the parent context
-            param
-                .default_value
-                .map(|v| parse_expr(v, context.parent.unwrap()))
+            ParameterDefinition {
+                default_value: param
+                    .default_value
+                    .map(|v| parse_expr(v, context.parent.unwrap())),
+                name: param.name.into_owned(),
+            }
         })
         .collect()
 }
 
-fn parse_parameter_values<'a>(
+fn parse_parameter_values(
     parameters: Vec<ast::ParameterValue>,
     context: &Context,
 ) -> Vec<ParameterValue> {
@@ -169,61 +487,105 @@ fn parse_expr<'a>(expr: ast::Expr, context: &Context<'a>) -> Expr {
     let parse_expr = |expr: ast::Expr| parse_expr(expr, context);
     let parse_boxed_expr = |expr: Box<ast::Expr>| Box::new(parse_expr(*expr));
 
-    match expr {
-        ast::Expr::Undef => Expr::Undef,
-        ast::Expr::Boolean(b) => Expr::Boolean(b),
-        ast::Expr::Number(n) => Expr::Number(n),
-        ast::Expr::Text(text) => Expr::Text(text.to_string()),
-        ast::Expr::Vector(values) => Expr::Vector(values.into_iter().map(parse_expr).collect()),
-        ast::Expr::Variable(var) => {
+    let span = expr.span;
+    let text = match expr.kind {
+        ast::ExprKind::Lambda { .. } => crate::format::echo_expr(&expr),
+        _ => String::new(),
+    };
+    match expr.kind {
+        ast::ExprKind::Undef => Expr::Undef,
+        ast::ExprKind::Boolean(b) => Expr::Boolean(b),
+        ast::ExprKind::Number(n) => Expr::Number(n),
+        ast::ExprKind::Text(text) => Expr::Text(text.into_owned()),
+        ast::ExprKind::Vector(values) => Expr::Vector(values.into_iter().map(parse_expr).collect()),
+        ast::ExprKind::Variable(var) if is_special(&var) => Expr::SpecialVariable(var.into_owned()),
+        ast::ExprKind::Variable(var) => {
             context
-                .find_var(var)
+                .find_var(&var)
                 .map(Expr::Variable)
                 .unwrap_or_else(|| {
-                    eprintln!("Could not find variable `{}`", var);
+                    let message = format!("unknown variable `{}`", var);
+                    context.report(Diagnostic::warning(Code::UnknownVariable, message, span));
                     Expr::Undef
                 })
         }
-        ast::Expr::Function(ast::FunctionCall { name, parameters }) => context
-            .find_function(name)
-            .map(|fid| Expr::Function(fid, parse_parameter_values(parameters, context)))
-            .unwrap_or_else(|| {
-                log::warn!("Could not find function `{}`", name);
-                Expr::Undef
-            }),
-        ast::Expr::Negative(expr) => Expr::Negative(parse_boxed_expr(expr)),
-        ast::Expr::Not(expr) => Expr::Not(parse_boxed_expr(expr)),
-        ast::Expr::Echo(params, expr) => Expr::Echo(
+        ast::ExprKind::Function(ast::FunctionCall {
+            function,
+            parameters,
+        }) => match function.kind {
+            // Like OpenSCAD, each scope is searched for a function then a variable holding one,
+            // from the innermost outward. Libraries and builtins come last.
+            ast::ExprKind::Variable(name) => {
+                let span = function.span;
+                if let Some(callee) = context.find_callee(&name) {
+                    let parameters = parse_parameter_values(parameters, context);
+                    match callee {
+                        Callee::Function(fid) => Expr::Function(fid, parameters),
+                        Callee::Variable(vid) => {
+                            Expr::Call(Box::new(Expr::Variable(vid)), parameters)
+                        }
+                    }
+                } else if is_special(&name) {
+                    Expr::Call(
+                        Box::new(Expr::SpecialVariable(name.into_owned())),
+                        parse_parameter_values(parameters, context),
+                    )
+                } else if let Some(fid) = context.find_library_function(&name) {
+                    Expr::LibraryFunction(fid, parse_parameter_values(parameters, context))
+                } else if let Some(builtin) = BuiltinFunction::from_name(&name) {
+                    Expr::Builtin(builtin, parse_parameter_values(parameters, context))
+                } else {
+                    let message = format!("unknown function `{}`", name);
+                    context.report(Diagnostic::warning(Code::UnknownFunction, message, span));
+                    Expr::Undef
+                }
+            }
+            _ => Expr::Call(
+                parse_boxed_expr(function),
+                parse_parameter_values(parameters, context),
+            ),
+        },
+        ast::ExprKind::Lambda { args, body } => Expr::Lambda {
+            function: Arc::new(parse_function("function", args, *body, context)),
+            text,
+        },
+        ast::ExprKind::Negative(expr) => Expr::Negative(parse_boxed_expr(expr)),
+        ast::ExprKind::Not(expr) => Expr::Not(parse_boxed_expr(expr)),
+        ast::ExprKind::BitNot(expr) => Expr::BitNot(parse_boxed_expr(expr)),
+        ast::ExprKind::Echo(params, expr) => Expr::Echo(
             parse_parameter_values(params, context),
             parse_boxed_expr(expr),
         ),
-        ast::Expr::Assert(params, expr) => Expr::Assert(
+        ast::ExprKind::Assert(params, expr) => Expr::Assert(
             parse_parameter_values(params, context),
             parse_boxed_expr(expr),
         ),
-        ast::Expr::Let(lets, expr) => Expr::Let(
-            lets.into_iter()
-                .flat_map(|params| parse_parameter_values(params.vars, context))
-                .collect(),
-            parse_boxed_expr(expr),
-        ),
-        ast::Expr::Or(a, b) => Expr::Or(parse_boxed_expr(a), parse_boxed_expr(b)),
-        ast::Expr::And(a, b) => Expr::And(parse_boxed_expr(a), parse_boxed_expr(b)),
-        ast::Expr::Op(a, op, b) => Expr::Op(parse_boxed_expr(a), op, parse_boxed_expr(b)),
-        ast::Expr::FieldAccess { parent, field } => Axis::from_str(field)
+        ast::ExprKind::Let(lets, expr) => {
+            let mut context = Context::new(context);
+            let vars = lets.into_iter().flat_map(|l| l.vars).collect();
+            let vars = parse_sequential_variables(vars, &mut context);
+            Expr::Let(vars, Box::new(self::parse_expr(*expr, &context)))
+        }
+        ast::ExprKind::Or(a, b) => Expr::Or(parse_boxed_expr(a), parse_boxed_expr(b)),
+        ast::ExprKind::And(a, b) => Expr::And(parse_boxed_expr(a), parse_boxed_expr(b)),
+        ast::ExprKind::Op(a, op, b) => Expr::Op(parse_boxed_expr(a), op, parse_boxed_expr(b)),
+        ast::ExprKind::FieldAccess { parent, field } => Axis::from_str(&field)
             .map(|field| Expr::FieldAccess {
                 parent: parse_boxed_expr(parent),
                 field,
             })
             .unwrap_or_else(|| {
-                log::warn!("Unrecognized field access `{}`", field);
+                let message = format!("unknown field `{}`", field);
+                let diagnostic = Diagnostic::warning(Code::UnknownField, message, span)
+                    .with_note("only `x`, `y` and `z` can be accessed", None);
+                context.report(diagnostic);
                 Expr::Undef
             }),
-        ast::Expr::ArrayAccess { array, index } => Expr::ArrayAccess {
+        ast::ExprKind::ArrayAccess { array, index } => Expr::ArrayAccess {
             array: parse_boxed_expr(array),
             index: parse_boxed_expr(index),
         },
-        ast::Expr::Ternary {
+        ast::ExprKind::Ternary {
             condition,
             if_true,
             if_false,
@@ -232,7 +594,7 @@ fn parse_expr<'a>(expr: ast::Expr, context: &Context<'a>) -> Expr {
             if_true: parse_boxed_expr(if_true),
             if_false: parse_boxed_expr(if_false),
         },
-        ast::Expr::Range {
+        ast::ExprKind::Range {
             start,
             end,
             increment,
@@ -241,66 +603,295 @@ fn parse_expr<'a>(expr: ast::Expr, context: &Context<'a>) -> Expr {
             end: parse_boxed_expr(end),
             increment: increment.map(parse_boxed_expr),
         },
-        ast::Expr::ListComprehension {
-            lets,
-            variables,
+        ast::ExprKind::ListComprehension(elements) => Expr::ListComprehension(
+            elements
+                .into_iter()
+                .map(|element| parse_list_element(element, context))
+                .collect(),
+        ),
+    }
+}
+
+/// Declares variables one after the other in a new scope.
+///
+/// Each value is resolved before its own variable is declared, so `let (a = a + 1)` refers to
+/// the outer `a`, but later values can use earlier variables. There is one variable per value,
+/// even without a name, so the `n`th value is the `n`th variable.
+fn parse_sequential_variables(
+    variables: Vec<ast::ParameterValue>,
+    context: &mut Context,
+) -> Vec<Expr> {
+    variables
+        .into_iter()
+        .map(|param| {
+            let span = param.value.span;
+            let value = parse_expr(param.value, context);
+            if let Some(name) = param.name {
+                context.add_variable(&name, span, |_| Expr::Extern);
+            } else {
+                let message = "assignment without a variable name";
+                context.report(Diagnostic::warning(Code::MissingName, message, span));
+                context.add_variable("", span, |_| Expr::Extern);
+            }
+            value
+        })
+        .collect()
+}
+
+fn parse_list_element(element: ast::ListElement, context: &Context) -> ListElement {
+    match element {
+        ast::ListElement::Expr(expr) => ListElement::Expr(parse_expr(expr, context)),
+        ast::ListElement::Each(element) => {
+            ListElement::Each(Box::new(parse_list_element(*element, context)))
+        }
+        ast::ListElement::For { variables, body } => {
+            let mut context = Context::new(context);
+            let ranges = parse_sequential_variables(variables, &mut context);
+            let body = Box::new(parse_list_element(*body, &context));
+            ListElement::For { ranges, body }
+        }
+        ast::ListElement::ForC {
+            init,
+            condition,
+            update,
             body,
-        } => Expr::ListComprehension {
-            lets: Vec::new(),
-            variables: parse_parameter_values(variables, context),
-            body: parse_boxed_expr(body),
+        } => {
+            let mut context = Context::new(context);
+            let init = parse_sequential_variables(init, &mut context);
+            let condition = parse_expr(condition, &context);
+            let update = update
+                .into_iter()
+                .filter_map(|param| {
+                    let span = param.value.span;
+                    let value = parse_expr(param.value, &context);
+                    let id = param
+                        .name
+                        .as_deref()
+                        .and_then(|name| context.find_local_var(name));
+                    match id {
+                        Some(id) => Some((id, value)),
+                        None => {
+                            let message = "loop update must assign a loop variable";
+                            let code = Code::InvalidLoopUpdate;
+                            context.report(Diagnostic::warning(code, message, span));
+                            None
+                        }
+                    }
+                })
+                .collect();
+            let body = Box::new(parse_list_element(*body, &context));
+            ListElement::ForC {
+                init,
+                condition,
+                update,
+                body,
+            }
+        }
+        ast::ListElement::If {
+            condition,
+            if_true,
+            if_false,
+        } => ListElement::If {
+            condition: parse_expr(condition, context),
+            if_true: Box::new(parse_list_element(*if_true, context)),
+            if_false: if_false.map(|e| Box::new(parse_list_element(*e, context))),
         },
+        ast::ListElement::Let { vars, body } => {
+            let mut context = Context::new(context);
+            let vars = parse_sequential_variables(vars, &mut context);
+            let body = Box::new(parse_list_element(*body, &context));
+            ListElement::Let { vars, body }
+        }
+    }
+}
+
+fn parse_function(
+    name: &str,
+    params: Vec<ast::ParameterDefinition>,
+    body: ast::Expr,
+    context: &Context,
+) -> Function {
+    let mut context = Context::new(context);
+
+    let parameters = parse_parameter_definitions(params, &mut context);
+    let body = parse_expr(body, &context);
+    let scope = context.scope;
+
+    Function {
+        name: name.to_string(),
+        parameters,
+        body,
+        scope,
     }
 }
 
-fn parse_statement<'a>(statement: ast::Statement, context: &mut Context<'a>) {
-    match statement {
-        ast::Statement::VariableDeclaration(name, expr) => {
-            // Insert a new variable in scope
-            context.add_variable(name, |context| parse_expr(expr, context));
+/// Declares the variable, function or module defined by this statement, if any.
+///
+/// Returns its index in the scope, so `parse_statement` can define it later.
+fn declare_statement(statement: &ast::Statement, context: &mut Context) -> Option<usize> {
+    match &statement.kind {
+        ast::StatementKind::VariableDeclaration(name, _) => {
+            Some(context.declare_variable(name, statement.span))
         }
-        ast::Statement::ModuleDefinition { name, args, body } => {
-            context.add_module(name, |context| {
-                let mut context = Context::new(context);
-                // For each param:
-                let default_values = parse_parameter_definitions(args, &mut context);
-
-                // Insert the module parameters in the child context
-                parse_scope(*body, &mut context);
-
-                Module {
-                    default_values,
-                    body: context.scope,
-                }
-            });
+        ast::StatementKind::ModuleDefinition { name, .. } => Some(context.declare_module(name)),
+        ast::StatementKind::FunctionDefinition(name, _, _) => Some(context.declare_function(name)),
+        _ => None,
+    }
+}
+
+fn parse_statement(statement: ast::Statement, id: Option<usize>, context: &mut Context) {
+    let span = statement.span;
+    match (statement.kind, id) {
+        (ast::StatementKind::VariableDeclaration(_, expr), Some(id)) => {
+            context.scope.variables[id] = parse_expr(expr, context);
+            context.scope.variable_spans[id] = span;
         }
-        ast::Statement::FunctionDefinition(name, params, body) => {
-            context.add_function(name, |context| {
-                let mut context = Context::new(context);
-
-                let default_values = parse_parameter_definitions(params, &mut context);
-                let body = parse_expr(body, &context);
-                let scope = context.scope;
-
-                Function {
-                    default_values,
-                    body,
-                    scope,
-                }
+        (ast::StatementKind::ModuleDefinition { name, args, body }, Some(id)) => {
+            let mut module_context = Context::new(context);
+            // Parameters come first in the module scope
+            let parameters = parse_parameter_definitions(args, &mut module_context);
+            parse_scope(vec![*body], &mut module_context);
+
+            context.scope.modules[id] = Arc::new(Module {
+                name: name.into_owned(),
+                parameters,
+                body: module_context.scope,
             });
         }
+        (ast::StatementKind::FunctionDefinition(name, params, body), Some(id)) => {
+            let function = parse_function(&name, params, body, context);
+            context.scope.functions[id] = Arc::new(function);
+        }
+        (ast::StatementKind::Modifier(modifier, statement), _) => {
+            let first = context.scope.items.len();
+            parse_statement(*statement, id, context);
+            for item in &mut context.scope.items[first..] {
+                item.modifiers.insert(0, modifier);
+            }
+        }
+        (ast::StatementKind::ModuleCall(call), _) => {
+            let item = parse_module_call(call, span, context);
+            context.scope.items.push(item);
+        }
+        (
+            ast::StatementKind::If {
+                condition,
+                if_true,
+                if_false,
+            },
+            _,
+        ) => {
+            let ty = ItemType::If {
+                condition: parse_expr(condition, context),
+                if_false: Box::new(parse_child_scope(*if_false, context)),
+            };
+            let item = Item {
+                ty,
+                modifiers: Vec::new(),
+                params: Vec::new(),
+                child: parse_child_scope(*if_true, context),
+                span,
+            };
+            context.scope.items.push(item);
+        }
+        (ast::StatementKind::For { variables, body }, _) => {
+            let item = parse_scoped_item(variables, *body, ItemType::For, span, context);
+            context.scope.items.push(item);
+        }
+        (ast::StatementKind::Let(lets, body), _) => {
+            let vars = lets.into_iter().flat_map(|l| l.vars).collect();
+            let item = parse_scoped_item(vars, *body, ItemType::Let, span, context);
+            context.scope.items.push(item);
+        }
+        (ast::StatementKind::Include(path), _) => context.scope.includes.push(path.into_owned()),
+        (ast::StatementKind::Use(path), _) => context.scope.uses.push(path.into_owned()),
+        // Statement lists were flattened by `parse_scope`, the rest has no effect.
         _ => (),
     }
 }
 
-fn parse_scope<'a>(statement: ast::Statement, context: &mut Context<'a>) {
-    // Step 1: check all Module, Function and variable names.
-    // No value / default value
-    // Ignore module calls/ifs for now
+fn parse_module_call(call: ast::ModuleCall, span: Span, context: &Context) -> Item {
+    let ty = if let Some(id) = context.find_module(&call.function) {
+        ItemType::User(id)
+    } else if let Some(id) = context.find_library_module(&call.function) {
+        ItemType::Library(id)
+    } else {
+        match &*call.function {
+            // Builtins that declare variables for their children
+            "let" => {
+                let ty = ItemType::Let;
+                return parse_scoped_item(call.params, *call.child, ty, span, context);
+            }
+            "intersection_for" => {
+                let ty = ItemType::IntersectionFor;
+                return parse_scoped_item(call.params, *call.child, ty, span, context);
+            }
+            _ => ItemType::Extern(call.function.into_owned()),
+        }
+    };
+
+    Item {
+        ty,
+        modifiers: Vec::new(),
+        params: parse_parameter_values(call.params, context),
+        child: parse_child_scope(*call.child, context),
+        span,
+    }
+}
+
+/// Resolves a loop or a `let`, with its variables declared one after the other in the child scope.
+fn parse_scoped_item(
+    variables: Vec<ast::ParameterValue>,
+    body: ast::Statement,
+    ty: fn(Vec<Expr>) -> ItemType,
+    span: Span,
+    context: &Context,
+) -> Item {
+    let mut context = Context::new(context);
+    let values = parse_sequential_variables(variables, &mut context);
+    parse_scope(vec![body], &mut context);
+
+    Item {
+        ty: ty(values),
+        modifiers: Vec::new(),
+        params: Vec::new(),
+        child: context.scope,
+        span,
+    }
+}
 
-    parse_statement(statement, context);
+fn parse_child_scope(statement: ast::Statement, context: &Context) -> Scope {
+    let mut context = Context::new(context);
+    parse_scope(vec![statement], &mut context);
+    context.scope
+}
+
+/// Inlines the content of `{ }` blocks: they do not introduce a new scope.
+fn flatten(statements: Vec<ast::Statement>) -> Vec<ast::Statement> {
+    let mut result = Vec::new();
+    for statement in statements {
+        match statement.kind {
+            ast::StatementKind::StatementList(statements) => result.extend(flatten(statements)),
+            _ => result.push(statement),
+        }
+    }
+    result
+}
+
+fn parse_scope(statements: Vec<ast::Statement>, context: &mut Context) {
+    let statements = flatten(statements);
+
+    // Step 1: declare all module, function and variable names,
+    // so they can be used anywhere in the scope.
+    let ids: Vec<_> = statements
+        .iter()
+        .map(|statement| declare_statement(statement, context))
+        .collect();
 
     // Step 2: resolve the actual values and sub-scopes
+    for (statement, id) in statements.into_iter().zip(ids) {
+        parse_statement(statement, id, context);
+    }
 }
 
 struct Context<'a> {
@@ -309,39 +900,120 @@ struct Context<'a> {
     functions_map: HashMap<String, usize>,
     modules_map: HashMap<String, usize>,
 
+    /// Where each variable of this scope was first assigned, for diagnostics.
+    assignments: HashMap<usize, Span>,
+
     parent: Option<&'a Context<'a>>,
+    /// Libraries imported in the document, searched after all scopes.
+    libraries: &'a [Arc<Document>],
+    /// Where problems are reported, shared by all scopes.
+    diagnostics: &'a RefCell<Diagnostics>,
 }
 
 impl<'a> Context<'a> {
-    fn new(parent: &'a Context<'a>) -> Self {
+    fn root(libraries: &'a [Arc<Document>], diagnostics: &'a RefCell<Diagnostics>) -> Self {
         Context {
             scope: Scope::default(),
-            parent: Some(parent),
+            parent: None,
+            libraries,
+            diagnostics,
             variables_map: HashMap::new(),
             functions_map: HashMap::new(),
             modules_map: HashMap::new(),
+            assignments: HashMap::new(),
         }
     }
 
-    fn add_function<F: FnOnce(&Self) -> Function>(&mut self, name: &str, f: F) {
+    fn new(parent: &'a Context<'a>) -> Self {
+        Context {
+            parent: Some(parent),
+            ..Context::root(parent.libraries, parent.diagnostics)
+        }
+    }
+
+    fn report(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    /// Declares a function, to be defined later.
+    fn declare_function(&mut self, name: &str) -> usize {
         let id = self.scope.functions.len();
         self.functions_map.insert(name.to_string(), id);
-        let function = f(self);
-        self.scope.functions.push(function);
+        self.scope.functions.push(Arc::new(Function {
+            name: name.to_string(),
+            parameters: Vec::new(),
+            scope: Scope::default(),
+            body: Expr::Undef,
+        }));
+        id
     }
 
-    fn add_module<F: FnOnce(&Self) -> Module>(&mut self, name: &str, f: F) {
+    /// Declares a module, to be defined later.
+    fn declare_module(&mut self, name: &str) -> usize {
         let id = self.scope.modules.len();
         self.modules_map.insert(name.to_string(), id);
-        let module = f(self);
-        self.scope.modules.push(module);
+        self.scope.modules.push(Arc::new(Module {
+            name: name.to_string(),
+            parameters: Vec::new(),
+            body: Scope::default(),
+        }));
+        id
     }
 
-    fn add_variable<F: FnOnce(&Self) -> Expr>(&mut self, name: &str, f: F) {
+    /// Declares a variable, to be defined later. Its value is `undef` until then.
+    ///
+    /// Like OpenSCAD, assigning a variable again in the same scope does not create a new one:
+    /// the last value wins, but it is evaluated at the position of the first assignment.
+    /// Assigning a parameter or a `for` variable overrides it.
+    fn declare_variable(&mut self, name: &str, span: Span) -> usize {
+        if let Some(id) = self.find_local_var(name) {
+            match self.assignments.get(&id) {
+                Some(&first) => {
+                    let message = format!(
+                        "`{}` was assigned twice in the same scope, only the last value is used",
+                        name
+                    );
+                    let diagnostic = Diagnostic::warning(Code::Reassignment, message, span)
+                        .with_note("first assigned here", Some(first));
+                    self.report(diagnostic);
+                }
+                // Parameters and `for` variables can be overridden without a warning, like in
+                // OpenSCAD.
+                None => {
+                    self.assignments.insert(id, span);
+                }
+            }
+            return id;
+        }
+        let id = self.add_variable(name, span, |_| Expr::Undef);
+        self.assignments.insert(id, span);
+        id
+    }
+
+    fn add_variable<F>(&mut self, name: &str, span: Span, f: F) -> usize
+    where
+        F: FnOnce(&Self) -> Expr,
+    {
         let id = self.scope.variables.len();
-        self.variables_map.insert(name.to_string(), id);
+        if is_special(name) {
+            self.scope.specials.push((name.to_string(), id));
+        } else {
+            self.variables_map.insert(name.to_string(), id);
+        }
         let variable = f(self);
         self.scope.variables.push(variable);
+        self.scope.variable_spans.push(span);
+        id
+    }
+
+    /// Returns the index of a variable already declared in this scope, special or not.
+    fn find_local_var(&self, name: &str) -> Option<usize> {
+        if is_special(name) {
+            let mut specials = self.scope.specials.iter().rev();
+            specials.find(|(n, _)| n == name).map(|&(_, id)| id)
+        } else {
+            self.variables_map.get(name).copied()
+        }
     }
 
     fn find_var(&self, name: &str) -> Option<VariableId> {
@@ -352,17 +1024,40 @@ impl<'a> Context<'a> {
         find_id(name, self, |s| &s.modules_map)
     }
 
-    fn find_function(&self, name: &str) -> Option<FunctionId> {
-        find_id(name, self, |s| &s.functions_map)
+    /// Returns the function, or the variable holding one, called by `name(...)` in the closest
+    /// scope that has either.
+    fn find_callee(&self, name: &str) -> Option<Callee> {
+        let mut context = self;
+        let mut depth = 0;
+        loop {
+            if let Some(&id) = context.functions_map.get(name) {
+                return Some(Callee::Function(Id { depth, id }));
+            } else if let Some(&id) = context.variables_map.get(name) {
+                return Some(Callee::Variable(Id { depth, id }));
+            }
+            context = context.parent?;
+            depth += 1;
+        }
+    }
+
+    fn find_library_module(&self, name: &str) -> Option<LibraryId> {
+        find_library_id(name, self.libraries, |d| &d.modules)
+    }
+
+    fn find_library_function(&self, name: &str) -> Option<LibraryId> {
+        find_library_id(name, self.libraries, |d| &d.functions)
     }
 }
 
-fn add<T, F: FnOnce(&Context) -> T>(
-    name: &str,
-    map: &mut HashMap<String, T>,
-    values: &mut Vec<T>,
-    f: F,
-) {
+/// What a call to a name refers to, in the scopes of the document.
+enum Callee {
+    Function(FunctionId),
+    Variable(VariableId),
+}
+
+/// Special variables start with `$`, like `$fn`, and are dynamically scoped.
+fn is_special(name: &str) -> bool {
+    name.starts_with('$')
 }
 
 fn find_id<F>(name: &str, context: &Context, f: F) -> Option<Id>
@@ -382,11 +1077,42 @@ where
         })
 }
 
-pub fn parse(statements: &[ast::Statement]) -> Scope {
-    Scope {
-        variables: vec![],
-        functions: vec![],
-        modules: vec![],
-        items: vec![],
+fn find_library_id<F>(name: &str, libraries: &[Arc<Document>], f: F) -> Option<LibraryId>
+where
+    F: Fn(&Document) -> &HashMap<String, usize>,
+{
+    libraries
+        .iter()
+        .enumerate()
+        .find_map(|(library, document)| f(document).get(name).map(|&id| LibraryId { library, id }))
+}
+
+/// Resolves all names in a document, along with the problems found.
+pub fn parse(statements: Vec<ast::Statement>) -> (Scope, Diagnostics) {
+    let diagnostics = RefCell::new(Diagnostics::new());
+    let mut context = Context::root(&[], &diagnostics);
+    parse_scope(statements, &mut context);
+    let scope = context.scope;
+    (scope, diagnostics.into_inner())
+}
+
+/// Resolves all names in a document, which can use modules and functions from the given libraries.
+pub fn parse_document(statements: Vec<ast::Statement>, libraries: Vec<Arc<Document>>) -> Document {
+    let diagnostics = RefCell::new(Diagnostics::new());
+    let mut context = Context::root(&libraries, &diagnostics);
+    parse_scope(statements, &mut context);
+    let Context {
+        scope,
+        functions_map,
+        modules_map,
+        ..
+    } = context;
+
+    Document {
+        scope,
+        modules: modules_map,
+        functions: functions_map,
+        diagnostics: diagnostics.into_inner(),
+        libraries,
     }
 }
@@ -0,0 +1,557 @@
+//! Traversal of the raw AST.
+//!
+//! Implement [`Visitor`] (or [`VisitorMut`] to modify the tree in place), and only override the
+//! methods for the nodes you care about. The default implementation of each `visit_*` method
+//! calls the matching `walk_*` function, which visits every child of the node.
+//!
+//! When overriding a method, call the `walk_*` function to keep visiting the children:
+//!
+//! ```
+//! use rscad::ast::{Expr, ExprKind};
+//! use rscad::visit::{self, Visitor};
+//!
+//! /// Collects the names of all variables read.
+//! struct Variables<'ast>(Vec<&'ast str>);
+//!
+//! impl<'ast, 'input> Visitor<'ast, 'input> for Variables<'ast> {
+//!     fn visit_expr(&mut self, expr: &'ast Expr<'input>) {
+//!         if let ExprKind::Variable(name) = &expr.kind {
+//!             self.0.push(name);
+//!         }
+//!         visit::walk_expr(self, expr);
+//!     }
+//! }
+//!
+//! let statements = rscad::parse("a = b + c; cube(d);").unwrap();
+//! let mut variables = Variables(Vec::new());
+//! variables.visit_statements(&statements);
+//! assert_eq!(variables.0, vec!["b", "c", "d"]);
+//! ```
+
+use crate::ast::{
+    Expr, ExprKind, FunctionCall, Let, ListElement, ModuleCall, ParameterDefinition,
+    ParameterValue, Statement, StatementKind,
+};
+
+/// Visits an AST by reference.
+///
+/// `'ast` is the lifetime of the borrowed tree, `'input` the lifetime of the source it was
+/// parsed from.
+pub trait Visitor<'ast, 'input> {
+    /// Visits each statement in order.
+    fn visit_statements(&mut self, statements: &'ast [Statement<'input>]) {
+        for statement in statements {
+            self.visit_statement(statement);
+        }
+    }
+
+    fn visit_statement(&mut self, statement: &'ast Statement<'input>) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_module_call(&mut self, call: &'ast ModuleCall<'input>) {
+        walk_module_call(self, call);
+    }
+
+    fn visit_expr(&mut self, expr: &'ast Expr<'input>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_function_call(&mut self, call: &'ast FunctionCall<'input>) {
+        walk_function_call(self, call);
+    }
+
+    fn visit_list_element(&mut self, element: &'ast ListElement<'input>) {
+        walk_list_element(self, element);
+    }
+
+    fn visit_let(&mut self, l: &'ast Let<'input>) {
+        walk_let(self, l);
+    }
+
+    fn visit_parameter_value(&mut self, param: &'ast ParameterValue<'input>) {
+        walk_parameter_value(self, param);
+    }
+
+    fn visit_parameter_definition(&mut self, param: &'ast ParameterDefinition<'input>) {
+        walk_parameter_definition(self, param);
+    }
+}
+
+pub fn walk_statement<'ast, 'input, V>(visitor: &mut V, statement: &'ast Statement<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    match &statement.kind {
+        StatementKind::VariableDeclaration(_, value) => visitor.visit_expr(value),
+        StatementKind::Modifier(_, child) => visitor.visit_statement(child),
+        StatementKind::StatementList(statements) => visitor.visit_statements(statements),
+        StatementKind::NoOp
+        | StatementKind::Error
+        | StatementKind::Include(_)
+        | StatementKind::Use(_)
+        | StatementKind::Comment(_) => (),
+        StatementKind::ModuleDefinition { args, body, .. } => {
+            for arg in args {
+                visitor.visit_parameter_definition(arg);
+            }
+            visitor.visit_statement(body);
+        }
+        StatementKind::Let(lets, body) => {
+            for l in lets {
+                visitor.visit_let(l);
+            }
+            visitor.visit_statement(body);
+        }
+        StatementKind::FunctionDefinition(_, args, body) => {
+            for arg in args {
+                visitor.visit_parameter_definition(arg);
+            }
+            visitor.visit_expr(body);
+        }
+        StatementKind::ModuleCall(call) => visitor.visit_module_call(call),
+        StatementKind::For { variables, body } => {
+            for variable in variables {
+                visitor.visit_parameter_value(variable);
+            }
+            visitor.visit_statement(body);
+        }
+        StatementKind::If {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_statement(if_true);
+            visitor.visit_statement(if_false);
+        }
+    }
+}
+
+pub fn walk_module_call<'ast, 'input, V>(visitor: &mut V, call: &'ast ModuleCall<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    for param in &call.params {
+        visitor.visit_parameter_value(param);
+    }
+    visitor.visit_statement(&call.child);
+}
+
+pub fn walk_expr<'ast, 'input, V>(visitor: &mut V, expr: &'ast Expr<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    match &expr.kind {
+        ExprKind::Undef
+        | ExprKind::Boolean(_)
+        | ExprKind::Number(_)
+        | ExprKind::Text(_)
+        | ExprKind::Variable(_) => (),
+        ExprKind::Negative(e) | ExprKind::Not(e) | ExprKind::BitNot(e) => visitor.visit_expr(e),
+        ExprKind::Function(call) => visitor.visit_function_call(call),
+        ExprKind::Lambda { args, body } => {
+            for arg in args {
+                visitor.visit_parameter_definition(arg);
+            }
+            visitor.visit_expr(body);
+        }
+        ExprKind::Echo(params, body) | ExprKind::Assert(params, body) => {
+            for param in params {
+                visitor.visit_parameter_value(param);
+            }
+            visitor.visit_expr(body);
+        }
+        ExprKind::Let(lets, body) => {
+            for l in lets {
+                visitor.visit_let(l);
+            }
+            visitor.visit_expr(body);
+        }
+        ExprKind::ListComprehension(elements) => {
+            for element in elements {
+                visitor.visit_list_element(element);
+            }
+        }
+        ExprKind::Vector(values) => {
+            for value in values {
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Op(a, _, b) | ExprKind::Or(a, b) | ExprKind::And(a, b) => {
+            visitor.visit_expr(a);
+            visitor.visit_expr(b);
+        }
+        ExprKind::FieldAccess { parent, .. } => visitor.visit_expr(parent),
+        ExprKind::ArrayAccess { array, index } => {
+            visitor.visit_expr(array);
+            visitor.visit_expr(index);
+        }
+        ExprKind::Ternary {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_expr(if_true);
+            visitor.visit_expr(if_false);
+        }
+        ExprKind::Range {
+            start,
+            end,
+            increment,
+        } => {
+            visitor.visit_expr(start);
+            if let Some(increment) = increment {
+                visitor.visit_expr(increment);
+            }
+            visitor.visit_expr(end);
+        }
+    }
+}
+
+pub fn walk_function_call<'ast, 'input, V>(visitor: &mut V, call: &'ast FunctionCall<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    visitor.visit_expr(&call.function);
+    for param in &call.parameters {
+        visitor.visit_parameter_value(param);
+    }
+}
+
+pub fn walk_list_element<'ast, 'input, V>(visitor: &mut V, element: &'ast ListElement<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    match element {
+        ListElement::Expr(expr) => visitor.visit_expr(expr),
+        ListElement::Each(body) => visitor.visit_list_element(body),
+        ListElement::For { variables, body } => {
+            for variable in variables {
+                visitor.visit_parameter_value(variable);
+            }
+            visitor.visit_list_element(body);
+        }
+        ListElement::ForC {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            for param in init {
+                visitor.visit_parameter_value(param);
+            }
+            visitor.visit_expr(condition);
+            for param in update {
+                visitor.visit_parameter_value(param);
+            }
+            visitor.visit_list_element(body);
+        }
+        ListElement::If {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            visitor.visit_expr(condition);
+            visitor.visit_list_element(if_true);
+            if let Some(if_false) = if_false {
+                visitor.visit_list_element(if_false);
+            }
+        }
+        ListElement::Let { vars, body } => {
+            for var in vars {
+                visitor.visit_parameter_value(var);
+            }
+            visitor.visit_list_element(body);
+        }
+    }
+}
+
+pub fn walk_let<'ast, 'input, V>(visitor: &mut V, l: &'ast Let<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    for var in &l.vars {
+        visitor.visit_parameter_value(var);
+    }
+}
+
+pub fn walk_parameter_value<'ast, 'input, V>(visitor: &mut V, param: &'ast ParameterValue<'input>)
+where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    visitor.visit_expr(&param.value);
+}
+
+pub fn walk_parameter_definition<'ast, 'input, V>(
+    visitor: &mut V,
+    param: &'ast ParameterDefinition<'input>,
+) where
+    V: Visitor<'ast, 'input> + ?Sized,
+{
+    if let Some(value) = &param.default_value {
+        visitor.visit_expr(value);
+    }
+}
+
+/// Visits an AST by mutable reference, to modify it in place.
+pub trait VisitorMut<'input> {
+    /// Visits each statement in order.
+    ///
+    /// Override this to add or remove statements from a list.
+    fn visit_statements_mut(&mut self, statements: &mut Vec<Statement<'input>>) {
+        for statement in statements {
+            self.visit_statement_mut(statement);
+        }
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement<'input>) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_module_call_mut(&mut self, call: &mut ModuleCall<'input>) {
+        walk_module_call_mut(self, call);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'input>) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_function_call_mut(&mut self, call: &mut FunctionCall<'input>) {
+        walk_function_call_mut(self, call);
+    }
+
+    fn visit_list_element_mut(&mut self, element: &mut ListElement<'input>) {
+        walk_list_element_mut(self, element);
+    }
+
+    fn visit_let_mut(&mut self, l: &mut Let<'input>) {
+        walk_let_mut(self, l);
+    }
+
+    fn visit_parameter_value_mut(&mut self, param: &mut ParameterValue<'input>) {
+        walk_parameter_value_mut(self, param);
+    }
+
+    fn visit_parameter_definition_mut(&mut self, param: &mut ParameterDefinition<'input>) {
+        walk_parameter_definition_mut(self, param);
+    }
+}
+
+pub fn walk_statement_mut<'input, V>(visitor: &mut V, statement: &mut Statement<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    match &mut statement.kind {
+        StatementKind::VariableDeclaration(_, value) => visitor.visit_expr_mut(value),
+        StatementKind::Modifier(_, child) => visitor.visit_statement_mut(child),
+        StatementKind::StatementList(statements) => visitor.visit_statements_mut(statements),
+        StatementKind::NoOp
+        | StatementKind::Error
+        | StatementKind::Include(_)
+        | StatementKind::Use(_)
+        | StatementKind::Comment(_) => (),
+        StatementKind::ModuleDefinition { args, body, .. } => {
+            for arg in args {
+                visitor.visit_parameter_definition_mut(arg);
+            }
+            visitor.visit_statement_mut(body);
+        }
+        StatementKind::Let(lets, body) => {
+            for l in lets {
+                visitor.visit_let_mut(l);
+            }
+            visitor.visit_statement_mut(body);
+        }
+        StatementKind::FunctionDefinition(_, args, body) => {
+            for arg in args {
+                visitor.visit_parameter_definition_mut(arg);
+            }
+            visitor.visit_expr_mut(body);
+        }
+        StatementKind::ModuleCall(call) => visitor.visit_module_call_mut(call),
+        StatementKind::For { variables, body } => {
+            for variable in variables {
+                visitor.visit_parameter_value_mut(variable);
+            }
+            visitor.visit_statement_mut(body);
+        }
+        StatementKind::If {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_statement_mut(if_true);
+            visitor.visit_statement_mut(if_false);
+        }
+    }
+}
+
+pub fn walk_module_call_mut<'input, V>(visitor: &mut V, call: &mut ModuleCall<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    for param in &mut call.params {
+        visitor.visit_parameter_value_mut(param);
+    }
+    visitor.visit_statement_mut(&mut call.child);
+}
+
+pub fn walk_expr_mut<'input, V>(visitor: &mut V, expr: &mut Expr<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    match &mut expr.kind {
+        ExprKind::Undef
+        | ExprKind::Boolean(_)
+        | ExprKind::Number(_)
+        | ExprKind::Text(_)
+        | ExprKind::Variable(_) => (),
+        ExprKind::Negative(e) | ExprKind::Not(e) | ExprKind::BitNot(e) => visitor.visit_expr_mut(e),
+        ExprKind::Function(call) => visitor.visit_function_call_mut(call),
+        ExprKind::Lambda { args, body } => {
+            for arg in args {
+                visitor.visit_parameter_definition_mut(arg);
+            }
+            visitor.visit_expr_mut(body);
+        }
+        ExprKind::Echo(params, body) | ExprKind::Assert(params, body) => {
+            for param in params {
+                visitor.visit_parameter_value_mut(param);
+            }
+            visitor.visit_expr_mut(body);
+        }
+        ExprKind::Let(lets, body) => {
+            for l in lets {
+                visitor.visit_let_mut(l);
+            }
+            visitor.visit_expr_mut(body);
+        }
+        ExprKind::ListComprehension(elements) => {
+            for element in elements {
+                visitor.visit_list_element_mut(element);
+            }
+        }
+        ExprKind::Vector(values) => {
+            for value in values {
+                visitor.visit_expr_mut(value);
+            }
+        }
+        ExprKind::Op(a, _, b) | ExprKind::Or(a, b) | ExprKind::And(a, b) => {
+            visitor.visit_expr_mut(a);
+            visitor.visit_expr_mut(b);
+        }
+        ExprKind::FieldAccess { parent, .. } => visitor.visit_expr_mut(parent),
+        ExprKind::ArrayAccess { array, index } => {
+            visitor.visit_expr_mut(array);
+            visitor.visit_expr_mut(index);
+        }
+        ExprKind::Ternary {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_expr_mut(if_true);
+            visitor.visit_expr_mut(if_false);
+        }
+        ExprKind::Range {
+            start,
+            end,
+            increment,
+        } => {
+            visitor.visit_expr_mut(start);
+            if let Some(increment) = increment {
+                visitor.visit_expr_mut(increment);
+            }
+            visitor.visit_expr_mut(end);
+        }
+    }
+}
+
+pub fn walk_function_call_mut<'input, V>(visitor: &mut V, call: &mut FunctionCall<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    visitor.visit_expr_mut(&mut call.function);
+    for param in &mut call.parameters {
+        visitor.visit_parameter_value_mut(param);
+    }
+}
+
+pub fn walk_list_element_mut<'input, V>(visitor: &mut V, element: &mut ListElement<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    match element {
+        ListElement::Expr(expr) => visitor.visit_expr_mut(expr),
+        ListElement::Each(body) => visitor.visit_list_element_mut(body),
+        ListElement::For { variables, body } => {
+            for variable in variables {
+                visitor.visit_parameter_value_mut(variable);
+            }
+            visitor.visit_list_element_mut(body);
+        }
+        ListElement::ForC {
+            init,
+            condition,
+            update,
+            body,
+        } => {
+            for param in init {
+                visitor.visit_parameter_value_mut(param);
+            }
+            visitor.visit_expr_mut(condition);
+            for param in update {
+                visitor.visit_parameter_value_mut(param);
+            }
+            visitor.visit_list_element_mut(body);
+        }
+        ListElement::If {
+            condition,
+            if_true,
+            if_false,
+        } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_list_element_mut(if_true);
+            if let Some(if_false) = if_false {
+                visitor.visit_list_element_mut(if_false);
+            }
+        }
+        ListElement::Let { vars, body } => {
+            for var in vars {
+                visitor.visit_parameter_value_mut(var);
+            }
+            visitor.visit_list_element_mut(body);
+        }
+    }
+}
+
+pub fn walk_let_mut<'input, V>(visitor: &mut V, l: &mut Let<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    for var in &mut l.vars {
+        visitor.visit_parameter_value_mut(var);
+    }
+}
+
+pub fn walk_parameter_value_mut<'input, V>(visitor: &mut V, param: &mut ParameterValue<'input>)
+where
+    V: VisitorMut<'input> + ?Sized,
+{
+    visitor.visit_expr_mut(&mut param.value);
+}
+
+pub fn walk_parameter_definition_mut<'input, V>(
+    visitor: &mut V,
+    param: &mut ParameterDefinition<'input>,
+) where
+    V: VisitorMut<'input> + ?Sized,
+{
+    if let Some(value) = &mut param.default_value {
+        visitor.visit_expr_mut(value);
+    }
+}
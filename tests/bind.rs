@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use rscad::diagnostic::{Code, Diagnostics};
+use rscad::interpreter::{bind, Argument, Bound, Context, Value};
+use rscad::parser::Id;
+use rscad::span::Span;
+
+fn positional(value: f64) -> Argument<Value> {
+    Argument {
+        name: None,
+        value: Value::Number(value),
+        span: Span::default(),
+    }
+}
+
+fn named(name: &str, value: f64, span: Span) -> Argument<Value> {
+    Argument {
+        name: Some(name.to_string()),
+        value: Value::Number(value),
+        span,
+    }
+}
+
+#[test]
+fn positional_then_named() {
+    let mut diagnostics = Diagnostics::new();
+    let bound = bind(
+        "cylinder",
+        &["h", "r1", "r2", "center"],
+        vec![
+            named("center", 1.0, Span::default()),
+            positional(10.0),
+            positional(2.0),
+            named("r1", 3.0, Span::default()),
+        ],
+        &mut diagnostics,
+    );
+
+    // The named `r1` replaces the second positional argument.
+    assert_eq!(
+        bound.values,
+        vec![
+            Some(Value::Number(10.0)),
+            Some(Value::Number(3.0)),
+            None,
+            Some(Value::Number(1.0)),
+        ]
+    );
+    assert!(diagnostics.is_empty());
+
+    let values = bound.with_defaults(|i| Value::Number(-(i as f64)));
+    assert_eq!(values[2], Value::Number(-2.0));
+}
+
+#[test]
+fn extra_and_unknown_arguments() {
+    let mut diagnostics = Diagnostics::new();
+    let bound = bind(
+        "m",
+        &["a"],
+        vec![
+            positional(1.0),
+            positional(2.0),
+            named("b", 3.0, Span::new(10, 11)),
+            named("$fn", 8.0, Span::new(20, 21)),
+            named("$fn", 16.0, Span::new(30, 32)),
+        ],
+        &mut diagnostics,
+    );
+
+    // Extra positional arguments are ignored, special variables are kept.
+    assert_eq!(
+        bound,
+        Bound {
+            values: vec![Some(Value::Number(1.0))],
+            specials: vec![("$fn".to_string(), Value::Number(16.0))],
+        }
+    );
+
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.code, d.span, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (
+                Code::UnknownArgument,
+                Span::new(10, 11),
+                "`b` is not a parameter of `m`"
+            ),
+            (
+                Code::DuplicateArgument,
+                Span::new(30, 32),
+                "argument `$fn` given more than once to `m`, only the last value is used"
+            ),
+        ]
+    );
+    assert_eq!(
+        diagnostics.iter().next().unwrap().notes[0].message,
+        "parameters are `a`"
+    );
+}
+
+#[test]
+fn duplicate_arguments_keep_the_last_value() {
+    let mut diagnostics = Diagnostics::new();
+    let bound = bind(
+        "sphere",
+        &["r"],
+        vec![
+            named("r", 1.0, Span::new(0, 5)),
+            named("r", 2.0, Span::new(10, 15)),
+            named("r", 3.0, Span::new(20, 25)),
+        ],
+        &mut diagnostics,
+    );
+
+    assert_eq!(bound.values, vec![Some(Value::Number(3.0))]);
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.span, d.notes[0].span))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Span::new(10, 15), Some(Span::new(0, 5))),
+            (Span::new(20, 25), Some(Span::new(10, 15))),
+        ]
+    );
+}
+
+#[test]
+fn arguments_in_context() {
+    let (scope, _) = rscad::resolve(rscad::parse("module m(size, $fn) cube(size);").unwrap());
+    let scope = &scope.modules[0].body;
+
+    let root = Arc::new(Context::root());
+    let context = Context::with_arguments(
+        scope,
+        vec![Value::Number(2.0), Value::Number(6.0)],
+        vec![
+            ("$fn".to_string(), Value::Number(4.0)),
+            ("$fa".to_string(), Value::Number(1.0)),
+        ],
+        Arc::clone(&root),
+        root,
+    );
+
+    assert_eq!(
+        context.variable(&Id { depth: 0, id: 0 }),
+        Some(&Value::Number(2.0))
+    );
+    // Parameters win over special variables given by name.
+    assert_eq!(context.special("$fn"), Some(&Value::Number(6.0)));
+    assert_eq!(context.special("$fa"), Some(&Value::Number(1.0)));
+    assert_eq!(context.special("$fs"), Some(&Value::Number(2.0)));
+}
@@ -0,0 +1,92 @@
+use rscad::csg::to_csg;
+
+/// Interprets a document, and writes the result in the `.csg` format.
+fn csg(source: &str) -> String {
+    let document = rscad::parser::parse_document(rscad::parse(source).unwrap(), Vec::new());
+    let output = rscad::interpreter::instantiate(&document);
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    to_csg(&output.root.unwrap())
+}
+
+#[test]
+fn primitives() {
+    assert_eq!(
+        csg(
+            "cube(); sphere(r = 1 / 3); cylinder(h = 2, r1 = 1, r2 = 0, center = true);
+            square([2, 3], true); circle(d = 1, $fn = 12);
+            polygon([[0, 0], [1, 0], [0, 1]], [[0, 1, 2]]);
+            polyhedron([[0, 0, 0], [1, 0, 0], [0, 1, 0], [0, 0, 1]], [[0, 1, 2], [0, 3, 1]]);"
+        ),
+        "group() {
+\tcube(size = [1, 1, 1], center = false);
+\tsphere($fn = 0, $fa = 12, $fs = 2, r = 0.333333);
+\tcylinder($fn = 0, $fa = 12, $fs = 2, h = 2, r1 = 1, r2 = 0, center = true);
+\tsquare(size = [2, 3], center = true);
+\tcircle($fn = 12, $fa = 12, $fs = 2, r = 0.5);
+\tpolygon(points = [[0, 0], [1, 0], [0, 1]], paths = [[0, 1, 2]], convexity = 1);
+\tpolyhedron(points = [[0, 0, 0], [1, 0, 0], [0, 1, 0], [0, 0, 1]], \
+faces = [[0, 1, 2], [0, 3, 1]], convexity = 1);
+}
+"
+    );
+}
+
+#[test]
+fn nesting_and_modifiers() {
+    assert_eq!(
+        csg(r#"module m() { children(); }
+            m() translate([1, 0, 0]) #cube();
+            %difference() { cube(); *sphere(); }
+            color("red") union();
+            linear_extrude(height = 2, center = true) square();
+            rotate_extrude() translate([2, 0]) circle();
+            hull() {}
+            minkowski(convexity = 3) { cube(); cube(); }"#),
+        "group() {
+\tgroup() {
+\t\tgroup() {
+\t\t\tmultmatrix([[1, 0, 0, 1], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]) {
+\t\t\t\t#cube(size = [1, 1, 1], center = false);
+\t\t\t}
+\t\t}
+\t}
+\t%difference() {
+\t\tcube(size = [1, 1, 1], center = false);
+\t}
+\tcolor([1, 0, 0, 1]) {
+\t\tunion();
+\t}
+\tlinear_extrude(height = 2, center = true, convexity = 1, scale = [1, 1], \
+$fn = 0, $fa = 12, $fs = 2) {
+\t\tsquare(size = [1, 1], center = false);
+\t}
+\trotate_extrude(angle = 360, convexity = 2, $fn = 0, $fa = 12, $fs = 2) {
+\t\tmultmatrix([[1, 0, 0, 2], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]) {
+\t\t\tcircle($fn = 0, $fa = 12, $fs = 2, r = 1);
+\t\t}
+\t}
+\thull();
+\tminkowski(convexity = 3) {
+\t\tcube(size = [1, 1, 1], center = false);
+\t\tcube(size = [1, 1, 1], center = false);
+\t}
+}
+"
+    );
+}
+
+#[test]
+fn show_only() {
+    assert_eq!(
+        csg("cube(); !sphere(2); import(\"a \\\"b\\\".stl\");"),
+        "sphere($fn = 0, $fa = 12, $fs = 2, r = 2);\n"
+    );
+    assert_eq!(
+        csg("import(\"a \\\"b\\\".stl\", convexity = 4);"),
+        "group() {
+\timport(file = \"a \\\"b\\\".stl\", layer = \"\", origin = [0, 0], scale = 1, convexity = 4, \
+$fn = 0, $fa = 12, $fs = 2, timestamp = 0);
+}
+"
+    );
+}
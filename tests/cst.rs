@@ -0,0 +1,280 @@
+use rscad::ast;
+use rscad::cst::{self, TokenKind};
+use rscad::span::Span;
+
+const SOURCE: &str = r#"// Header comment
+include <lib/utils.scad>
+
+/* The size */
+size = 1.50;  // trailing
+
+module box() {
+    // Inside the module
+    cube([size, 0x1F, 2e+3]);
+}
+"#;
+
+#[test]
+fn tokens_are_lossless() {
+    let tokens = cst::tokenize(SOURCE);
+    let text: String = tokens.iter().map(|t| t.text).collect();
+    assert_eq!(text, SOURCE);
+
+    let numbers: Vec<_> = tokens
+        .iter()
+        .filter(|t| t.kind == TokenKind::Number)
+        .map(|t| t.text)
+        .collect();
+    assert_eq!(numbers, vec!["1.50", "0x1F", "2e+3"]);
+
+    assert_eq!(tokens[0].kind, TokenKind::LineComment);
+    assert_eq!(tokens[0].text, "// Header comment");
+    assert_eq!(tokens[2].kind, TokenKind::Include);
+    assert_eq!(tokens[2].text, "include <lib/utils.scad>");
+}
+
+#[test]
+fn tokenize_tricky_tokens() {
+    let kinds = |source| -> Vec<_> {
+        cst::tokenize(source)
+            .into_iter()
+            .filter(|t| !t.kind.is_trivia())
+            .map(|t| (t.kind, t.text))
+            .collect()
+    };
+
+    assert_eq!(
+        kinds(r#"a<<2d "x\"y" .5"#),
+        vec![
+            (TokenKind::Identifier, "a"),
+            (TokenKind::Punctuation, "<<"),
+            (TokenKind::Identifier, "2d"),
+            (TokenKind::String, r#""x\"y""#),
+            (TokenKind::Number, ".5"),
+        ]
+    );
+    assert_eq!(
+        kinds("for(i=1e-3)"),
+        vec![
+            (TokenKind::Keyword, "for"),
+            (TokenKind::Punctuation, "("),
+            (TokenKind::Identifier, "i"),
+            (TokenKind::Punctuation, "="),
+            (TokenKind::Number, "1e-3"),
+            (TokenKind::Punctuation, ")"),
+        ]
+    );
+}
+
+/// Regular expressions of the lexer generated from the grammar, copied by the build script.
+const GRAMMAR_TOKENS: &[(&str, bool)] = include!(concat!(env!("OUT_DIR"), "/tokens.rs"));
+
+/// Returns the string literals of a Rust file, skipping those with escapes other than `\\`,
+/// `\"`, `\n` and `\t`.
+fn string_literals(rust: &str) -> Vec<String> {
+    let literal = regex::Regex::new(r##"(?s)r#"(.*?)"#|"((?:[^"\\]|\\.)*)""##).unwrap();
+    literal
+        .captures_iter(rust)
+        .filter_map(|captures| {
+            if let Some(raw) = captures.get(1) {
+                return Some(raw.as_str().to_string());
+            }
+            let mut text = String::new();
+            let mut chars = captures[2].chars();
+            while let Some(c) = chars.next() {
+                text.push(match (c, c == '\\') {
+                    (_, false) => c,
+                    _ => match chars.next()? {
+                        'n' => '\n',
+                        't' => '\t',
+                        c @ ('\\' | '"') => c,
+                        _ => return None,
+                    },
+                });
+            }
+            Some(text)
+        })
+        .collect()
+}
+
+#[test]
+fn tokens_match_the_grammar() {
+    let lexer = lalrpop_util::lexer::MatcherBuilder::new(GRAMMAR_TOKENS.iter().copied()).unwrap();
+    let mut corpus = string_literals(include_str!("parse.rs"));
+    corpus.extend(
+        [
+            SOURCE,
+            r#"a<<2d "x\"y" .5 1e-3 1.e2 2e 0x1F 0x 1.2.3"#,
+            "aé = 1; _é$ = 2; $fn = 3;",
+            "s = \"line\nbreak\";",
+            "include <a/b.scad>use<c.scad> include < x; use",
+            "x = 1./*c*/2; // end\r\n/**/",
+        ]
+        .iter()
+        .map(|source| source.to_string()),
+    );
+    let mut checked = 0;
+    for source in &corpus {
+        let grammar: Result<Vec<_>, _> = lexer
+            .matcher::<()>(source)
+            .map(|token| token.map(|(start, token, end)| (start, token.1, end)))
+            .collect();
+        // Only compare documents the grammar can split into tokens.
+        let grammar = match grammar {
+            Ok(grammar) => grammar,
+            Err(_) => continue,
+        };
+        let tokens: Vec<_> = cst::tokenize(source)
+            .into_iter()
+            .filter(|t| !t.kind.is_trivia())
+            .map(|t| (t.span.start, t.text, t.span.end))
+            .collect();
+        assert_eq!(tokens, grammar, "{:?}", source);
+        checked += 1;
+    }
+    assert!(checked > 100, "only {} documents checked", checked);
+}
+
+#[test]
+fn comments_become_statements() {
+    let tree = cst::parse_lossless(SOURCE).unwrap();
+
+    let kinds: Vec<_> = tree
+        .statements
+        .iter()
+        .map(|s| match &s.kind {
+            ast::StatementKind::Comment(text) => format!("comment {}", text),
+            ast::StatementKind::Include(path) => format!("include {}", path),
+            ast::StatementKind::VariableDeclaration(name, _) => format!("variable {}", name),
+            ast::StatementKind::ModuleDefinition { name, .. } => format!("module {}", name),
+            other => panic!("unexpected statement {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            "comment // Header comment",
+            "include lib/utils.scad",
+            "comment /* The size */",
+            "variable size",
+            "comment // trailing",
+            "module box",
+        ]
+    );
+
+    match &tree.statements[5].kind {
+        ast::StatementKind::ModuleDefinition { body, .. } => match &body.kind {
+            ast::StatementKind::StatementList(statements) => {
+                assert_eq!(
+                    statements[0].kind,
+                    ast::StatementKind::Comment("// Inside the module".into())
+                );
+                assert_eq!(statements.len(), 2);
+            }
+            other => panic!("unexpected body {:?}", other),
+        },
+        _ => unreachable!(),
+    }
+
+    // The regular parser still ignores comments.
+    assert_eq!(rscad::parse(SOURCE).unwrap().len(), 3);
+}
+
+#[test]
+fn trivia_around_nodes() {
+    let tree = cst::parse_lossless(SOURCE).unwrap();
+    let size = &tree.statements[3];
+
+    let leading: String = tree
+        .leading_trivia(size.span)
+        .iter()
+        .map(|t| t.text)
+        .collect();
+    assert_eq!(leading, "\n\n/* The size */\n");
+
+    let trailing: String = tree
+        .trailing_trivia(size.span)
+        .iter()
+        .map(|t| t.text)
+        .collect();
+    assert_eq!(trailing, "  // trailing\n\n");
+}
+
+#[test]
+fn rewrite_keeps_formatting() {
+    let tree = cst::parse_lossless(SOURCE).unwrap();
+
+    // Find the original spelling of the number.
+    let value = match &tree.statements[3].kind {
+        ast::StatementKind::VariableDeclaration(_, value) => value,
+        _ => unreachable!(),
+    };
+    assert_eq!(tree.text(value.span), "1.50");
+
+    let rewritten = tree.edit(&[(value.span, "2.25")]).unwrap();
+    assert_eq!(rewritten, SOURCE.replace("1.50", "2.25"));
+}
+
+#[test]
+fn overlapping_edits_are_rejected() {
+    let tree = cst::parse_lossless(SOURCE).unwrap();
+    let first = Span::new(10, 20);
+    let second = Span::new(15, 25);
+    assert_eq!(
+        tree.edit(&[(second, "b"), (first, "a")]),
+        Err(cst::EditError::Overlapping(first, second))
+    );
+    assert!(tree.edit(&[(first, "a"), (Span::new(20, 25), "b")]).is_ok());
+}
+
+#[test]
+fn invalid_edit_spans_are_rejected() {
+    let source = "s = \"é\";";
+    let tree = cst::parse_lossless(source).unwrap();
+    for &span in [
+        Span::new(5, 100),
+        Span::new(100, 200),
+        Span::new(6, 5),
+        Span::new(5, 6),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            tree.edit(&[(span, "x")]),
+            Err(cst::EditError::InvalidSpan(span))
+        );
+    }
+    assert_eq!(tree.edit(&[(Span::new(5, 7), "e")]).unwrap(), "s = \"e\";");
+}
+
+#[test]
+fn comments_outside_statements_are_unattached() {
+    let source = "module box(/* size */ s) {
+    cube(s);
+}
+x = [1, // one
+    2];
+translate([1, 0, 0]) // moved
+    cube();
+if (x) a(); /* then */ else b();
+";
+    let tree = cst::parse_lossless(source).unwrap();
+    let unattached: Vec<_> = tree.unattached_comments.iter().map(|c| c.text).collect();
+    assert_eq!(
+        unattached,
+        vec!["/* size */", "// one", "// moved", "/* then */"]
+    );
+
+    // The comment in the parameters is not moved into the body.
+    match &tree.statements[0].kind {
+        ast::StatementKind::ModuleDefinition { body, .. } => match &body.kind {
+            ast::StatementKind::StatementList(statements) => assert_eq!(statements.len(), 1),
+            other => panic!("unexpected body {:?}", other),
+        },
+        other => panic!("unexpected statement {:?}", other),
+    }
+    assert!(tree
+        .statements
+        .iter()
+        .all(|s| !matches!(s.kind, ast::StatementKind::Comment(_))));
+}
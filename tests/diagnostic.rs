@@ -0,0 +1,85 @@
+use rscad::diagnostic::{Code, Diagnostic, Diagnostics, Severity};
+use rscad::span::Span;
+
+fn reassignment() -> Diagnostic {
+    Diagnostic::warning(
+        Code::Reassignment,
+        "`a` was assigned twice in the same scope, only the last value is used",
+        Span::new(16, 22),
+    )
+    .with_note("first assigned here", Some(Span::new(0, 6)))
+}
+
+#[test]
+fn render_diagnostics() {
+    let source = "a = 1;\ncube(a);\na = 2;";
+    assert_eq!(
+        reassignment().render(source),
+        "warning[reassignment]: `a` was assigned twice in the same scope, only the last value is used
+ --> 3:1
+  |
+3 | a = 2;
+  | ^^^^^^
+  = note: first assigned here (1:1)"
+    );
+
+    let error = Diagnostic::error(
+        Code::UnknownVariable,
+        "unknown variable `b`",
+        Span::new(12, 13),
+    );
+    assert_eq!(error.severity, Severity::Error);
+    assert_eq!(
+        error.to_string(),
+        "error[unknown-variable]: unknown variable `b`"
+    );
+
+    let diagnostics = Diagnostics::from(vec![reassignment(), error]);
+    assert!(diagnostics.has_errors());
+    assert_eq!(
+        diagnostics.render(source),
+        format!(
+            "{}\n\n{}",
+            reassignment().render(source),
+            diagnostics.iter().nth(1).unwrap().render(source)
+        )
+    );
+}
+
+#[test]
+fn diagnostics_as_json() {
+    let source = "a = 1;\ncube(a);\na = 2;";
+    let mut diagnostics = Diagnostics::new();
+    diagnostics.push(reassignment());
+    diagnostics.push(Diagnostic::warning(
+        Code::UnknownField,
+        "unknown field `w`\n\"quoted\"",
+        Span::new(7, 11),
+    ));
+
+    let json: serde_json::Value = serde_json::from_str(&diagnostics.to_json(source)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {
+                "severity": "warning",
+                "code": "reassignment",
+                "message": "`a` was assigned twice in the same scope, only the last value is used",
+                "span": {"start": 16, "end": 22},
+                "line": 3,
+                "column": 1,
+                "notes": [{"message": "first assigned here", "span": {"start": 0, "end": 6}}],
+            },
+            {
+                "severity": "warning",
+                "code": "unknown-field",
+                "message": "unknown field `w`\n\"quoted\"",
+                "span": {"start": 7, "end": 11},
+                "line": 2,
+                "column": 1,
+                "notes": [],
+            },
+        ])
+    );
+    assert_eq!(Diagnostics::new().to_json(source), "[]");
+}
@@ -0,0 +1,342 @@
+use rscad::diagnostic::{Code, Diagnostics};
+use rscad::interpreter::{Aborted, Evaluator, Value};
+use rscad::parser::Id;
+
+/// Evaluates a document, and returns the value of its top-level variables, in order.
+fn eval(source: &str) -> (Result<Vec<Value>, Aborted>, Evaluator) {
+    let document = rscad::parser::parse_document(rscad::parse(source).unwrap(), Vec::new());
+    assert!(
+        document.diagnostics.is_empty(),
+        "{:?}",
+        document.diagnostics
+    );
+
+    let mut evaluator = Evaluator::new();
+    let values = evaluator.document(&document).map(|context| {
+        (0..document.scope.variables.len())
+            .map(|id| context.variable(&Id { depth: 0, id }).unwrap().clone())
+            .collect()
+    });
+    (values, evaluator)
+}
+
+fn values(source: &str) -> Vec<Value> {
+    let (values, evaluator) = eval(source);
+    assert!(
+        evaluator.diagnostics().is_empty(),
+        "{:?}",
+        evaluator.diagnostics()
+    );
+    values.unwrap()
+}
+
+fn value(source: &str) -> Value {
+    values(source).pop().unwrap()
+}
+
+fn codes(diagnostics: &Diagnostics) -> Vec<Code> {
+    diagnostics.iter().map(|d| d.code).collect()
+}
+
+fn number(x: f64) -> Value {
+    Value::Number(x)
+}
+
+fn numbers(xs: &[f64]) -> Value {
+    Value::Vector(xs.iter().copied().map(Value::Number).collect())
+}
+
+fn text(s: &str) -> Value {
+    Value::Text(s.to_string())
+}
+
+#[test]
+fn operators() {
+    assert_eq!(
+        values(
+            "a = 1 + 2 * 3; b = 2 ^ 6; c = -7 % 3; d = 1 / 0; e = !0 && (1 || (assert(false) 0));"
+        ),
+        vec![
+            number(7.0),
+            number(64.0),
+            number(-1.0),
+            number(f64::INFINITY),
+            Value::Bool(true),
+        ]
+    );
+    assert_eq!(
+        values(r#"a = "abc" < "abd"; b = "b" > "abc"; c = [1, 2] == [1, 2]; d = 1 == "1";"#),
+        vec![
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(true),
+            Value::Bool(false),
+        ]
+    );
+    assert_eq!(value("a = 0 / 0 < 1 || 0 / 0 >= 1;"), Value::Bool(false));
+    assert_eq!(value("a = (6 & 3) + (1 << 4) + (~0);"), number(17.0));
+}
+
+#[test]
+fn vector_operators() {
+    assert_eq!(value("a = [1, 2, 3] + [10, 20];"), numbers(&[11.0, 22.0]));
+    assert_eq!(
+        value("a = [[1, 2], 3] - [[1, 1], 4];"),
+        Value::Vector(vec![numbers(&[0.0, 1.0]), number(-1.0)])
+    );
+    assert_eq!(
+        value(r#"a = [1, "a"] + [1, 1];"#),
+        Value::Vector(vec![number(2.0), Value::Undef])
+    );
+    assert_eq!(value("a = -[1, -2];"), numbers(&[-1.0, 2.0]));
+    assert_eq!(
+        value("a = 2 * [1, [2]];"),
+        Value::Vector(vec![number(2.0), numbers(&[4.0])])
+    );
+    assert_eq!(value("a = [2, 4] / 2;"), numbers(&[1.0, 2.0]));
+    assert_eq!(value("a = 4 / [2, 4];"), numbers(&[2.0, 1.0]));
+
+    // Dot product, matrix * vector, vector * matrix and matrix * matrix.
+    assert_eq!(value("a = [1, 2, 3] * [4, 5, 6];"), number(32.0));
+    assert_eq!(
+        value("a = [[1, 2], [3, 4], [5, 6]] * [1, 1];"),
+        numbers(&[3.0, 7.0, 11.0])
+    );
+    assert_eq!(
+        value("a = [1, 1, 1] * [[1, 2], [3, 4], [5, 6]];"),
+        numbers(&[9.0, 12.0])
+    );
+    assert_eq!(
+        value("a = [[1, 2], [3, 4]] * [[5, 6], [7, 8]];"),
+        Value::Vector(vec![numbers(&[19.0, 22.0]), numbers(&[43.0, 50.0])])
+    );
+}
+
+#[test]
+fn undefined_operations() {
+    let (values, evaluator) =
+        eval(r#"a = 1 + "a"; b = [1, 2] * [1, 2, 3]; c = -"a"; d = true < 1;"#);
+    assert_eq!(values.unwrap(), vec![Value::Undef; 4]);
+    let messages: Vec<_> = evaluator
+        .diagnostics()
+        .iter()
+        .map(|d| (d.code, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (
+                Code::UndefinedOperation,
+                "undefined operation (number + string)"
+            ),
+            (
+                Code::UndefinedOperation,
+                "undefined operation (vector * vector)"
+            ),
+            (Code::UndefinedOperation, "undefined operation (-string)"),
+            (
+                Code::UndefinedOperation,
+                "undefined operation (bool < number)"
+            ),
+        ]
+    );
+
+    // Diagnostics point at the assignment.
+    let spans: Vec<_> = evaluator.diagnostics().iter().map(|d| d.span).collect();
+    assert_eq!(spans[0].start, 0);
+    assert_eq!(spans[1].start, 13);
+}
+
+#[test]
+fn indexing() {
+    assert_eq!(
+        values(
+            r#"v = [1, 2, 3]; a = v[1]; b = v[2.9]; c = v[3]; d = v[-1]; e = v.z; f = "héllo"[1];"#
+        ),
+        vec![
+            numbers(&[1.0, 2.0, 3.0]),
+            number(2.0),
+            number(3.0),
+            Value::Undef,
+            Value::Undef,
+            number(3.0),
+            text("é"),
+        ]
+    );
+    assert_eq!(value(r#"a = [0 : 2 : 10][1];"#), number(2.0));
+    assert_eq!(value(r#"a = 5[0];"#), Value::Undef);
+    // Negative indices are out of range, even above -1.
+    assert_eq!(value(r#"a = [1, 2, 3][-0.5];"#), Value::Undef);
+}
+
+#[test]
+fn ranges() {
+    assert_eq!(
+        value("a = [1 : 3];"),
+        Value::Range {
+            start: 1.0,
+            step: 1.0,
+            end: 3.0,
+        }
+    );
+    let (values, evaluator) = eval(r#"a = [3 : 1]; b = [0 : "a"];"#);
+    assert_eq!(
+        values.unwrap(),
+        vec![
+            Value::Range {
+                start: 1.0,
+                step: 1.0,
+                end: 3.0,
+            },
+            Value::Undef,
+        ]
+    );
+    assert_eq!(
+        codes(evaluator.diagnostics()),
+        vec![Code::InvalidRange, Code::InvalidRange]
+    );
+}
+
+#[test]
+fn functions() {
+    assert_eq!(
+        values(
+            "function f(x, y = 10) = x + y;
+            a = f(1);
+            b = f(y = 2, x = 1);
+"
+        ),
+        vec![number(11.0), number(3.0)]
+    );
+    assert_eq!(
+        value(
+            "function fact(n) = n <= 1 ? 1 : n * fact(n - 1);
+            a = fact(10);"
+        ),
+        number(3628800.0)
+    );
+
+    // Function literals capture their context, and can call themselves through it.
+    assert_eq!(
+        values(
+            "k = 3;
+            add = function(x) x + k;
+            fib = function(n) n < 2 ? n : fib(n - 1) + fib(n - 2);
+            a = add(1);
+            b = fib(10);
+            c = (function(f) f(2))(add);"
+        )[3..],
+        [number(4.0), number(55.0), number(5.0)]
+    );
+
+    // Special variables are looked up from the caller.
+    assert_eq!(
+        values("function f() = $fn; a = f(); b = f($fn = 8); $fn = 3; c = f();"),
+        vec![number(0.0), number(8.0), number(3.0), number(3.0)]
+    );
+}
+
+#[test]
+fn tail_calls_do_not_nest() {
+    assert_eq!(
+        value(
+            "function sum(n, acc = 0) = n == 0 ? acc : sum(n - 1, acc + n);
+            a = sum(100000);"
+        ),
+        number(5000050000.0)
+    );
+
+    // The stack grows as needed: this runs on the small stack of the test thread.
+    let (values, evaluator) = eval(
+        "function depth(n) = n == 0 ? 0 : 1 + depth(n - 1);
+        a = depth(999);
+        b = depth(5000);",
+    );
+    assert_eq!(values, Err(Aborted));
+    assert_eq!(codes(evaluator.diagnostics()), vec![Code::RecursionLimit]);
+}
+
+#[test]
+fn let_echo_and_assert() {
+    assert_eq!(
+        value("a = let (x = 1, y = x + 1, x = y * 10) [x, y];"),
+        numbers(&[20.0, 2.0])
+    );
+
+    let (values, evaluator) = eval(r#"a = echo("a", b = [1, 2.5]) 3;"#);
+    assert_eq!(values.unwrap(), vec![number(3.0)]);
+    assert_eq!(evaluator.echoes(), ["ECHO: \"a\", b = [1, 2.5]"]);
+
+    // Operations in function literals are echoed in parentheses, like OpenSCAD does.
+    let (_, evaluator) =
+        eval("f = function(x) x + 1; g = function(x) x ? -x : 2 * (x - 1); a = echo(f, g) 0;");
+    assert_eq!(
+        evaluator.echoes(),
+        ["ECHO: function(x) (x + 1), function(x) (x ? -x : (2 * (x - 1)))"]
+    );
+
+    assert_eq!(value("a = assert(true) 1;"), number(1.0));
+    let (values, evaluator) = eval(r#"a = 1; b = assert(a > 1, "a is too small") 2;"#);
+    assert_eq!(values, Err(Aborted));
+    let diagnostic = evaluator.diagnostics().iter().next().unwrap();
+    assert_eq!(diagnostic.code, Code::AssertionFailed);
+    assert_eq!(diagnostic.message, "assertion failed: a is too small");
+    assert!(evaluator.diagnostics().has_errors());
+}
+
+#[test]
+fn list_comprehensions() {
+    assert_eq!(
+        value("a = [for (i = [0 : 3]) if (i % 2 == 0) i * 10 else -i];"),
+        numbers(&[0.0, -1.0, 20.0, -3.0])
+    );
+    assert_eq!(
+        value("a = [for (i = [1 : 3], j = [i : 3]) [i, j]];"),
+        Value::Vector(vec![
+            numbers(&[1.0, 1.0]),
+            numbers(&[1.0, 2.0]),
+            numbers(&[1.0, 3.0]),
+            numbers(&[2.0, 2.0]),
+            numbers(&[2.0, 3.0]),
+            numbers(&[3.0, 3.0]),
+        ])
+    );
+    assert_eq!(
+        value(r#"a = [each [1, 2], each "ab", each [3 : 4], each 5, let (x = 6) x];"#),
+        Value::Vector(vec![
+            number(1.0),
+            number(2.0),
+            text("a"),
+            text("b"),
+            number(3.0),
+            number(4.0),
+            number(5.0),
+            number(6.0),
+        ])
+    );
+    assert_eq!(
+        value("a = [for (a = 0, b = 1; a < 20; a = b, b = a + b) a];"),
+        numbers(&[0.0, 1.0, 1.0, 2.0, 3.0, 5.0, 8.0, 13.0])
+    );
+
+    let (values, evaluator) = eval("a = [for (i = [0 : 0 : 1]) i];");
+    assert_eq!(values.unwrap(), vec![Value::Vector(vec![])]);
+    assert_eq!(codes(evaluator.diagnostics()), vec![Code::RangeTooLarge]);
+}
+
+#[test]
+fn unassigned_variables() {
+    let (values, evaluator) = eval("a = b; b = 1;");
+    assert_eq!(values.unwrap(), vec![Value::Undef, number(1.0)]);
+    assert_eq!(
+        codes(evaluator.diagnostics()),
+        vec![Code::UnassignedVariable]
+    );
+
+    let (values, evaluator) = eval("f = 1; a = f(2); b = $unknown;");
+    assert_eq!(values.unwrap()[1..], [Value::Undef, Value::Undef]);
+    assert_eq!(
+        codes(evaluator.diagnostics()),
+        vec![Code::NotAFunction, Code::UnknownVariable]
+    );
+}
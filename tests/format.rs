@@ -0,0 +1,215 @@
+use rscad::ast;
+use rscad::format::{self, BraceStyle, FormatOptions};
+use rscad::parse;
+
+const SOURCES: &[&str] = &[
+    "cube([1,2,3]);",
+    "a=1;b=a+2*3;c=(a+b)*c;d=a-(b-c);e=-(a+b);f=2^(3^4);g=(2^3)^4;h=(-2)^2;",
+    "x = a ? b : c ? d : e; y = (a ? b : c) + 1; z = !(a && b) || (c || d) && e;",
+    "x = a << 1 | b & ~c >> 2; y = (a | b) & c; z = a == (b < c);",
+    r#"s = "tab\there \"quoted\" back\\slash\nnewline";"#,
+    "n = [1, 0.5, 1e-7, 1.5e30, 0x1F, 1e400, 123456789012345678];",
+    "v = a[1].x[2](3)(4); w = (function(x) x)(2); f = function(x, y = 2) x + y;",
+    "e = echo(a) assert(b, \"msg\") let(c = 1, d = 2) c + d; l = let(a = 1) (let(b = 2) b);",
+    "r = [0:10]; s = [0:2:10]; t = [-1:-(1):a+b];",
+    "l = [for (i = [0:3], j = [i:3]) if (i % 2) i else each [i, j], let (k = 1) for (m = [0:k]) m];",
+    "l = [for (i = 0, j = 1; i < 10; i = i + 1, j = j * 2) [i, j], if (a) (if (b) 1) else 2];",
+    "module m(a, b = 1) { if (a) if (b) cube(); else sphere(); else { cylinder(); } }",
+    "for (i = [0:3]) translate([i, 0, 0]) { %cube(); #sphere(); !cylinder(); *square(); }",
+    "include <a/b.scad>\nuse <c.scad>\nmodule m() cube(); m() { ; } { a = 1; } let (a = 1) cube(a);",
+    "if (a) { } else if (b) x(); else if (c) { y(); } else z();",
+];
+
+fn round_trip(source: &str, options: &FormatOptions) {
+    let statements = parse(source).unwrap();
+    let formatted = format::format(&statements, options);
+    let reparsed = parse(&formatted)
+        .unwrap_or_else(|e| panic!("cannot parse formatted code:\n{}\n{}", formatted, e));
+    assert_eq!(statements, reparsed, "formatted code:\n{}", formatted);
+
+    // Formatting is deterministic and idempotent.
+    assert_eq!(formatted, format::format(&reparsed, options));
+}
+
+#[test]
+fn format_round_trips() {
+    for source in SOURCES {
+        round_trip(source, &FormatOptions::default());
+    }
+}
+
+#[test]
+fn format_round_trips_with_options() {
+    let options = FormatOptions {
+        indent_width: 2,
+        max_line_length: 20,
+        brace_style: BraceStyle::NextLine,
+        trailing_commas: true,
+    };
+    for source in SOURCES {
+        round_trip(source, &options);
+    }
+}
+
+#[test]
+fn format_layout() {
+    let source = "module box(size){translate([0,0,1])cube(size,center=true);if(size>1){sphere(1);}else cylinder(r=1,h=2);}";
+    let expected = "\
+module box(size) {
+    translate([0, 0, 1]) cube(size, center = true);
+    if (size > 1) {
+        sphere(1);
+    } else cylinder(r = 1, h = 2);
+}
+";
+    assert_eq!(
+        format::format_source(source, &FormatOptions::default()).unwrap(),
+        expected
+    );
+
+    let options = FormatOptions {
+        indent_width: 2,
+        brace_style: BraceStyle::NextLine,
+        ..FormatOptions::default()
+    };
+    let expected = "\
+module box(size)
+{
+  translate([0, 0, 1]) cube(size, center = true);
+  if (size > 1)
+  {
+    sphere(1);
+  }
+  else cylinder(r = 1, h = 2);
+}
+";
+    assert_eq!(format::format_source(source, &options).unwrap(), expected);
+}
+
+#[test]
+fn format_long_vectors() {
+    let source = "points = [[0, 0], [10, 0], [10, 10], [0, 10]];";
+    let options = FormatOptions {
+        max_line_length: 30,
+        ..FormatOptions::default()
+    };
+    let expected = "\
+points = [
+    [0, 0],
+    [10, 0],
+    [10, 10],
+    [0, 10]
+];
+";
+    assert_eq!(format::format_source(source, &options).unwrap(), expected);
+
+    let options = FormatOptions {
+        trailing_commas: true,
+        ..options
+    };
+    let expected = expected.replace("[0, 10]\n", "[0, 10],\n");
+    assert_eq!(format::format_source(source, &options).unwrap(), expected);
+
+    // Short vectors stay on one line.
+    let options = FormatOptions::default();
+    assert_eq!(
+        format::format_source(source, &options).unwrap(),
+        "points = [[0, 0], [10, 0], [10, 10], [0, 10]];\n"
+    );
+}
+
+#[test]
+fn format_keeps_comments() {
+    let source = "\
+// Header
+
+
+/* The size */
+size=1;   // trailing
+module box() {
+  // Inside
+  cube(size);
+}
+";
+    let expected = "\
+// Header
+
+/* The size */
+size = 1; // trailing
+module box() {
+    // Inside
+    cube(size);
+}
+";
+    let formatted = format::format_source(source, &FormatOptions::default()).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(parse(&formatted).unwrap(), parse(source).unwrap());
+}
+
+#[test]
+fn format_adds_needed_parentheses() {
+    use ast::ExprKind::{Op, Variable};
+    use ast::Opcode;
+
+    // Built by hand: `(a + b) * c`
+    let sum: ast::Expr = Op(
+        Box::new(Variable("a".into()).into()),
+        Opcode::Add,
+        Box::new(Variable("b".into()).into()),
+    )
+    .into();
+    let product: ast::Expr = Op(
+        Box::new(sum.clone()),
+        Opcode::Mul,
+        Box::new(Variable("c".into()).into()),
+    )
+    .into();
+    assert_eq!(format::format_expr(&product), "(a + b) * c");
+
+    // `a - (a + b)`
+    let difference: ast::Expr = Op(
+        Box::new(Variable("a".into()).into()),
+        Opcode::Sub,
+        Box::new(sum),
+    )
+    .into();
+    assert_eq!(format::format_expr(&difference), "a - (a + b)");
+    assert_eq!(format::echo_expr(&difference), "(a - (a + b))");
+}
+
+#[test]
+fn format_refuses_to_drop_comments() {
+    let cases = [
+        ("x = [1, // one\n    2];", "// one"),
+        ("x = 1 + /* two */ 2;", "/* two */"),
+        ("translate([1, 0, 0]) // moved\n    cube();", "// moved"),
+        ("if (a) b(); // then\nelse c();", "// then"),
+        ("module box(/* size */ s) { cube(s); }", "/* size */"),
+        ("cube(/* size */ 1);", "/* size */"),
+    ];
+    for &(source, comment) in cases.iter() {
+        let errors = match format::format_source(source, &FormatOptions::default()) {
+            Err(rscad::Error::Format(errors)) => errors,
+            other => panic!("{}: unexpected result {:?}", source, other),
+        };
+        assert_eq!(errors.len(), 1, "{}", source);
+        assert_eq!(errors[0].span.text(source), comment, "{}", source);
+    }
+
+    let error = format::format_source("a = 1;\nb = [2, /* c */ 3];", &FormatOptions::default())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "\
+error: cannot keep this comment when formatting
+ --> 2:9
+  |
+2 | b = [2, /* c */ 3];
+  |         ^^^^^^^ only comments between statements can be formatted"
+    );
+}
+
+#[test]
+fn format_rejects_invalid_code() {
+    assert!(format::format_source("cube(", &FormatOptions::default()).is_err());
+}
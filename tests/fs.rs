@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use rscad::fs::{FileSystem, MemoryFileSystem};
+
+#[test]
+fn memory_paths_are_normalized() {
+    let mut fs = MemoryFileSystem::new();
+    fs.insert("project/./lib/utils.scad", "x = 1;");
+
+    assert!(fs.is_file(Path::new("project/lib/utils.scad")));
+    assert!(fs.is_file(Path::new("project/model/../lib/utils.scad")));
+    assert!(!fs.is_file(Path::new("project/lib")));
+    assert_eq!(
+        fs.canonicalize(Path::new("./project/lib/../lib/utils.scad"))
+            .unwrap(),
+        PathBuf::from("project/lib/utils.scad")
+    );
+    assert_eq!(
+        fs.read_to_string(Path::new("project/lib/utils.scad"))
+            .unwrap(),
+        "x = 1;"
+    );
+
+    // Going above a relative root is kept, above the absolute root is not.
+    fs.insert("../shared.scad", "");
+    fs.insert("/../abs.scad", "");
+    assert!(fs.is_file(Path::new("a/../../shared.scad")));
+    assert!(fs.is_file(Path::new("/abs.scad")));
+}
+
+#[test]
+fn memory_errors() {
+    let mut fs = MemoryFileSystem::from(
+        vec![(PathBuf::from("a.scad"), "a = 1;".to_string())]
+            .into_iter()
+            .collect::<HashMap<_, _>>(),
+    );
+    fs.insert("binary.stl", vec![0xff, 0xfe]);
+
+    let missing = fs.read(Path::new("b.scad")).unwrap_err();
+    assert_eq!(missing.kind(), ErrorKind::NotFound);
+    assert_eq!(
+        fs.canonicalize(Path::new("b.scad")).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+    assert_eq!(
+        fs.read_to_string(Path::new("binary.stl"))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidData
+    );
+
+    assert_eq!(fs.remove("a.scad"), Some(b"a = 1;".to_vec()));
+    assert!(!fs.is_file(Path::new("a.scad")));
+}
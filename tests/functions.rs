@@ -0,0 +1,186 @@
+use rscad::diagnostic::Code;
+use rscad::interpreter::{Evaluator, Value};
+use rscad::parser::Id;
+
+/// Evaluates a document, and returns the value of its top-level variables, with the codes of
+/// the diagnostics.
+fn eval(source: &str) -> (Vec<Value>, Vec<Code>) {
+    let document = rscad::parser::parse_document(rscad::parse(source).unwrap(), Vec::new());
+    assert!(
+        document.diagnostics.is_empty(),
+        "{:?}",
+        document.diagnostics
+    );
+
+    let mut evaluator = Evaluator::new();
+    let context = evaluator.document(&document).unwrap();
+    let values = (0..document.scope.variables.len())
+        .map(|id| context.variable(&Id { depth: 0, id }).unwrap().clone())
+        .collect();
+    let codes = evaluator.diagnostics().iter().map(|d| d.code).collect();
+    (values, codes)
+}
+
+fn numbers(source: &str) -> Vec<f64> {
+    let (values, codes) = eval(source);
+    assert_eq!(codes, vec![]);
+    values
+        .into_iter()
+        .map(|value| match value {
+            Value::Number(x) => x,
+            value => panic!("not a number: {:?}", value),
+        })
+        .collect()
+}
+
+#[test]
+fn trigonometry() {
+    // Exact for multiples of 30° and 45°.
+    assert_eq!(
+        numbers(
+            "a = sin(180); b = sin(30); c = sin(-90); d = sin(720 + 150);
+            e = cos(90); f = cos(60); g = cos(-180); h = cos(270);"
+        ),
+        vec![0.0, 0.5, -1.0, 0.5, 0.0, 0.5, -1.0, 0.0]
+    );
+    assert_eq!(
+        numbers("a = tan(45); b = tan(135); c = tan(0); d = tan(90); e = sin(45) * sin(45);"),
+        vec![1.0, -1.0, 0.0, f64::INFINITY, 0.5000000000000001]
+    );
+    assert_eq!(
+        numbers("a = asin(1); b = acos(0.5); c = atan(1); d = atan2(1, -1); e = atan2(0, 0);"),
+        vec![90.0, 60.00000000000001, 45.0, 135.0, 0.0]
+    );
+    assert!(numbers("a = sin(1 / 0);")[0].is_nan());
+}
+
+#[test]
+fn arithmetic() {
+    assert_eq!(
+        numbers(
+            "a = abs(-2); b = ceil(1.2); c = floor(-1.2); d = round(2.5); e = round(-2.5);
+            f = sign(-3); g = sign(0); h = sqrt(16); i = pow(2, 10); j = exp(0);
+            k = ln(1); l = log(1000); m = log(2, 8);"
+        ),
+        vec![2.0, 2.0, -2.0, 3.0, -3.0, -1.0, 0.0, 4.0, 1024.0, 1.0, 0.0, 3.0, 3.0]
+    );
+
+    let (values, codes) = eval(r#"a = sin("a"); b = pow(2); c = abs(); d = sqrt(true);"#);
+    assert_eq!(values, vec![Value::Undef; 4]);
+    assert_eq!(codes, vec![Code::InvalidArgument; 4]);
+}
+
+#[test]
+fn min_and_max() {
+    assert_eq!(
+        numbers("a = min(3, 1, 2); b = max([3, 1, 2]); c = max(5); d = min([-1]);"),
+        vec![1.0, 3.0, 5.0, -1.0]
+    );
+
+    let (values, codes) = eval(r#"a = max([]); b = min(); c = max(1, "a"); d = min([1, [2]]);"#);
+    assert_eq!(values, vec![Value::Undef; 4]);
+    assert_eq!(codes, vec![Code::InvalidArgument; 4]);
+}
+
+#[test]
+fn vectors() {
+    assert_eq!(
+        numbers("a = norm([3, 4]); b = norm([]); c = cross([1, 2], [3, 4]);"),
+        vec![5.0, 0.0, -2.0]
+    );
+    let (values, codes) = eval("a = cross([1, 0, 0], [0, 1, 0]); b = cross([1, 2], [1, 2, 3]);");
+    assert_eq!(
+        values,
+        vec![
+            Value::Vector(vec![
+                Value::Number(0.0),
+                Value::Number(0.0),
+                Value::Number(1.0)
+            ]),
+            Value::Undef,
+        ]
+    );
+    assert_eq!(codes, vec![Code::InvalidArgument]);
+}
+
+#[test]
+fn random_numbers() {
+    // Same numbers as `std::uniform_real_distribution` with `std::mt19937`.
+    assert_eq!(
+        numbers("a = rands(0, 1, 1, 5489)[0];"),
+        vec![0.1354770042967805]
+    );
+
+    let (values, codes) = eval(
+        "a = rands(5, -5, 100, 42); b = rands(5, -5, 100, 42); c = rands(0, 1, 3); d = rands(0, 1);",
+    );
+    assert_eq!(values[0], values[1]);
+    let in_bounds = |value: &Value, min: f64, max: f64| match value {
+        Value::Vector(values) => values
+            .iter()
+            .all(|value| matches!(value, Value::Number(x) if (min..max).contains(x))),
+        _ => false,
+    };
+    assert!(in_bounds(&values[0], -5.0, 5.0));
+    assert!(in_bounds(&values[2], 0.0, 1.0));
+    assert_eq!(values[3], Value::Undef);
+    assert_eq!(codes, vec![Code::InvalidArgument]);
+}
+
+#[test]
+fn named_arguments() {
+    assert_eq!(
+        numbers(
+            "a = round(x = 1.5); b = pow(exponent = 3, base = 2); c = atan2(x = -1, y = 1);
+            d = log(x = 100); e = log(base = 2, x = 8); f = sin(30, $fn = 3);"
+        ),
+        vec![2.0, 8.0, 135.0, 2.0, 3.0, 0.5]
+    );
+
+    let (values, codes) = eval(
+        "a = rands(seed_value = 42, value_count = 3, max_value = 1, min_value = 0);
+        b = rands(0, 1, 3, 42);",
+    );
+    assert_eq!(values[0], values[1]);
+    assert_eq!(codes, vec![]);
+}
+
+#[test]
+fn unknown_and_missing_arguments() {
+    let (values, codes) = eval(
+        "a = sin(x = 30, x = 60); b = max(3, a = 5); c = sin(y = 30); d = log(base = 2);
+        e = sin(1, 2);",
+    );
+    assert_eq!(
+        values,
+        vec![
+            Value::Number(60f64.to_radians().sin()),
+            Value::Number(3.0),
+            Value::Undef,
+            Value::Undef,
+            Value::Undef
+        ]
+    );
+    assert_eq!(
+        codes,
+        vec![
+            Code::DuplicateArgument,
+            Code::UnknownArgument,
+            Code::UnknownArgument,
+            Code::InvalidArgument,
+            Code::InvalidArgument,
+            Code::InvalidArgument,
+        ]
+    );
+}
+
+#[test]
+fn user_functions_come_first() {
+    let (values, codes) =
+        eval("function sin(x) = x; a = sin(30); f = function(x) cos(x); b = f(60);");
+    assert_eq!(
+        (&values[0], &values[2]),
+        (&Value::Number(30.0), &Value::Number(0.5))
+    );
+    assert_eq!(codes, vec![]);
+}
@@ -0,0 +1,424 @@
+use rscad::csg::{CsgNode, Fragments, IDENTITY};
+use rscad::diagnostic::Code;
+use rscad::interpreter::Output;
+
+fn instantiate(source: &str) -> Output {
+    let document = rscad::parser::parse_document(rscad::parse(source).unwrap(), Vec::new());
+    assert!(
+        document.diagnostics.is_empty(),
+        "{:?}",
+        document.diagnostics
+    );
+    rscad::interpreter::instantiate(&document)
+}
+
+/// Returns the top-level nodes, checking there were no problems.
+fn nodes(source: &str) -> Vec<CsgNode> {
+    let output = instantiate(source);
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    match output.root {
+        Some(CsgNode::Group(nodes)) => nodes,
+        root => panic!("unexpected root: {:?}", root),
+    }
+}
+
+fn node(source: &str) -> CsgNode {
+    let mut nodes = nodes(source);
+    assert_eq!(nodes.len(), 1, "{:?}", nodes);
+    nodes.pop().unwrap()
+}
+
+fn cube(size: f64) -> CsgNode {
+    CsgNode::Cube {
+        size: [size; 3],
+        center: false,
+    }
+}
+
+fn sphere(r: f64) -> CsgNode {
+    CsgNode::Sphere {
+        r,
+        fragments: Fragments::default(),
+    }
+}
+
+fn matrix(rows: [[f64; 3]; 3], translation: [f64; 3]) -> [[f64; 4]; 4] {
+    let mut matrix = IDENTITY;
+    for i in 0..3 {
+        matrix[i][..3].copy_from_slice(&rows[i]);
+        matrix[i][3] = translation[i];
+    }
+    matrix
+}
+
+#[test]
+fn primitives() {
+    assert_eq!(
+        nodes(
+            "cube([1, 2, 3], center = true);
+            cube();
+            sphere(d = 4);
+            cylinder(h = 2, r1 = 1, d2 = 6, center = true);
+            cylinder(5, r = 2);
+            square(2);
+            circle(3);
+            polygon([[0, 0], [1, 0], [0, 1]]);
+            polyhedron([[0, 0, 0], [1, 0, 0], [0, 1, 0], [0, 0, 1]], [[0, 1, 2], [0, 3, 1]]);"
+        ),
+        vec![
+            CsgNode::Cube {
+                size: [1.0, 2.0, 3.0],
+                center: true,
+            },
+            cube(1.0),
+            sphere(2.0),
+            CsgNode::Cylinder {
+                h: 2.0,
+                r1: 1.0,
+                r2: 3.0,
+                center: true,
+                fragments: Fragments::default(),
+            },
+            CsgNode::Cylinder {
+                h: 5.0,
+                r1: 2.0,
+                r2: 2.0,
+                center: false,
+                fragments: Fragments::default(),
+            },
+            CsgNode::Square {
+                size: [2.0, 2.0],
+                center: false,
+            },
+            CsgNode::Circle {
+                r: 3.0,
+                fragments: Fragments::default(),
+            },
+            CsgNode::Polygon {
+                points: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+                paths: None,
+                convexity: 1,
+            },
+            CsgNode::Polyhedron {
+                points: vec![
+                    [0.0, 0.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                    [0.0, 0.0, 1.0],
+                ],
+                faces: vec![vec![0, 1, 2], vec![0, 3, 1]],
+                convexity: 1,
+            },
+        ]
+    );
+}
+
+#[test]
+fn transformations() {
+    assert_eq!(
+        node("translate([1, 2]) scale(2) cube();"),
+        CsgNode::Multmatrix {
+            matrix: matrix(
+                [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                [1.0, 2.0, 0.0]
+            ),
+            children: vec![CsgNode::Multmatrix {
+                matrix: matrix(
+                    [[2.0, 0.0, 0.0], [0.0, 2.0, 0.0], [0.0, 0.0, 2.0]],
+                    [0.0; 3]
+                ),
+                children: vec![cube(1.0)],
+            }],
+        }
+    );
+
+    // Rotations by multiples of 90° are exact.
+    assert_eq!(
+        node("rotate(90) cube();"),
+        CsgNode::Multmatrix {
+            matrix: matrix(
+                [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+                [0.0; 3]
+            ),
+            children: vec![cube(1.0)],
+        }
+    );
+    assert_eq!(
+        node("rotate([90, 0, 90]) cube();"),
+        CsgNode::Multmatrix {
+            matrix: matrix(
+                [[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                [0.0; 3]
+            ),
+            children: vec![cube(1.0)],
+        }
+    );
+    assert_eq!(
+        node("rotate(180, [1, 0, 0]) cube();"),
+        CsgNode::Multmatrix {
+            matrix: matrix(
+                [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]],
+                [0.0; 3]
+            ),
+            children: vec![cube(1.0)],
+        }
+    );
+    assert_eq!(
+        node("mirror([1, 1, 0]) cube();"),
+        CsgNode::Multmatrix {
+            matrix: matrix(
+                [[0.0, -1.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+                [0.0; 3]
+            ),
+            children: vec![cube(1.0)],
+        }
+    );
+}
+
+#[test]
+fn extrusions_and_fragments() {
+    let fragments = Fragments {
+        number: 6.0,
+        angle: 12.0,
+        size: 0.5,
+    };
+    assert_eq!(
+        nodes(
+            r#"$fs = 0.5;
+            linear_extrude(5, twist = 90, scale = 2, $fn = 6) circle(1);
+            rotate_extrude(angle = 400) translate([2, 0]) square();
+            hull() { sphere(); cube(); }
+            minkowski() cube();
+            import("part.stl");"#
+        ),
+        vec![
+            CsgNode::LinearExtrude {
+                height: 5.0,
+                center: false,
+                convexity: 1,
+                twist: Some(90.0),
+                slices: None,
+                scale: [2.0, 2.0],
+                fragments,
+                children: vec![CsgNode::Circle { r: 1.0, fragments }],
+            },
+            CsgNode::RotateExtrude {
+                angle: 360.0,
+                convexity: 2,
+                fragments: Fragments {
+                    number: 0.0,
+                    ..fragments
+                },
+                children: vec![CsgNode::Multmatrix {
+                    matrix: matrix(
+                        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+                        [2.0, 0.0, 0.0]
+                    ),
+                    children: vec![CsgNode::Square {
+                        size: [1.0, 1.0],
+                        center: false,
+                    }],
+                }],
+            },
+            CsgNode::Hull(vec![
+                CsgNode::Sphere {
+                    r: 1.0,
+                    fragments: Fragments {
+                        number: 0.0,
+                        ..fragments
+                    },
+                },
+                cube(1.0),
+            ]),
+            CsgNode::Minkowski {
+                convexity: 0,
+                children: vec![cube(1.0)],
+            },
+            CsgNode::Import {
+                file: "part.stl".to_string(),
+                layer: String::new(),
+                origin: [0.0, 0.0],
+                scale: 1.0,
+                convexity: 1,
+                fragments: Fragments {
+                    number: 0.0,
+                    ..fragments
+                },
+            },
+        ]
+    );
+}
+
+#[test]
+fn colors_and_booleans() {
+    assert_eq!(
+        nodes(
+            r##"color("Red", 0.5) cube();
+            color([0, 0.5]) cube();
+            color("#00f") cube();
+            difference() { cube(); sphere(); }"##
+        ),
+        vec![
+            CsgNode::Color {
+                rgba: [1.0, 0.0, 0.0, 0.5],
+                children: vec![cube(1.0)],
+            },
+            CsgNode::Color {
+                rgba: [0.0, 0.5, 1.0, 1.0],
+                children: vec![cube(1.0)],
+            },
+            CsgNode::Color {
+                rgba: [0.0, 0.0, 1.0, 1.0],
+                children: vec![cube(1.0)],
+            },
+            CsgNode::Difference(vec![cube(1.0), sphere(1.0)]),
+        ]
+    );
+
+    let output = instantiate(r#"color("nocolor") cube();"#);
+    assert_eq!(
+        output.root,
+        Some(CsgNode::Group(vec![CsgNode::Color {
+            rgba: [-1.0, -1.0, -1.0, 1.0],
+            children: vec![cube(1.0)],
+        }]))
+    );
+    let codes: Vec<_> = output.diagnostics.iter().map(|d| d.code).collect();
+    assert_eq!(codes, vec![Code::InvalidArgument]);
+}
+
+#[test]
+fn user_modules() {
+    assert_eq!(
+        node(
+            "size = 2;
+            module m(s = size) { n = $children; cube(s); children(n - 1); }
+            m(3) { sphere(1); sphere(2); }"
+        ),
+        CsgNode::Group(vec![cube(3.0), CsgNode::Group(vec![sphere(2.0)])])
+    );
+
+    // Children are evaluated where they are written, with the special variables of where
+    // `children()` is called.
+    assert_eq!(
+        node(
+            "module outer() inner() children([1, 0]);
+            module inner() { $fn = 5; children(0); }
+            r = 4;
+            outer() { cube(r); cube($fn); }"
+        ),
+        CsgNode::Group(vec![CsgNode::Group(vec![CsgNode::Group(vec![
+            CsgNode::Group(vec![cube(5.0), cube(4.0),])
+        ])])])
+    );
+
+    let output = instantiate("module m() children(2); m() cube();");
+    let codes: Vec<_> = output.diagnostics.iter().map(|d| d.code).collect();
+    assert_eq!(codes, vec![Code::InvalidArgument]);
+}
+
+#[test]
+fn control_flow() {
+    assert_eq!(
+        nodes(
+            "if (1 > 2) cube(); else sphere();
+            for (i = [1 : 2], j = [i : 2]) cube(i * 10 + j);
+            intersection_for (r = [1, 2]) sphere(r);
+            let (a = 1, b = a + 1) cube(b);"
+        ),
+        vec![
+            CsgNode::Group(vec![sphere(1.0)]),
+            CsgNode::Group(vec![cube(11.0), cube(12.0), cube(22.0)]),
+            CsgNode::Intersection(vec![
+                CsgNode::Group(vec![sphere(1.0)]),
+                CsgNode::Group(vec![sphere(2.0)]),
+            ]),
+            CsgNode::Group(vec![cube(2.0)]),
+        ]
+    );
+}
+
+#[test]
+fn bodies_can_assign_loop_and_let_variables() {
+    let output = instantiate(
+        "for (i = [0 : 2]) { i = 5; echo(i); }
+        let (a = 1, b = a + 1) { a = 10; echo(a, b); }",
+    );
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    assert_eq!(
+        output.echoes,
+        ["ECHO: 5", "ECHO: 5", "ECHO: 5", "ECHO: 10, 2"]
+    );
+}
+
+#[test]
+fn calls_look_for_functions_then_variables_in_each_scope() {
+    let output = instantiate(
+        "function f(x) = 1;
+        module m() { f = function(x) 2; echo(f(0)); }
+        m();
+        sin = function(x) 42;
+        echo(sin(0));",
+    );
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    assert_eq!(output.echoes, ["ECHO: 2", "ECHO: 42"]);
+}
+
+#[test]
+fn modifiers() {
+    assert_eq!(
+        nodes("*cube(); #cube(2); %sphere();"),
+        vec![
+            CsgNode::Highlight(Box::new(cube(2.0))),
+            CsgNode::Background(Box::new(sphere(1.0))),
+        ]
+    );
+
+    let output = instantiate("cube(); translate([1, 0, 0]) !sphere(); !cube(2);");
+    assert_eq!(output.root, Some(sphere(1.0)));
+}
+
+#[test]
+fn echo_assert_and_unknown_modules() {
+    let output = instantiate(
+        r#"module m() echo($fn) children();
+        m($fn = 6) echo("child") cube();
+        frobnicate() cube();"#,
+    );
+    assert_eq!(output.echoes, ["ECHO: 6", "ECHO: \"child\""]);
+    let codes: Vec<_> = output.diagnostics.iter().map(|d| d.code).collect();
+    assert_eq!(codes, vec![Code::UnknownModule]);
+    assert_eq!(
+        output.root,
+        Some(CsgNode::Group(vec![CsgNode::Group(vec![CsgNode::Group(
+            vec![CsgNode::Group(vec![CsgNode::Group(vec![cube(1.0)])])]
+        )])]))
+    );
+
+    let output = instantiate("cube(); assert(false, \"stop\") sphere();");
+    assert_eq!(output.root, None);
+    assert!(output.diagnostics.has_errors());
+}
+
+#[test]
+fn recursion_limit() {
+    // The stack grows as needed: this runs on the small stack of the test thread.
+    let output = instantiate(
+        "module r(n) { if (n > 0) r(n - 1) children(); else children(); }
+        r(999) cube();",
+    );
+    assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+    let mut node = output.root.unwrap();
+    let mut depth = 0;
+    while let CsgNode::Group(mut nodes) = node {
+        assert_eq!(nodes.len(), 1);
+        node = nodes.pop().unwrap();
+        depth += 1;
+    }
+    assert_eq!((depth, node), (3001, cube(1.0)));
+
+    let output = instantiate("module r(n) { if (n > 0) r(n - 1); } r(5000);");
+    assert_eq!(output.root, None);
+    let codes: Vec<_> = output.diagnostics.iter().map(|d| d.code).collect();
+    assert_eq!(codes, vec![Code::RecursionLimit]);
+}
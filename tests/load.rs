@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use rscad::fs::MemoryFileSystem;
+use rscad::load::Loader;
+use rscad::parser::{Expr, ItemType, LibraryId};
+use rscad::{Error, LoadErrorKind};
+
+/// Creates a fresh directory with the given files.
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("rscad-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (path, content) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    root.canonicalize().unwrap()
+}
+
+#[test]
+fn include_is_textual() {
+    let root = project(
+        "include",
+        &[
+            (
+                "main.scad",
+                "include <parts/config.scad>\nsize = 2;\nbox();",
+            ),
+            (
+                "parts/config.scad",
+                "include <common.scad>\nsize = 1;\nmodule box() cube(size);",
+            ),
+            ("parts/common.scad", "wall = 0.5;"),
+        ],
+    );
+
+    let document = Loader::new().load(root.join("main.scad")).unwrap();
+    let scope = &document.scope;
+
+    // `size` is assigned again after the include: the last value wins.
+    assert_eq!(scope.variables, vec![Expr::Number(0.5), Expr::Number(2.0)]);
+    assert!(scope.includes.is_empty());
+    assert_eq!(scope.modules.len(), 1);
+    assert!(matches!(scope.items[0].ty, ItemType::User(_)));
+}
+
+#[test]
+fn use_imports_modules_and_functions() {
+    let root = project(
+        "use",
+        &[
+            (
+                "main.scad",
+                "use <lib.scad>\nmodule local() sphere();\nlocal();\nshape(r = twice(1));",
+            ),
+            (
+                "lib.scad",
+                "r = 3;\nfunction twice(x) = 2 * x;\nmodule shape(r) circle(r);\nshape();\nmodule local() cube();",
+            ),
+        ],
+    );
+
+    let document = Loader::new().load(root.join("main.scad")).unwrap();
+    assert_eq!(document.libraries.len(), 1);
+    assert_eq!(document.scope.uses, vec!["lib.scad".to_string()]);
+
+    // Local modules come first, then those of the library.
+    let items = &document.scope.items;
+    assert!(matches!(items[0].ty, ItemType::User(_)));
+    assert_eq!(
+        items[1].ty,
+        ItemType::Library(LibraryId { library: 0, id: 0 })
+    );
+    assert_eq!(
+        items[1].params[0].value,
+        Expr::LibraryFunction(
+            LibraryId { library: 0, id: 0 },
+            vec![rscad::parser::ParameterValue {
+                name: None,
+                value: Expr::Number(1.0),
+                span: Default::default(),
+            }]
+        )
+    );
+
+    // Variables and items of the library are not imported.
+    assert!(document.scope.variables.is_empty());
+    assert_eq!(items.len(), 2);
+}
+
+#[test]
+fn files_are_cached() {
+    let root = project(
+        "cache",
+        &[
+            ("a.scad", "use <lib.scad>\nf();"),
+            ("b.scad", "use <lib.scad>\ng();"),
+            ("lib.scad", "module f() cube();\nmodule g() sphere();"),
+        ],
+    );
+
+    let mut loader = Loader::new();
+    let a = loader.load(root.join("a.scad")).unwrap();
+    let b = loader.load(root.join("b.scad")).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&a.libraries[0], &b.libraries[0]));
+    assert!(std::sync::Arc::ptr_eq(
+        &a,
+        &loader.load(root.join("a.scad")).unwrap()
+    ));
+}
+
+#[test]
+fn search_library_paths() {
+    let root = project(
+        "library-path",
+        &[
+            ("model/main.scad", "use <shapes.scad>\nstar();"),
+            ("libraries/shapes.scad", "module star() circle();"),
+        ],
+    );
+
+    let mut loader = Loader::new();
+    match loader.load(root.join("model/main.scad")) {
+        Err(Error::Load(error)) => {
+            assert_eq!(
+                error.kind,
+                LoadErrorKind::NotFound("shapes.scad".to_string())
+            );
+            assert_eq!(error.chain, vec![root.join("model/main.scad")]);
+        }
+        other => panic!("expected a load error, got {:?}", other),
+    }
+
+    loader.add_library_path(root.join("libraries"));
+    let document = loader.load(root.join("model/main.scad")).unwrap();
+    assert!(matches!(document.scope.items[0].ty, ItemType::Library(_)));
+}
+
+#[test]
+fn include_cycles_are_errors() {
+    let root = project(
+        "cycle",
+        &[
+            ("main.scad", "include <a.scad>"),
+            ("a.scad", "include <b.scad>"),
+            ("b.scad", "x = 1;\ninclude <a.scad>"),
+        ],
+    );
+
+    let error = match Loader::new().load(root.join("main.scad")) {
+        Err(Error::Load(error)) => error,
+        other => panic!("expected a load error, got {:?}", other),
+    };
+    assert_eq!(error.kind, LoadErrorKind::Cycle(root.join("a.scad")));
+    assert_eq!(
+        error.chain,
+        vec![
+            root.join("main.scad"),
+            root.join("a.scad"),
+            root.join("b.scad")
+        ]
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "error: {} includes itself\n  in {}\n  loaded from {}\n  loaded from {}",
+            root.join("a.scad").display(),
+            root.join("b.scad").display(),
+            root.join("a.scad").display(),
+            root.join("main.scad").display(),
+        )
+    );
+}
+
+#[test]
+fn mutual_uses_are_allowed() {
+    let root = project(
+        "mutual-use",
+        &[
+            ("a.scad", "use <b.scad>\nmodule a() cube();\nb();"),
+            ("b.scad", "use <a.scad>\nmodule b() sphere();\na();"),
+        ],
+    );
+
+    let mut loader = Loader::new();
+    let a = loader.load(root.join("a.scad")).unwrap();
+    let b = &a.libraries[0];
+    assert!(matches!(a.scope.items[0].ty, ItemType::Library(_)));
+    assert!(matches!(b.scope.items[0].ty, ItemType::Library(_)));
+    assert!(b.libraries[0].modules.contains_key("a"));
+
+    // The complete document replaces the one its library sees.
+    assert_eq!(a.libraries.len(), 1);
+    assert!(std::sync::Arc::ptr_eq(
+        &a,
+        &loader.load(root.join("a.scad")).unwrap()
+    ));
+}
+
+#[test]
+fn parse_errors_name_the_file() {
+    let root = project(
+        "parse-error",
+        &[("main.scad", "use <bad.scad>"), ("bad.scad", "cube(;")],
+    );
+
+    match Loader::new().load(root.join("main.scad")) {
+        Err(Error::Load(error)) => match error.kind {
+            LoadErrorKind::Parse(path, errors) => {
+                assert_eq!(path, root.join("bad.scad"));
+                assert_eq!(errors.len(), 1);
+            }
+            kind => panic!("expected a parse error, got {:?}", kind),
+        },
+        other => panic!("expected a load error, got {:?}", other),
+    }
+}
+
+#[test]
+fn load_from_memory() {
+    let files: HashMap<PathBuf, String> = vec![
+        (
+            "/project/main.scad",
+            "include <parts/box.scad>\nuse <shapes.scad>\nbox();\nstar();",
+        ),
+        (
+            "/project/parts/box.scad",
+            "include <../config.scad>\nmodule box() cube(size);",
+        ),
+        ("/project/config.scad", "size = 10;"),
+        ("/libraries/shapes.scad", "module star() circle();"),
+    ]
+    .into_iter()
+    .map(|(path, content)| (PathBuf::from(path), content.to_string()))
+    .collect();
+
+    let mut loader = Loader::with_file_system(MemoryFileSystem::from(files));
+    loader.add_library_path("/libraries");
+    let document = loader.load("/project/./main.scad").unwrap();
+
+    assert_eq!(document.scope.variables, vec![Expr::Number(10.0)]);
+    assert!(matches!(document.scope.items[0].ty, ItemType::User(_)));
+    assert!(matches!(document.scope.items[1].ty, ItemType::Library(_)));
+
+    match loader.load("/project/missing.scad") {
+        Err(Error::Load(error)) => assert_eq!(
+            error.kind,
+            LoadErrorKind::NotFound("/project/missing.scad".to_string())
+        ),
+        other => panic!("expected a load error, got {:?}", other),
+    }
+}
@@ -1,19 +1,22 @@
 use rscad::ast;
 use rscad::parse;
+use rscad::span;
 
 fn cube<'a>() -> ast::Statement<'a> {
-    ast::Statement::ModuleCall(ast::ModuleCall {
-        function: "cube",
+    ast::StatementKind::ModuleCall(ast::ModuleCall {
+        function: "cube".into(),
         params: vec![ast::ParameterValue {
             name: None,
-            value: ast::Expr::Vector(vec![
-                ast::Expr::Number(1f32),
-                ast::Expr::Number(2f32),
-                ast::Expr::Number(3f32),
-            ]),
+            value: ast::ExprKind::Vector(vec![
+                ast::ExprKind::Number(1f64).into(),
+                ast::ExprKind::Number(2f64).into(),
+                ast::ExprKind::Number(3f64).into(),
+            ])
+            .into(),
         }],
-        child: Box::new(ast::Statement::NoOp),
+        child: Box::new(ast::StatementKind::NoOp.into()),
     })
+    .into()
 }
 
 #[test]
@@ -43,10 +46,7 @@ fn fail_on_bad_modifiers() {
 fn parse_modifiers() {
     assert_eq!(
         parse("%cube([1,2,3]);").unwrap(),
-        vec![ast::Statement::Modifier(
-            ast::Modifier::Transparent,
-            Box::new(cube()),
-        )],
+        vec![ast::StatementKind::Modifier(ast::Modifier::Transparent, Box::new(cube()),).into()],
     );
 }
 
@@ -64,29 +64,35 @@ fn parse_module_child() {
             "#
         )
         .unwrap(),
-        vec![ast::Statement::ModuleCall(ast::ModuleCall {
-            function: "translate",
+        vec![ast::StatementKind::ModuleCall(ast::ModuleCall {
+            function: "translate".into(),
             params: vec![ast::ParameterValue {
                 name: None,
-                value: ast::Expr::Vector(vec![
-                    ast::Expr::Number(1.0),
-                    ast::Expr::Number(2.0),
-                    ast::Expr::Number(3.0),
-                ]),
+                value: ast::ExprKind::Vector(vec![
+                    ast::ExprKind::Number(1.0).into(),
+                    ast::ExprKind::Number(2.0).into(),
+                    ast::ExprKind::Number(3.0).into(),
+                ])
+                .into(),
             }],
-            child: Box::new(ast::Statement::ModuleCall(ast::ModuleCall {
-                function: "cube",
-                params: vec![ast::ParameterValue {
-                    name: None,
-                    value: ast::Expr::Vector(vec![
-                        ast::Expr::Number(4.0),
-                        ast::Expr::Number(5.0),
-                        ast::Expr::Number(6.0),
-                    ]),
-                }],
-                child: Box::new(ast::Statement::NoOp),
-            })),
-        })],
+            child: Box::new(
+                ast::StatementKind::ModuleCall(ast::ModuleCall {
+                    function: "cube".into(),
+                    params: vec![ast::ParameterValue {
+                        name: None,
+                        value: ast::ExprKind::Vector(vec![
+                            ast::ExprKind::Number(4.0).into(),
+                            ast::ExprKind::Number(5.0).into(),
+                            ast::ExprKind::Number(6.0).into(),
+                        ])
+                        .into(),
+                    }],
+                    child: Box::new(ast::StatementKind::NoOp.into()),
+                })
+                .into()
+            ),
+        })
+        .into()],
     );
 }
 
@@ -101,11 +107,12 @@ fn parse_module_definition() {
             "#,
         )
         .unwrap(),
-        vec![ast::Statement::ModuleDefinition {
-            name: "foo",
+        vec![ast::StatementKind::ModuleDefinition {
+            name: "foo".into(),
             args: vec![],
-            body: Box::new(ast::Statement::StatementList(vec![cube()])),
-        }],
+            body: Box::new(ast::StatementKind::StatementList(vec![cube()]).into()),
+        }
+        .into()],
     );
 }
 
@@ -147,30 +154,36 @@ fn parse_list_comprehension() {
             "#
         )
         .unwrap(),
-        vec![ast::Statement::VariableDeclaration(
-            "a",
-            ast::Expr::ListComprehension {
-                lets: vec![ast::Let {
-                    vars: vec![ast::ParameterValue {
-                        name: Some("n"),
-                        value: ast::Expr::Number(5.0),
-                    }],
-                }],
-                variables: vec![ast::ParameterValue {
-                    name: Some("i"),
-                    value: ast::Expr::Range {
-                        start: Box::new(ast::Expr::Number(1.0)),
-                        end: Box::new(ast::Expr::Variable("n")),
-                        increment: None,
-                    },
+        vec![ast::StatementKind::VariableDeclaration(
+            "a".into(),
+            ast::ExprKind::ListComprehension(vec![ast::ListElement::Let {
+                vars: vec![ast::ParameterValue {
+                    name: Some("n".into()),
+                    value: ast::ExprKind::Number(5.0).into(),
                 }],
-                body: Box::new(ast::Expr::Op(
-                    Box::new(ast::Expr::Variable("i")),
-                    ast::Opcode::Mul,
-                    Box::new(ast::Expr::Variable("i")),
-                )),
-            }
-        )],
+                body: Box::new(ast::ListElement::For {
+                    variables: vec![ast::ParameterValue {
+                        name: Some("i".into()),
+                        value: ast::ExprKind::Range {
+                            start: Box::new(ast::ExprKind::Number(1.0).into()),
+                            end: Box::new(ast::ExprKind::Variable("n".into()).into()),
+                            increment: None,
+                        }
+                        .into(),
+                    }],
+                    body: Box::new(ast::ListElement::Expr(
+                        ast::ExprKind::Op(
+                            Box::new(ast::ExprKind::Variable("i".into()).into()),
+                            ast::Opcode::Mul,
+                            Box::new(ast::ExprKind::Variable("i".into()).into()),
+                        )
+                        .into(),
+                    )),
+                }),
+            }])
+            .into()
+        )
+        .into()],
     );
 }
 
@@ -183,22 +196,30 @@ fn fail_on_nested_comments() {
 fn parse_ternary() {
     assert_eq!(
         parse("a = 1 > 2 ? 1 + 2 : 3;").unwrap(),
-        vec![ast::Statement::VariableDeclaration(
-            "a",
-            ast::Expr::Ternary {
-                condition: Box::new(ast::Expr::Op(
-                    Box::new(ast::Expr::Number(1.0)),
-                    ast::Opcode::Gt,
-                    Box::new(ast::Expr::Number(2.0)),
-                )),
-                if_true: Box::new(ast::Expr::Op(
-                    Box::new(ast::Expr::Number(1.0)),
-                    ast::Opcode::Add,
-                    Box::new(ast::Expr::Number(2.0)),
-                )),
-                if_false: Box::new(ast::Expr::Number(3.0)),
+        vec![ast::StatementKind::VariableDeclaration(
+            "a".into(),
+            ast::ExprKind::Ternary {
+                condition: Box::new(
+                    ast::ExprKind::Op(
+                        Box::new(ast::ExprKind::Number(1.0).into()),
+                        ast::Opcode::Gt,
+                        Box::new(ast::ExprKind::Number(2.0).into()),
+                    )
+                    .into()
+                ),
+                if_true: Box::new(
+                    ast::ExprKind::Op(
+                        Box::new(ast::ExprKind::Number(1.0).into()),
+                        ast::Opcode::Add,
+                        Box::new(ast::ExprKind::Number(2.0).into()),
+                    )
+                    .into()
+                ),
+                if_false: Box::new(ast::ExprKind::Number(3.0).into()),
             }
-        )],
+            .into()
+        )
+        .into()],
     );
 }
 
@@ -213,21 +234,25 @@ fn parse_array() {
         )
         .unwrap(),
         vec![
-            ast::Statement::VariableDeclaration(
-                "a",
-                ast::Expr::Vector(vec![
-                    ast::Expr::Number(1.0),
-                    ast::Expr::Number(2.0),
-                    ast::Expr::Number(3.0),
-                ]),
-            ),
-            ast::Statement::VariableDeclaration(
-                "b",
-                ast::Expr::ArrayAccess {
-                    array: Box::new(ast::Expr::Variable("a")),
-                    index: Box::new(ast::Expr::Number(0.0)),
-                },
+            ast::StatementKind::VariableDeclaration(
+                "a".into(),
+                ast::ExprKind::Vector(vec![
+                    ast::ExprKind::Number(1.0).into(),
+                    ast::ExprKind::Number(2.0).into(),
+                    ast::ExprKind::Number(3.0).into(),
+                ])
+                .into(),
+            )
+            .into(),
+            ast::StatementKind::VariableDeclaration(
+                "b".into(),
+                ast::ExprKind::ArrayAccess {
+                    array: Box::new(ast::ExprKind::Variable("a".into()).into()),
+                    index: Box::new(ast::ExprKind::Number(0.0).into()),
+                }
+                .into(),
             )
+            .into()
         ]
     );
 }
@@ -243,21 +268,25 @@ fn parse_field_access() {
         )
         .unwrap(),
         vec![
-            ast::Statement::VariableDeclaration(
-                "a",
-                ast::Expr::Vector(vec![
-                    ast::Expr::Number(1.0),
-                    ast::Expr::Number(2.0),
-                    ast::Expr::Number(3.0),
-                ]),
-            ),
-            ast::Statement::VariableDeclaration(
-                "b",
-                ast::Expr::FieldAccess {
-                    parent: Box::new(ast::Expr::Variable("a")),
-                    field: "x",
-                },
+            ast::StatementKind::VariableDeclaration(
+                "a".into(),
+                ast::ExprKind::Vector(vec![
+                    ast::ExprKind::Number(1.0).into(),
+                    ast::ExprKind::Number(2.0).into(),
+                    ast::ExprKind::Number(3.0).into(),
+                ])
+                .into(),
+            )
+            .into(),
+            ast::StatementKind::VariableDeclaration(
+                "b".into(),
+                ast::ExprKind::FieldAccess {
+                    parent: Box::new(ast::ExprKind::Variable("a".into()).into()),
+                    field: "x".into(),
+                }
+                .into(),
             )
+            .into()
         ]
     );
 }
@@ -271,16 +300,24 @@ fn parse_boolean() {
             "#
         )
         .unwrap(),
-        vec![ast::Statement::VariableDeclaration(
-            "a",
-            ast::Expr::Or(
-                Box::new(ast::Expr::Boolean(true)),
-                Box::new(ast::Expr::Not(Box::new(ast::Expr::And(
-                    Box::new(ast::Expr::Boolean(false)),
-                    Box::new(ast::Expr::Boolean(true)),
-                )))),
-            ),
-        )],
+        vec![ast::StatementKind::VariableDeclaration(
+            "a".into(),
+            ast::ExprKind::Or(
+                Box::new(ast::ExprKind::Boolean(true).into()),
+                Box::new(
+                    ast::ExprKind::Not(Box::new(
+                        ast::ExprKind::And(
+                            Box::new(ast::ExprKind::Boolean(false).into()),
+                            Box::new(ast::ExprKind::Boolean(true).into()),
+                        )
+                        .into()
+                    ))
+                    .into()
+                ),
+            )
+            .into(),
+        )
+        .into()],
     );
 }
 
@@ -296,31 +333,647 @@ fn parse_translate_child() {
             "#
         )
         .unwrap(),
-        vec![ast::Statement::ModuleCall(ast::ModuleCall {
-            function: "translate",
+        vec![ast::StatementKind::ModuleCall(ast::ModuleCall {
+            function: "translate".into(),
             params: vec![ast::ParameterValue {
                 name: None,
-                value: ast::Expr::Vector(vec![
-                    ast::Expr::Number(1.0),
-                    ast::Expr::Number(2.0),
-                    ast::Expr::Number(3.0),
-                ]),
+                value: ast::ExprKind::Vector(vec![
+                    ast::ExprKind::Number(1.0).into(),
+                    ast::ExprKind::Number(2.0).into(),
+                    ast::ExprKind::Number(3.0).into(),
+                ])
+                .into(),
             }],
-            child: Box::new(ast::Statement::StatementList(vec![
-                ast::Statement::VariableDeclaration("a", ast::Expr::Number(5.0),),
-                ast::Statement::StatementList(vec![ast::Statement::ModuleCall(ast::ModuleCall {
-                    function: "cube",
-                    params: vec![ast::ParameterValue {
-                        name: None,
-                        value: ast::Expr::Vector(vec![
-                            ast::Expr::Variable("a"),
-                            ast::Expr::Variable("a"),
-                            ast::Expr::Variable("a"),
-                        ]),
-                    }],
-                    child: Box::new(ast::Statement::NoOp),
-                })]),
-            ])),
-        })],
+            child: Box::new(
+                ast::StatementKind::StatementList(vec![
+                    ast::StatementKind::VariableDeclaration(
+                        "a".into(),
+                        ast::ExprKind::Number(5.0).into(),
+                    )
+                    .into(),
+                    ast::StatementKind::StatementList(vec![ast::StatementKind::ModuleCall(
+                        ast::ModuleCall {
+                            function: "cube".into(),
+                            params: vec![ast::ParameterValue {
+                                name: None,
+                                value: ast::ExprKind::Vector(vec![
+                                    ast::ExprKind::Variable("a".into()).into(),
+                                    ast::ExprKind::Variable("a".into()).into(),
+                                    ast::ExprKind::Variable("a".into()).into(),
+                                ])
+                                .into(),
+                            }],
+                            child: Box::new(ast::StatementKind::NoOp.into()),
+                        }
+                    )
+                    .into()])
+                    .into(),
+                ])
+                .into()
+            ),
+        })
+        .into()],
+    );
+}
+
+#[test]
+fn parse_spans() {
+    let source = "a = 1 + 2;\ncube([a, 2, 3]);";
+    let statements = parse(source).unwrap();
+
+    assert_eq!(statements[0].span.text(source), "a = 1 + 2;");
+    match &statements[0].kind {
+        ast::StatementKind::VariableDeclaration(_, expr) => {
+            assert_eq!(expr.span.text(source), "1 + 2");
+            match &expr.kind {
+                ast::ExprKind::Op(a, _, b) => {
+                    assert_eq!(a.span.text(source), "1");
+                    assert_eq!(b.span.text(source), "2");
+                }
+                other => panic!("unexpected expression: {:?}", other),
+            }
+        }
+        other => panic!("unexpected statement: {:?}", other),
+    }
+
+    assert_eq!(statements[1].span.text(source), "cube([a, 2, 3]);");
+    match &statements[1].kind {
+        ast::StatementKind::ModuleCall(call) => {
+            assert_eq!(call.params[0].value.span.text(source), "[a, 2, 3]");
+        }
+        other => panic!("unexpected statement: {:?}", other),
+    }
+}
+
+#[test]
+fn line_index() {
+    let source = "a = 1;\n\nb = \"é\" + c;\n";
+    let index = span::LineIndex::new(source);
+
+    assert_eq!(index.position(0), span::Position { line: 1, column: 1 });
+    assert_eq!(index.position(4), span::Position { line: 1, column: 5 });
+    assert_eq!(index.position(7), span::Position { line: 2, column: 1 });
+    let c = source.find('c').unwrap();
+    assert_eq!(
+        index.position(c),
+        span::Position {
+            line: 3,
+            column: 11
+        }
+    );
+    assert_eq!(index.line(3), "b = \"é\" + c;");
+    assert_eq!(index.line_count(), 4);
+}
+
+fn parse_error(source: &str) -> rscad::ParseError {
+    match parse(source) {
+        Err(rscad::Error::Parse(mut errors)) => errors.remove(0),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
+
+#[test]
+fn error_unexpected_token() {
+    let error = parse_error("a = 1;\nb = 1 2;");
+    assert_eq!(
+        error.kind,
+        rscad::ParseErrorKind::UnexpectedToken("2".to_string())
+    );
+    assert_eq!(error.span, span::Span::new(13, 14));
+    assert_eq!(error.position, span::Position { line: 2, column: 7 });
+    assert!(error.expected.contains(&"`;`".to_string()));
+}
+
+#[test]
+fn error_expected_tokens() {
+    let error = parse_error("a = ;");
+    assert!(error.expected.contains(&"identifier".to_string()));
+    assert!(error.expected.contains(&"number".to_string()));
+    assert!(error.expected.contains(&"string".to_string()));
+    assert!(error.expected.contains(&"`undef`".to_string()));
+}
+
+#[test]
+fn error_kinds() {
+    assert_eq!(
+        parse_error("cube(").kind,
+        rscad::ParseErrorKind::UnexpectedEof
+    );
+    assert_eq!(
+        parse_error("a = \"abc;").kind,
+        rscad::ParseErrorKind::UnterminatedString
+    );
+    assert_eq!(
+        parse_error("/* abc").kind,
+        rscad::ParseErrorKind::UnterminatedComment
+    );
+    assert_eq!(
+        parse_error("a = 1 @ 2;").kind,
+        rscad::ParseErrorKind::InvalidCharacter('@')
+    );
+}
+
+#[test]
+fn error_display() {
+    let error = parse_error("a = 1;\nb = [1, 2;");
+    assert_eq!(
+        error.to_string(),
+        "error: unexpected token `;`\n \
+         --> 2:10\n  \
+         |\n\
+         2 | b = [1, 2;\n  \
+         |          ^ expected one of `,`, `]`",
+    );
+}
+
+#[test]
+fn recover_from_errors() {
+    let source = r#"
+        a = 1
+        b = 2;
+        module foo() {
+            cube([1,2,3);
+            sphere(1);
+        }
+        c = ;
+        d = 4;
+    "#;
+
+    let (statements, errors) = rscad::parse_partial(source);
+    let kinds: Vec<_> = errors.iter().map(|e| e.kind.clone()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            rscad::ParseErrorKind::UnexpectedToken("b".to_string()),
+            rscad::ParseErrorKind::UnexpectedToken(")".to_string()),
+            rscad::ParseErrorKind::UnexpectedToken(";".to_string()),
+        ]
+    );
+
+    let names: Vec<_> = statements
+        .iter()
+        .filter_map(|s| match &s.kind {
+            ast::StatementKind::VariableDeclaration(name, _) => Some(name.as_ref()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, vec!["b", "d"]);
+
+    match &statements
+        .iter()
+        .find(|s| matches!(s.kind, ast::StatementKind::ModuleDefinition { .. }))
+        .unwrap()
+        .kind
+    {
+        ast::StatementKind::ModuleDefinition { body, .. } => {
+            assert_eq!(
+                body.kind,
+                ast::StatementKind::StatementList(vec![
+                    ast::StatementKind::Error.into(),
+                    ast::StatementKind::NoOp.into(),
+                    ast::StatementKind::ModuleCall(ast::ModuleCall {
+                        function: "sphere".into(),
+                        params: vec![ast::ParameterValue {
+                            name: None,
+                            value: ast::ExprKind::Number(1.0).into(),
+                        }],
+                        child: Box::new(ast::StatementKind::NoOp.into()),
+                    })
+                    .into(),
+                ])
+            );
+        }
+        _ => unreachable!(),
+    }
+
+    match parse(source) {
+        Err(rscad::Error::Parse(all)) => assert_eq!(all, errors),
+        other => panic!("expected parse errors, got {:?}", other),
+    }
+}
+
+#[test]
+fn recover_stops_at_unterminated_comment() {
+    let (_, errors) = rscad::parse_partial("a = 1; /* b = 2; c d e");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, rscad::ParseErrorKind::UnterminatedComment);
+}
+
+#[test]
+fn parse_function_literal() {
+    assert_eq!(
+        parse("f = function(x, y=2) x + y;").unwrap(),
+        vec![ast::StatementKind::VariableDeclaration(
+            "f".into(),
+            ast::ExprKind::Lambda {
+                args: vec![
+                    ast::ParameterDefinition {
+                        name: "x".into(),
+                        default_value: None,
+                    },
+                    ast::ParameterDefinition {
+                        name: "y".into(),
+                        default_value: Some(ast::ExprKind::Number(2.0).into()),
+                    },
+                ],
+                body: Box::new(
+                    ast::ExprKind::Op(
+                        Box::new(ast::ExprKind::Variable("x".into()).into()),
+                        ast::Opcode::Add,
+                        Box::new(ast::ExprKind::Variable("y".into()).into()),
+                    )
+                    .into()
+                ),
+            }
+            .into(),
+        )
+        .into()],
+    );
+}
+
+#[test]
+fn parse_function_call_on_expression() {
+    let call = |function: ast::Expr<'static>, arg: f64| -> ast::Expr<'static> {
+        ast::ExprKind::Function(ast::FunctionCall {
+            function: Box::new(function),
+            parameters: vec![ast::ParameterValue {
+                name: None,
+                value: ast::ExprKind::Number(arg).into(),
+            }],
+        })
+        .into()
+    };
+    let identity = ast::ExprKind::Lambda {
+        args: vec![ast::ParameterDefinition {
+            name: "x".into(),
+            default_value: None,
+        }],
+        body: Box::new(ast::ExprKind::Variable("x".into()).into()),
+    };
+
+    assert_eq!(
+        parse("a = f(1)(2); b = (function(x) x)(3);").unwrap(),
+        vec![
+            ast::StatementKind::VariableDeclaration(
+                "a".into(),
+                call(call(ast::ExprKind::Variable("f".into()).into(), 1.0), 2.0),
+            )
+            .into(),
+            ast::StatementKind::VariableDeclaration("b".into(), call(identity.into(), 3.0)).into(),
+        ],
+    );
+}
+
+fn number(n: f64) -> ast::Expr<'static> {
+    ast::ExprKind::Number(n).into()
+}
+
+fn variable(name: &str) -> ast::Expr<'_> {
+    ast::ExprKind::Variable(name.into()).into()
+}
+
+fn op<'a>(a: ast::Expr<'a>, op: ast::Opcode, b: ast::Expr<'a>) -> ast::Expr<'a> {
+    ast::ExprKind::Op(Box::new(a), op, Box::new(b)).into()
+}
+
+fn range(start: f64, end: f64) -> ast::Expr<'static> {
+    ast::ExprKind::Range {
+        start: Box::new(number(start)),
+        end: Box::new(number(end)),
+        increment: None,
+    }
+    .into()
+}
+
+fn assign<'a>(name: &'a str, value: ast::Expr<'a>) -> ast::ParameterValue<'a> {
+    ast::ParameterValue {
+        name: Some(name.into()),
+        value,
+    }
+}
+
+/// Parses a single `a = ...;` declaration, and returns the expression.
+fn parse_value(source: &str) -> ast::Expr<'_> {
+    match parse(source).unwrap().remove(0).kind {
+        ast::StatementKind::VariableDeclaration(_, value) => value,
+        other => panic!("expected a variable declaration, got {:?}", other),
+    }
+}
+
+#[test]
+fn parse_list_comprehension_if_else() {
+    assert_eq!(
+        parse_value("a = [for (i=[0:3]) if (i%2) i else -i];"),
+        ast::ExprKind::ListComprehension(vec![ast::ListElement::For {
+            variables: vec![assign("i", range(0.0, 3.0))],
+            body: Box::new(ast::ListElement::If {
+                condition: op(variable("i"), ast::Opcode::Rem, number(2.0)),
+                if_true: Box::new(ast::ListElement::Expr(variable("i"))),
+                if_false: Some(Box::new(ast::ListElement::Expr(
+                    ast::ExprKind::Negative(Box::new(variable("i"))).into()
+                ))),
+            }),
+        }])
+        .into(),
+    );
+}
+
+#[test]
+fn parse_list_comprehension_dangling_else() {
+    // The `else` belongs to the innermost `if`.
+    assert_eq!(
+        parse_value("a = [for (i=[0:3]) if (i > 0) if (i > 1) 1 else 2];"),
+        ast::ExprKind::ListComprehension(vec![ast::ListElement::For {
+            variables: vec![assign("i", range(0.0, 3.0))],
+            body: Box::new(ast::ListElement::If {
+                condition: op(variable("i"), ast::Opcode::Gt, number(0.0)),
+                if_true: Box::new(ast::ListElement::If {
+                    condition: op(variable("i"), ast::Opcode::Gt, number(1.0)),
+                    if_true: Box::new(ast::ListElement::Expr(number(1.0))),
+                    if_false: Some(Box::new(ast::ListElement::Expr(number(2.0)))),
+                }),
+                if_false: None,
+            }),
+        }])
+        .into(),
+    );
+}
+
+#[test]
+fn parse_list_comprehension_each() {
+    assert_eq!(
+        parse_value("a = [each v, 1];"),
+        ast::ExprKind::ListComprehension(vec![
+            ast::ListElement::Each(Box::new(ast::ListElement::Expr(variable("v")))),
+            ast::ListElement::Expr(number(1.0)),
+        ])
+        .into(),
+    );
+}
+
+#[test]
+fn parse_list_comprehension_nested_for() {
+    assert_eq!(
+        parse_value("a = [for (a=[0:1]) for (b=[0:2]) [a, b]];"),
+        ast::ExprKind::ListComprehension(vec![ast::ListElement::For {
+            variables: vec![assign("a", range(0.0, 1.0))],
+            body: Box::new(ast::ListElement::For {
+                variables: vec![assign("b", range(0.0, 2.0))],
+                body: Box::new(ast::ListElement::Expr(
+                    ast::ExprKind::Vector(vec![variable("a"), variable("b")]).into()
+                )),
+            }),
+        }])
+        .into(),
+    );
+}
+
+#[test]
+fn parse_list_comprehension_c_style_for() {
+    assert_eq!(
+        parse_value("a = [for (i=0; i<10; i=i+1) i];"),
+        ast::ExprKind::ListComprehension(vec![ast::ListElement::ForC {
+            init: vec![assign("i", number(0.0))],
+            condition: op(variable("i"), ast::Opcode::Lt, number(10.0)),
+            update: vec![assign(
+                "i",
+                op(variable("i"), ast::Opcode::Add, number(1.0))
+            )],
+            body: Box::new(ast::ListElement::Expr(variable("i"))),
+        }])
+        .into(),
+    );
+}
+
+#[test]
+fn parse_list_comprehension_parenthesized() {
+    assert_eq!(
+        parse_value("a = [let (n=2) (for (i=[0:1]) each [i, n])];"),
+        ast::ExprKind::ListComprehension(vec![ast::ListElement::Let {
+            vars: vec![assign("n", number(2.0))],
+            body: Box::new(ast::ListElement::For {
+                variables: vec![assign("i", range(0.0, 1.0))],
+                body: Box::new(ast::ListElement::Each(Box::new(ast::ListElement::Expr(
+                    ast::ExprKind::Vector(vec![variable("i"), variable("n")]).into()
+                )))),
+            }),
+        }])
+        .into(),
+    );
+    // A plain `let` still is a simple expression.
+    assert_eq!(
+        parse_value("a = [let (n=2) n];"),
+        ast::ExprKind::Vector(vec![ast::ExprKind::Let(
+            vec![ast::Let {
+                vars: vec![assign("n", number(2.0))]
+            }],
+            Box::new(variable("n")),
+        )
+        .into()])
+        .into(),
+    );
+}
+
+/// Prints an expression with explicit parentheses, to check precedence.
+fn parenthesize(expr: &ast::Expr) -> String {
+    match &expr.kind {
+        ast::ExprKind::Number(n) => n.to_string(),
+        ast::ExprKind::Variable(name) => name.to_string(),
+        ast::ExprKind::Negative(e) => format!("(-{})", parenthesize(e)),
+        ast::ExprKind::Not(e) => format!("(!{})", parenthesize(e)),
+        ast::ExprKind::BitNot(e) => format!("(~{})", parenthesize(e)),
+        ast::ExprKind::Or(a, b) => format!("({} || {})", parenthesize(a), parenthesize(b)),
+        ast::ExprKind::And(a, b) => format!("({} && {})", parenthesize(a), parenthesize(b)),
+        ast::ExprKind::ArrayAccess { array, index } => {
+            format!("{}[{}]", parenthesize(array), parenthesize(index))
+        }
+        ast::ExprKind::Op(a, op, b) => {
+            let op = match op {
+                ast::Opcode::Mul => "*",
+                ast::Opcode::Div => "/",
+                ast::Opcode::Rem => "%",
+                ast::Opcode::Add => "+",
+                ast::Opcode::Sub => "-",
+                ast::Opcode::Equal => "==",
+                ast::Opcode::NotEqual => "!=",
+                ast::Opcode::Gt => ">",
+                ast::Opcode::Gte => ">=",
+                ast::Opcode::Lt => "<",
+                ast::Opcode::Lte => "<=",
+                ast::Opcode::Pow => "^",
+                ast::Opcode::ShiftLeft => "<<",
+                ast::Opcode::ShiftRight => ">>",
+                ast::Opcode::BitAnd => "&",
+                ast::Opcode::BitOr => "|",
+            };
+            format!("({} {} {})", parenthesize(a), op, parenthesize(b))
+        }
+        other => panic!("unexpected expression: {:?}", other),
+    }
+}
+
+#[test]
+fn parse_operator_precedence() {
+    let cases = [
+        ("-2^2", "(-(2 ^ 2))"),
+        ("2^3^2", "(2 ^ (3 ^ 2))"),
+        ("2^-1", "(2 ^ (-1))"),
+        ("2 * 3^2", "(2 * (3 ^ 2))"),
+        ("a[1]^2", "(a[1] ^ 2)"),
+        ("1 + 2 << 3", "((1 + 2) << 3)"),
+        ("1 << 2 >> 3", "((1 << 2) >> 3)"),
+        ("1 | 2 & 3", "(1 | (2 & 3))"),
+        ("1 & 2 << 3", "(1 & (2 << 3))"),
+        ("~a & b", "((~a) & b)"),
+        ("1 < 2 | 3", "(1 < (2 | 3))"),
+        ("a < b == c > d", "((a < b) == (c > d))"),
+        ("a || b && c", "(a || (b && c))"),
+        ("a && b == c", "(a && (b == c))"),
+        ("!a^2", "(!(a ^ 2))"),
+    ];
+
+    for &(source, expected) in cases.iter() {
+        let source = format!("x = {};", source);
+        assert_eq!(parenthesize(&parse_value(&source)), expected, "{}", source);
+    }
+}
+
+#[test]
+fn parse_number_literals() {
+    let cases = [
+        ("0", 0.0),
+        ("42", 42.0),
+        ("1.", 1.0),
+        (".5", 0.5),
+        ("1.5", 1.5),
+        ("007", 7.0),
+        ("1e3", 1e3),
+        ("1E3", 1e3),
+        ("1e+3", 1e3),
+        ("1e-3", 1e-3),
+        ("2.5E+4", 2.5e4),
+        ("1.e2", 100.0),
+        (".5e-1", 0.05),
+        ("123456.789", 123_456.789),
+        ("0.1", 0.1),
+        ("1e400", f64::INFINITY),
+        ("0x1F", 31.0),
+        ("0XfF", 255.0),
+    ];
+
+    for &(source, expected) in cases.iter() {
+        let source = format!("x = {};", source);
+        assert_eq!(parse_value(&source), number(expected), "{}", source);
+    }
+}
+
+#[test]
+fn parse_number_literals_in_context() {
+    // Signed exponents do not eat the following operator.
+    assert_eq!(
+        parse_value("x = 1e-3-2;"),
+        op(number(1e-3), ast::Opcode::Sub, number(2.0)),
+    );
+    // Numbers and identifiers can both start with digits.
+    assert_eq!(parse_value("x = 2d;"), variable("2d"));
+    assert_eq!(parse_value("x = 1e;"), variable("1e"));
+}
+
+#[test]
+fn fail_on_bad_number_literals() {
+    assert!(parse("x = 1.2.3;").is_err());
+    assert!(parse("x = 1e+;").is_err());
+    assert_eq!(
+        parse_error("x = 0x1FFFFFFFFFFFFFFFF;").kind,
+        rscad::ParseErrorKind::InvalidNumber("0x1FFFFFFFFFFFFFFFF".to_string())
+    );
+}
+
+fn text<'a>(value: &ast::Expr<'a>) -> std::borrow::Cow<'a, str> {
+    match &value.kind {
+        ast::ExprKind::Text(text) => text.clone(),
+        other => panic!("expected a text literal, got {:?}", other),
+    }
+}
+
+#[test]
+fn parse_string_escapes() {
+    let cases = [
+        (r#""abc""#, "abc"),
+        (r#""a\tb""#, "a\tb"),
+        (r#""a\nb\r""#, "a\nb\r"),
+        (r#""\\ \"quoted\"""#, "\\ \"quoted\""),
+        (r#""\x41\x7f""#, "A\x7f"),
+        (r#""☺""#, "☺"),
+        (r#""\U01F600""#, "😀"),
+        (r#""a\tb☺\x41""#, "a\tb☺A"),
+    ];
+
+    for &(source, expected) in cases.iter() {
+        let source = format!("x = {};", source);
+        assert_eq!(text(&parse_value(&source)), expected, "{}", source);
+    }
+}
+
+#[test]
+fn parse_string_without_escapes_is_borrowed() {
+    let value = parse_value(r#"x = "no escapes here";"#);
+    assert!(matches!(text(&value), std::borrow::Cow::Borrowed(_)));
+    assert!(matches!(
+        value.kind,
+        ast::ExprKind::Text(std::borrow::Cow::Borrowed("no escapes here"))
+    ));
+
+    let value = parse_value(r#"x = "tab\there";"#);
+    assert!(matches!(
+        value.kind,
+        ast::ExprKind::Text(std::borrow::Cow::Owned(_))
+    ));
+}
+
+#[test]
+fn fail_on_invalid_escapes() {
+    let cases = [
+        (r#""\q""#, r"\q"),
+        (r#""\x80""#, r"\x80"),
+        (r#""\x4""#, r"\x4"),
+        (r#""\u12G4""#, r"\u12G4"),
+        (r#""\UD800__""#, r"\UD800__"),
+        (r#""\U110000""#, r"\U110000"),
+        (r#""\x1é""#, r"\x1é"),
+        (r#""\u12é4""#, r"\u12é"),
+        (r#""\xé""#, r"\xé"),
+    ];
+
+    for &(source, escape) in cases.iter() {
+        let source = format!("x = {};", source);
+        let error = parse_error(&source);
+        assert_eq!(
+            error.kind,
+            rscad::ParseErrorKind::InvalidEscape(escape.to_string()),
+            "{}",
+            source
+        );
+        assert_eq!(error.span.start, 5, "{}", source);
+    }
+}
+
+#[test]
+fn owned_ast_outlives_source() {
+    let source = String::from(
+        r#"include <lib.scad> module m(a = "x\ty") { translate([a, 0]) cube(f(b).c); }"#,
+    );
+    let owned: Vec<ast::Statement<'static>> = parse(&source)
+        .unwrap()
+        .into_iter()
+        .map(ast::Statement::into_owned)
+        .collect();
+    drop(source);
+
+    let owned = std::thread::spawn(move || owned).join().unwrap();
+    assert_eq!(
+        owned,
+        parse(r#"include <lib.scad> module m(a = "x\ty") { translate([a, 0]) cube(f(b).c); }"#)
+            .unwrap()
     );
 }
@@ -0,0 +1,334 @@
+use rscad::ast::Modifier;
+use rscad::diagnostic::{Code, Diagnostic, Diagnostics};
+use rscad::parser::{Expr, Id, Item, ItemType, ParameterDefinition, ParameterValue, Scope};
+use rscad::span::Span;
+
+fn resolve(source: &str) -> Scope {
+    resolve_with_diagnostics(source).0
+}
+
+fn resolve_with_diagnostics(source: &str) -> (Scope, Diagnostics) {
+    rscad::resolve(rscad::parse(source).unwrap())
+}
+
+fn id(depth: usize, id: usize) -> Id {
+    Id { depth, id }
+}
+
+fn var(depth: usize, i: usize) -> Expr {
+    Expr::Variable(id(depth, i))
+}
+
+fn param(value: Expr) -> ParameterValue {
+    ParameterValue {
+        name: None,
+        value,
+        span: Span::default(),
+    }
+}
+
+fn call(name: &str, params: Vec<ParameterValue>) -> Item {
+    Item {
+        ty: ItemType::Extern(name.to_string()),
+        modifiers: vec![],
+        params,
+        child: Scope::default(),
+        span: Span::default(),
+    }
+}
+
+#[test]
+fn resolve_items() {
+    let scope = resolve("a = 2; translate([a, 0]) { b = a; cube(b); }");
+
+    assert_eq!(scope.variables, vec![Expr::Number(2.0)]);
+    assert_eq!(
+        scope.items,
+        vec![Item {
+            ty: ItemType::Extern("translate".to_string()),
+            modifiers: vec![],
+            params: vec![param(Expr::Vector(vec![var(0, 0), Expr::Number(0.0)]))],
+            child: Scope {
+                variables: vec![var(1, 0)],
+                items: vec![call("cube", vec![param(var(0, 0))])],
+                ..Scope::default()
+            },
+            span: Span::default(),
+        }]
+    );
+}
+
+#[test]
+fn definitions_are_hoisted() {
+    let scope = resolve(
+        "m(size = 3);
+        module m(size) { cube(f(size)); }
+        function f(x) = g(x) + n;
+        function g(x) = x;
+        n = 1;",
+    );
+
+    assert_eq!(scope.items[0].ty, ItemType::User(id(0, 0)));
+    assert_eq!(
+        scope.items[0].params,
+        vec![ParameterValue {
+            name: Some("size".to_string()),
+            value: Expr::Number(3.0),
+            span: Span::default(),
+        }]
+    );
+
+    let module = &scope.modules[0];
+    assert_eq!(
+        module.parameters,
+        vec![ParameterDefinition {
+            name: "size".to_string(),
+            default_value: None,
+        }]
+    );
+    assert_eq!(module.body.variables, vec![Expr::Extern]);
+    assert_eq!(
+        module.body.items[0].params,
+        vec![param(Expr::Function(id(1, 0), vec![param(var(0, 0))]))]
+    );
+
+    // `f` calls `g`, defined after it, and reads `n`, declared last.
+    assert_eq!(
+        scope.functions[0].body,
+        Expr::Op(
+            Box::new(Expr::Function(id(1, 1), vec![param(var(0, 0))])),
+            rscad::ast::Opcode::Add,
+            Box::new(var(1, 0)),
+        )
+    );
+    assert_eq!(scope.variables, vec![Expr::Number(1.0)]);
+}
+
+#[test]
+fn resolve_control_flow() {
+    let scope = resolve(
+        "x = 1;
+        if (x > 0) cube(x); else { y = 2; sphere(y); }
+        for (i = [0:3], j = [0:i]) cube(j);
+        let (a = x, b = a) cube(b);
+        intersection_for (i = [1, 2]) cube(i);",
+    );
+
+    let items = &scope.items;
+    assert_eq!(
+        items[0].ty,
+        ItemType::If {
+            condition: Expr::Op(
+                Box::new(var(0, 0)),
+                rscad::ast::Opcode::Gt,
+                Box::new(Expr::Number(0.0)),
+            ),
+            if_false: Box::new(Scope {
+                variables: vec![Expr::Number(2.0)],
+                items: vec![call("sphere", vec![param(var(0, 0))])],
+                ..Scope::default()
+            }),
+        }
+    );
+    assert_eq!(
+        items[0].child.items,
+        vec![call("cube", vec![param(var(1, 0))])]
+    );
+
+    // Loop variables come first in the child scope, and can use the previous ones.
+    match &items[1].ty {
+        ItemType::For(ranges) => assert_eq!(
+            ranges[1],
+            Expr::Range {
+                start: Box::new(Expr::Number(0.0)),
+                end: Box::new(var(0, 0)),
+                increment: None,
+            }
+        ),
+        ty => panic!("unexpected item: {:?}", ty),
+    }
+    assert_eq!(items[1].child.variables, vec![Expr::Extern, Expr::Extern]);
+    assert_eq!(
+        items[1].child.items,
+        vec![call("cube", vec![param(var(0, 1))])]
+    );
+
+    assert_eq!(items[2].ty, ItemType::Let(vec![var(1, 0), var(0, 0)]));
+    assert_eq!(
+        items[2].child.items,
+        vec![call("cube", vec![param(var(0, 1))])]
+    );
+
+    assert!(matches!(items[3].ty, ItemType::IntersectionFor(_)));
+    assert_eq!(
+        items[3].child.items,
+        vec![call("cube", vec![param(var(0, 0))])]
+    );
+}
+
+#[test]
+fn resolve_modifiers_and_files() {
+    let scope = resolve(
+        "include <a.scad>
+        use <b.scad>
+        { size = 2; }
+        !cube(size);
+        %if (true) sphere();",
+    );
+
+    assert_eq!(scope.includes, vec!["a.scad".to_string()]);
+    assert_eq!(scope.uses, vec!["b.scad".to_string()]);
+
+    // Blocks do not create a scope.
+    assert_eq!(scope.variables, vec![Expr::Number(2.0)]);
+    assert_eq!(scope.items[0].params, vec![param(var(0, 0))]);
+
+    assert_eq!(scope.items[0].modifiers, vec![Modifier::ShowOnly]);
+    assert_eq!(scope.items[1].modifiers, vec![Modifier::Transparent]);
+}
+
+#[test]
+fn reassignment_keeps_first_position() {
+    // Like OpenSCAD, `echo(b)` prints 2: `a` is only assigned once, with its last value.
+    let (scope, diagnostics) = resolve_with_diagnostics("a = 1; b = a; echo(b); a = 2;");
+    assert_eq!(scope.variables, vec![Expr::Number(2.0), var(0, 0)]);
+    assert_eq!(scope.items, vec![call("echo", vec![param(var(0, 1))])]);
+
+    let expected = Diagnostic::warning(
+        Code::Reassignment,
+        "`a` was assigned twice in the same scope, only the last value is used",
+        Span::new(23, 29),
+    )
+    .with_note("first assigned here", Some(Span::new(0, 6)));
+    assert_eq!(diagnostics.iter().collect::<Vec<_>>(), vec![&expected]);
+}
+
+#[test]
+fn reassignment_refers_to_itself() {
+    // `a + 1` is evaluated where `a` is first assigned, before it has a value: it is `undef`.
+    let scope = resolve("a = 1; a = a + 1;");
+    assert_eq!(
+        scope.variables,
+        vec![Expr::Op(
+            Box::new(var(0, 0)),
+            rscad::ast::Opcode::Add,
+            Box::new(Expr::Number(1.0)),
+        )]
+    );
+}
+
+#[test]
+fn reassignment_only_in_same_scope() {
+    let scope = resolve(
+        "a = 1;
+        translate() { a = 2; cube(a); }
+        module m(x) { x = 3; cube(x); }",
+    );
+
+    // The block is a new scope: its `a` is a different variable.
+    assert_eq!(scope.variables, vec![Expr::Number(1.0)]);
+    assert_eq!(scope.items[0].child.variables, vec![Expr::Number(2.0)]);
+    assert_eq!(
+        scope.items[0].child.items,
+        vec![call("cube", vec![param(var(0, 0))])]
+    );
+
+    // Parameters can be overridden in the module body.
+    assert_eq!(scope.modules[0].body.variables, vec![Expr::Number(3.0)]);
+    assert_eq!(
+        scope.modules[0].body.items,
+        vec![call("cube", vec![param(var(0, 0))])]
+    );
+}
+
+#[test]
+fn overriding_parameters_is_not_a_reassignment() {
+    let source = "module m(x) { x = 3; x = 4; } for (i = [0:2]) { i = 1; }";
+    let (scope, diagnostics) = resolve_with_diagnostics(source);
+    assert_eq!(scope.modules[0].body.variables, vec![Expr::Number(4.0)]);
+    assert_eq!(scope.items[0].child.variables, vec![Expr::Number(1.0)]);
+
+    // Only assigning the parameter twice in the body is reported.
+    let expected = Diagnostic::warning(
+        Code::Reassignment,
+        "`x` was assigned twice in the same scope, only the last value is used",
+        Span::new(21, 27),
+    )
+    .with_note("first assigned here", Some(Span::new(14, 20)));
+    assert_eq!(diagnostics.iter().collect::<Vec<_>>(), vec![&expected]);
+}
+
+#[test]
+fn special_variables_are_dynamic() {
+    let scope = resolve(
+        "$fn = 8;
+        module m() sphere(r = $fs);
+        m($fs = 2);
+        let ($fa = 5) cylinder($fn = $fn * 2);
+        $fn = 16;",
+    );
+
+    // `$fn` is assigned once, and never resolved lexically.
+    assert_eq!(scope.variables, vec![Expr::Number(16.0)]);
+    assert_eq!(scope.specials, vec![("$fn".to_string(), 0)]);
+    assert_eq!(
+        scope.modules[0].body.items[0].params,
+        vec![ParameterValue {
+            name: Some("r".to_string()),
+            value: Expr::SpecialVariable("$fs".to_string()),
+            span: Span::default(),
+        }]
+    );
+    assert_eq!(
+        scope.items[0].params,
+        vec![ParameterValue {
+            name: Some("$fs".to_string()),
+            value: Expr::Number(2.0),
+            span: Span::default(),
+        }]
+    );
+
+    let let_item = &scope.items[1];
+    assert_eq!(let_item.child.specials, vec![("$fa".to_string(), 0)]);
+    assert_eq!(
+        let_item.child.items[0].params,
+        vec![ParameterValue {
+            name: Some("$fn".to_string()),
+            value: Expr::Op(
+                Box::new(Expr::SpecialVariable("$fn".to_string())),
+                rscad::ast::Opcode::Mul,
+                Box::new(Expr::Number(2.0)),
+            ),
+            span: Span::default(),
+        }]
+    );
+}
+
+#[test]
+fn unknown_names_are_reported() {
+    let source = "x = y;\nz = f(1) + [1, 2].w;";
+    let (scope, diagnostics) = resolve_with_diagnostics(source);
+
+    assert_eq!(scope.variables[0], Expr::Undef);
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.code, d.span.text(source)))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (Code::UnknownVariable, "y"),
+            (Code::UnknownFunction, "f"),
+            (Code::UnknownField, "[1, 2].w"),
+        ]
+    );
+    assert!(!diagnostics.has_errors());
+
+    let source = "v = [for (i = 0; i < 3; j = i + 1) i];";
+    let (_, diagnostics) = resolve_with_diagnostics(source);
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.code, d.span.text(source)))
+        .collect();
+    assert_eq!(found, vec![(Code::InvalidLoopUpdate, "i + 1")]);
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "serde")]
+
+use rscad::ast;
+use serde_json::json;
+
+fn span(start: usize, end: usize) -> serde_json::Value {
+    json!({ "start": start, "end": end })
+}
+
+#[test]
+fn json_schema() {
+    let statements = rscad::parse("a = -1; translate([a, 2]) cube(size = 3);").unwrap();
+    let number = |n: f64, start, end| json!({ "kind": { "number": n }, "span": span(start, end) });
+
+    assert_eq!(
+        serde_json::to_value(&statements).unwrap(),
+        json!([
+            {
+                "kind": {
+                    "variable_declaration": [
+                        "a",
+                        { "kind": { "negative": number(1.0, 5, 6) }, "span": span(4, 6) },
+                    ]
+                },
+                "span": span(0, 7),
+            },
+            {
+                "kind": {
+                    "module_call": {
+                        "function": "translate",
+                        "params": [{
+                            "name": null,
+                            "value": {
+                                "kind": {
+                                    "vector": [
+                                        { "kind": { "variable": "a" }, "span": span(19, 20) },
+                                        number(2.0, 22, 23),
+                                    ]
+                                },
+                                "span": span(18, 24),
+                            },
+                        }],
+                        "child": {
+                            "kind": {
+                                "module_call": {
+                                    "function": "cube",
+                                    "params": [{ "name": "size", "value": number(3.0, 38, 39) }],
+                                    "child": { "kind": "no_op", "span": span(40, 41) },
+                                }
+                            },
+                            "span": span(26, 41),
+                        },
+                    }
+                },
+                "span": span(8, 41),
+            },
+        ])
+    );
+}
+
+#[test]
+fn json_enum_names() {
+    let statements = rscad::parse("x = [for (i = [0:2:10]) if (i > 1) i] + 1; !cube();").unwrap();
+    let json = serde_json::to_string(&statements).unwrap();
+
+    for name in &[
+        r#""modifier":["show_only","#,
+        r#""list_comprehension":"#,
+        r#""for":{"variables":"#,
+        r#""range":{"start":"#,
+        r#""if":{"condition":"#,
+        r#""if_false":null"#,
+        r#""gt""#,
+        r#""add""#,
+    ] {
+        assert!(json.contains(name), "{} not in {}", name, json);
+    }
+}
+
+#[test]
+fn json_roundtrip() {
+    let source = r#"
+        use <lib.scad>
+        module m(a = "x\ty", b) { translate([a.x, 0]) #cube(f(b)[1]); }
+        function f(x) = let (y = x ^ 2) x < 0 ? undef : [each y, for (i = 0; i < 3; i = i + 1) i];
+        if (true) m(); else { echo("no"); }
+    "#;
+    let statements = rscad::parse(source).unwrap();
+
+    let json = serde_json::to_string(&statements).unwrap();
+    let parsed: Vec<ast::Statement<'static>> = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, statements);
+
+    // Spans are not part of the equality, so check them separately.
+    let spans =
+        |statements: &[ast::Statement]| -> Vec<_> { statements.iter().map(|s| s.span).collect() };
+    assert_eq!(spans(&parsed), spans(&statements));
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+}
+
+#[test]
+fn json_roundtrip_resolved() {
+    let statements = rscad::parse("module m(a = 1) { for (i = [0:a]) cube(i); } m(2);").unwrap();
+    let (scope, _) = rscad::resolve(statements);
+
+    let json = serde_json::to_string(&scope).unwrap();
+    let parsed: rscad::parser::Scope = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, scope);
+}
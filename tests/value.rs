@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use rscad::interpreter::{Closure, Context, RangeIter, Value};
+use rscad::parser::Expr;
+
+fn number(x: f64) -> Value {
+    Value::Number(x)
+}
+
+#[test]
+fn echo_numbers() {
+    let cases = [
+        (1.0, "1"),
+        (-1.5, "-1.5"),
+        (1.0 / 3.0, "0.333333"),
+        (2.0 / 3.0, "0.666667"),
+        (123456.0, "123456"),
+        (1234567.0, "1.23457e+06"),
+        (999999.5, "1e+06"),
+        (1e6, "1e+06"),
+        (1e100, "1e+100"),
+        (0.0001, "0.0001"),
+        (0.00001, "1e-05"),
+        (-0.000012345678, "-1.23457e-05"),
+        (0.0, "0"),
+        (-0.0, "0"),
+        (f64::INFINITY, "inf"),
+        (f64::NEG_INFINITY, "-inf"),
+        (f64::NAN, "nan"),
+    ];
+    for &(x, expected) in &cases {
+        assert_eq!(number(x).to_string(), expected, "{:e}", x);
+    }
+}
+
+#[test]
+fn echo_values() {
+    assert_eq!(Value::Undef.to_string(), "undef");
+    assert_eq!(Value::Bool(true).to_string(), "true");
+    assert_eq!(Value::Text("a b".to_string()).to_string(), "\"a b\"");
+    assert_eq!(
+        Value::Text("a\"b\\c\nd\te\rf".to_string()).to_string(),
+        r#""a\"b\\c\nd\te\rf""#
+    );
+    assert_eq!(Value::Vector(vec![]).to_string(), "[]");
+    assert_eq!(
+        Value::Vector(vec![
+            number(1.0),
+            Value::Vector(vec![Value::Text("x".to_string()), Value::Undef]),
+            Value::Bool(false),
+        ])
+        .to_string(),
+        "[1, [\"x\", undef], false]"
+    );
+    let range = Value::Range {
+        start: 0.0,
+        step: 0.5,
+        end: 10.0,
+    };
+    assert_eq!(range.to_string(), "[0 : 0.5 : 10]");
+
+    let (scope, _) = rscad::resolve(rscad::parse("f = function(x, y = 2) x * y;").unwrap());
+    let (function, text) = match &scope.variables[0] {
+        Expr::Lambda { function, text } => (Arc::clone(function), text.clone()),
+        other => panic!("expected a function literal, got {:?}", other),
+    };
+    let closure = Arc::new(Closure {
+        function,
+        text,
+        context: Arc::new(Context::root()),
+    });
+    let value = Value::Function(Arc::clone(&closure));
+    assert_eq!(value.to_string(), "function(x, y = 2) (x * y)");
+    assert_eq!(value, Value::Function(closure));
+}
+
+#[test]
+fn truthiness() {
+    let falsy = [
+        Value::Undef,
+        Value::Bool(false),
+        number(0.0),
+        number(-0.0),
+        Value::Text(String::new()),
+        Value::Vector(vec![]),
+    ];
+    for value in &falsy {
+        assert!(!value.as_bool(), "{} should be false", value);
+    }
+
+    let truthy = [
+        Value::Bool(true),
+        number(f64::MIN_POSITIVE / 2.0),
+        number(f64::NAN),
+        number(f64::INFINITY),
+        Value::Text("0".to_string()),
+        Value::Vector(vec![Value::Undef]),
+        Value::Range {
+            start: 1.0,
+            step: 1.0,
+            end: 0.0,
+        },
+    ];
+    for value in &truthy {
+        assert!(value.as_bool(), "{} should be true", value);
+    }
+}
+
+#[test]
+fn equality() {
+    assert_eq!(
+        Value::Vector(vec![number(1.0), Value::Undef]),
+        Value::Vector(vec![number(1.0), Value::Undef])
+    );
+    assert_ne!(number(1.0), Value::Bool(true));
+    assert_ne!(number(0.0), Value::Undef);
+    assert_ne!(number(f64::NAN), number(f64::NAN));
+    assert_ne!(
+        Value::Vector(vec![number(1.0)]),
+        Value::Vector(vec![number(1.0), number(2.0)])
+    );
+}
+
+#[test]
+fn range_values() {
+    let values = |start, step, end| RangeIter::new(start, step, end).collect::<Vec<_>>();
+
+    assert_eq!(values(0.0, 1.0, 3.0), vec![0.0, 1.0, 2.0, 3.0]);
+    assert_eq!(values(0.0, 2.0, 3.0), vec![0.0, 2.0]);
+    assert_eq!(values(3.0, -1.5, 0.0), vec![3.0, 1.5, 0.0]);
+    assert_eq!(values(1.0, 1.0, 1.0), vec![1.0]);
+    assert_eq!(values(1.0, f64::INFINITY, 5.0), vec![1.0]);
+
+    // Going the wrong way, or with `nan`, gives nothing.
+    assert!(values(3.0, 1.0, 0.0).is_empty());
+    assert!(values(0.0, -1.0, 3.0).is_empty());
+    assert!(values(0.0, f64::NAN, 3.0).is_empty());
+
+    // The number of steps is rounded up by one ulp, before truncating it.
+    assert_eq!(values(0.0, 0.1, 0.3), vec![0.0, 0.1, 0.2, 0.1 * 3.0]);
+    assert_eq!(values(0.3, -0.1, 0.0).len(), 4);
+
+    // Values are computed from the start, without accumulating errors.
+    let last = RangeIter::new(0.0, 0.1, 100.0).last().unwrap();
+    assert_eq!(last, 0.1 * 1000.0);
+
+    assert_eq!(RangeIter::new(0.0, 0.0, 1.0).len(), u32::MAX as usize);
+    assert_eq!(
+        RangeIter::new(0.0, 1.0, f64::INFINITY).len(),
+        u32::MAX as usize
+    );
+}
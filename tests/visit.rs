@@ -0,0 +1,131 @@
+use rscad::ast::{Expr, ExprKind, ModuleCall, Opcode, Statement, StatementKind};
+use rscad::parse;
+use rscad::visit::{self, Visitor, VisitorMut};
+
+/// Collects the names of all modules called.
+#[derive(Default)]
+struct ModuleCalls<'ast>(Vec<&'ast str>);
+
+impl<'ast, 'input> Visitor<'ast, 'input> for ModuleCalls<'ast> {
+    fn visit_module_call(&mut self, call: &'ast ModuleCall<'input>) {
+        self.0.push(&call.function);
+        visit::walk_module_call(self, call);
+    }
+}
+
+/// Collects the names of all variables read.
+#[derive(Default)]
+struct Variables<'ast>(Vec<&'ast str>);
+
+impl<'ast, 'input> Visitor<'ast, 'input> for Variables<'ast> {
+    fn visit_expr(&mut self, expr: &'ast Expr<'input>) {
+        if let ExprKind::Variable(name) = &expr.kind {
+            self.0.push(name);
+        }
+        visit::walk_expr(self, expr);
+    }
+}
+
+#[test]
+fn visit_module_calls() {
+    let statements = parse(
+        "module m() { if (a) cube(); else { for (i = [0:2]) translate([i, 0, 0]) sphere(); } }
+        let (b = 1) %cylinder();
+        m();",
+    )
+    .unwrap();
+
+    let mut calls = ModuleCalls::default();
+    calls.visit_statements(&statements);
+    assert_eq!(
+        calls.0,
+        vec!["cube", "translate", "sphere", "let", "cylinder", "m"]
+    );
+}
+
+#[test]
+fn visit_variables() {
+    let statements = parse(
+        "function f(x = a) = let (y = b) [for (i = [c : d]) if (i > e) each g(i) else h ? i : j];
+        for (k = l) if (m) cube(n.x[o]);
+        v = [for (p = 0; p < q; p = p + r) echo(s) assert(t) function(u) u];",
+    )
+    .unwrap();
+
+    let mut variables = Variables::default();
+    variables.visit_statements(&statements);
+    assert_eq!(
+        variables.0,
+        vec![
+            "a", "b", "c", "d", "i", "e", "g", "i", "h", "i", "j", "l", "m", "n", "o", "p", "q",
+            "p", "r", "s", "t", "u",
+        ]
+    );
+}
+
+/// Renames a variable everywhere.
+struct Rename(&'static str, &'static str);
+
+impl<'input> VisitorMut<'input> for Rename {
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'input>) {
+        if let ExprKind::Variable(name) = &mut expr.kind {
+            if *name == self.0 {
+                *name = self.1.into();
+            }
+        }
+        visit::walk_expr_mut(self, expr);
+    }
+}
+
+/// Replaces additions of two numbers with their result.
+struct FoldAdditions;
+
+impl<'input> VisitorMut<'input> for FoldAdditions {
+    fn visit_expr_mut(&mut self, expr: &mut Expr<'input>) {
+        // Fold the operands first.
+        visit::walk_expr_mut(self, expr);
+
+        if let ExprKind::Op(a, Opcode::Add, b) = &expr.kind {
+            if let (ExprKind::Number(a), ExprKind::Number(b)) = (&a.kind, &b.kind) {
+                expr.kind = ExprKind::Number(a + b);
+            }
+        }
+    }
+}
+
+/// Removes empty `{ }` blocks.
+struct RemoveEmptyBlocks;
+
+impl<'input> VisitorMut<'input> for RemoveEmptyBlocks {
+    fn visit_statements_mut(&mut self, statements: &mut Vec<Statement<'input>>) {
+        statements.retain(|s| !matches!(&s.kind, StatementKind::StatementList(l) if l.is_empty()));
+        for statement in statements {
+            self.visit_statement_mut(statement);
+        }
+    }
+}
+
+#[test]
+fn visit_mut_rewrites() {
+    let mut statements =
+        parse("x = a + [for (i = [0:a]) a * i]; module m(s = a) { cube(a); }").unwrap();
+    Rename("a", "b").visit_statements_mut(&mut statements);
+    assert_eq!(
+        statements,
+        parse("x = b + [for (i = [0:b]) b * i]; module m(s = b) { cube(b); }").unwrap()
+    );
+
+    let mut statements = parse("x = 1 + 2 + 3; cube([1 + 1, a + 1, (2 + 2) * 3]);").unwrap();
+    FoldAdditions.visit_statements_mut(&mut statements);
+    assert_eq!(
+        statements,
+        parse("x = 6; cube([2, a + 1, 4 * 3]);").unwrap()
+    );
+
+    let mut statements = parse("{ } module m() { { } cube(); { } } if (a) { { } }").unwrap();
+    RemoveEmptyBlocks.visit_statements_mut(&mut statements);
+    assert_eq!(
+        statements,
+        parse("module m() { cube(); } if (a) { }").unwrap()
+    );
+}